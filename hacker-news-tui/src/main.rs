@@ -1,27 +1,57 @@
 use chrono::DateTime;
 use color_eyre::Result;
 use hacker_news::api::derived::Listing;
-use hacker_news::client::Client;
+use hacker_news::config::{Flags, Settings};
+use hacker_news::render;
 use ratatui::{
     crossterm::event::{self, Event, KeyCode, KeyEventKind},
     layout::{Constraint, Layout},
-    text::Line,
-    widgets::{Block, BorderType, Borders, Cell, Padding, Row, Table},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, BorderType, Borders, Cell, Padding, Paragraph, Row, Table, TableState},
     DefaultTerminal, Frame,
 };
+use std::path::PathBuf;
 
 struct App {
     listings: Vec<Listing>,
+    state: TableState,
+}
+
+/// The settings of the `hn` config file, overridden by `--source <spec>`,
+/// `--profile <name>` and `--config <path>` arguments in the same format as
+/// the `hn` cli.
+fn settings() -> Result<Settings> {
+    let mut flags = Flags::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => flags.settings.source = args.next(),
+            "--config" => flags.config = args.next().map(PathBuf::from),
+            "--profile" => flags.profile = args.next(),
+            _ => {}
+        }
+    }
+
+    Settings::load(&flags).map_err(|err| color_eyre::eyre::eyre!("{}", err))
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let hn_client = Client::new();
-    let top = hn_client.top_stories().await.unwrap();
-    let listings: Vec<Listing> = hn_client
-        .items(&top[..30])
+    let settings = settings()?;
+    let source = settings
+        .source_spec()
+        .build_with_client(settings.client())
+        .map_err(|err| color_eyre::eyre::eyre!("{}", err))?;
+    let top = source
+        .list_ids(settings.list)
+        .await
+        .map_err(|err| color_eyre::eyre::eyre!("{}", err))?
+        .unwrap_or_default();
+    let listings: Vec<Listing> = source
+        .get_items(&top[..top.len().min(settings.count)])
         .await
-        .unwrap()
+        .map_err(|err| color_eyre::eyre::eyre!("{}", err))?
         .into_iter()
         .filter_map(|item| Listing::try_from(item).ok())
         .collect();
@@ -29,24 +59,72 @@ async fn main() -> Result<()> {
     color_eyre::install()?;
     let terminal = ratatui::init();
     // let result = run(terminal);
-    let app = App { listings };
+    let app = App {
+        listings,
+        state: TableState::default().with_selected(Some(0)),
+    };
     let result = run(terminal, app);
     ratatui::restore();
     result
 }
 
-fn run(mut terminal: DefaultTerminal, app: App) -> Result<()> {
+fn run(mut terminal: DefaultTerminal, mut app: App) -> Result<()> {
     loop {
-        terminal.draw(|frame| draw(frame, &app))?;
+        terminal.draw(|frame| draw(frame, &mut app))?;
         if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
-                break Ok(());
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') => break Ok(()),
+                KeyCode::Char('j') | KeyCode::Down => app.state.select_next(),
+                KeyCode::Char('k') | KeyCode::Up => app.state.select_previous(),
+                _ => {}
             }
         }
     }
 }
 
-fn listing_to_rows(idx: usize, listing: &Listing, index_width: usize) -> Vec<Row<'static>> {
+/// Style the spans of [render::layout] like `hn` styles a terminal.
+fn styled_line(spans: Vec<render::Span>) -> Line<'static> {
+    let spans: Vec<Span> = spans
+        .into_iter()
+        .map(|span| {
+            let mut style = Style::default();
+            if span.style.italic {
+                style = style.add_modifier(Modifier::ITALIC);
+            }
+            if span.style.code {
+                style = style.add_modifier(Modifier::DIM);
+            }
+            if span.style.quote {
+                style = style.fg(Color::Green);
+            }
+            if span.style.link.is_some() {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            Span::styled(span.text, style)
+        })
+        .collect();
+
+    Line::from(spans)
+}
+
+/// The text of a listing, or its URL for link stories, wrapped to `width`.
+fn preview(listing: &Listing, width: usize) -> Text<'static> {
+    match (&listing.text, &listing.url) {
+        (Some(text), _) => Text::from(
+            render::layout(text, width, false)
+                .into_iter()
+                .map(styled_line)
+                .collect::<Vec<_>>(),
+        ),
+        (None, Some(url)) => Text::from(url.clone()),
+        (None, None) => Text::default(),
+    }
+}
+
+fn listing_to_row(idx: usize, listing: &Listing, index_width: usize) -> Row<'static> {
     let number = format!("{:>width$}.", idx + 1, width = index_width);
 
     let title = listing.title.clone();
@@ -69,13 +147,14 @@ fn listing_to_rows(idx: usize, listing: &Listing, index_width: usize) -> Vec<Row
     };
     let meta = Line::from(meta_text);
 
-    vec![
-        Row::new(vec![Cell::from(number), Cell::from(title)]),
-        Row::new(vec![Cell::from(""), Cell::from(meta)]),
-    ]
+    Row::new(vec![
+        Cell::from(number),
+        Cell::from(Text::from(vec![title, meta])),
+    ])
+    .height(2)
 }
 
-fn draw(frame: &mut Frame, app: &App) {
+fn draw(frame: &mut Frame, app: &mut App) {
     let area = frame.area();
 
     let vertical = Layout::vertical([
@@ -94,7 +173,9 @@ fn draw(frame: &mut Frame, app: &App) {
     ])
     .split(middle);
 
-    let box_area = horizontal[1];
+    let [box_area, preview_area] =
+        Layout::vertical([Constraint::Percentage(70), Constraint::Percentage(30)])
+            .areas(horizontal[1]);
 
     // width of the largest index ("30" -> 2 digits, etc.)
     let index_width = app.listings.len().to_string().len();
@@ -103,8 +184,7 @@ fn draw(frame: &mut Frame, app: &App) {
         .listings
         .iter()
         .enumerate()
-        .map(|(idx, listing)| listing_to_rows(idx, listing, index_width))
-        .flatten()
+        .map(|(idx, listing)| listing_to_row(idx, listing, index_width))
         .collect();
 
     let block = Block::default()
@@ -122,7 +202,20 @@ fn draw(frame: &mut Frame, app: &App) {
         ],
     )
     .block(block)
-    .column_spacing(1);
+    .column_spacing(1)
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    frame.render_stateful_widget(table, box_area, &mut app.state);
 
-    frame.render_widget(table, box_area);
+    let selected = app.state.selected().and_then(|i| app.listings.get(i));
+    // inside the borders and padding of the block
+    let width = preview_area.width.saturating_sub(4) as usize;
+    let text = selected
+        .map(|listing| preview(listing, width))
+        .unwrap_or_default();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .padding(Padding::horizontal(1));
+    frame.render_widget(Paragraph::new(text).block(block), preview_area);
 }
@@ -0,0 +1,564 @@
+//! Alerts on stories and comments matching user defined rules.
+//!
+//! A [Rule] matches items by title or text regex, author, type and score, with
+//! all of its set conditions having to match, i.e. "a story whose title
+//! matches `rust|tokio` with at least 100 points", "a story or comment by
+//! `dang`" or "a comment whose text mentions `our-product`".
+//!
+//! An [Alerter] checks the items of the new item firehose and of score updates
+//! against the rules, and sends an [Alert] per match to its [Sink]s: stdout, a
+//! file, a shell command or an HTTP webhook. Sent alerts are remembered on
+//! disk, so an item triggers a rule at most once, even across restarts.
+//!
+//! Rules and sinks are read from the `alerts` section of the config file.
+
+use crate::api::{Id, Item, Score};
+use crate::client::Client;
+use crate::error::HnError;
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::Debug;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+
+/// Number of sent alerts remembered for de-duplication.
+const SENT_CAPACITY: usize = 10_000;
+
+/// Largest number of new items fetched per poll of the firehose.
+const MAX_NEW_ITEMS: u64 = 1000;
+
+/// Number of items fetched at the same time by [Alerter::poll].
+const FETCH_CONCURRENCY: usize = 10;
+
+/// The `alerts` section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertsConfig {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Where alerts are sent. Alerts are printed to stdout when empty.
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// Conditions an item must all meet to trigger an alert. Unset conditions
+/// match every item.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Name of the rule, included in its alerts.
+    pub name: String,
+    /// Regex the title of a story, job or poll must match.
+    pub title: Option<String>,
+    /// Regex the text of an item must match, i.e. a comment mentioning a name.
+    pub text: Option<String>,
+    /// Username of the author.
+    pub author: Option<String>,
+    /// Type of the item, i.e. "story" or "comment".
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Minimum score of the item.
+    pub min_score: Option<Score>,
+}
+
+/// A [Rule] with its regexes compiled.
+#[derive(Debug)]
+struct CompiledRule {
+    rule: Rule,
+    title: Option<Regex>,
+    text: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn new(rule: Rule) -> Result<Self, HnError> {
+        let compile = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|_| HnError::ArgumentError(Some("alert rule has an invalid regex")))
+        };
+        Ok(Self {
+            title: compile(&rule.title)?,
+            text: compile(&rule.text)?,
+            rule,
+        })
+    }
+
+    fn matches(&self, item: &Item) -> bool {
+        let rule = &self.rule;
+        if item.deleted() || item.dead() {
+            return false;
+        }
+        if let Some(ref title) = self.title {
+            if !item.title().map(|t| title.is_match(t)).unwrap_or(false) {
+                return false;
+            }
+        }
+        if let Some(ref text) = self.text {
+            if !item.text().map(|t| text.is_match(t)).unwrap_or(false) {
+                return false;
+            }
+        }
+        if let Some(ref author) = rule.author {
+            if item.by() != Some(author.as_str()) {
+                return false;
+            }
+        }
+        if let Some(ref kind) = rule.kind {
+            if item.kind() != kind {
+                return false;
+            }
+        }
+        if let Some(min_score) = rule.min_score {
+            if item.score().unwrap_or(0) < min_score {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// An item which matched a [Rule].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// Name of the matched rule.
+    pub rule: String,
+    pub id: Id,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub by: Option<String>,
+    pub title: Option<String>,
+    pub text: Option<String>,
+    pub score: Option<Score>,
+    /// Creation date of the item, in Unix Time.
+    pub time: u64,
+    /// Link to the item on Hacker News.
+    pub link: String,
+}
+
+impl Alert {
+    fn new(rule: &str, item: &Item) -> Self {
+        Self {
+            rule: rule.to_string(),
+            id: item.id(),
+            kind: item.kind().to_string(),
+            by: item.by().map(str::to_string),
+            title: item.title().map(str::to_string),
+            text: item.text().map(str::to_string),
+            score: item.score(),
+            time: item.time(),
+            link: format!("https://news.ycombinator.com/item?id={}", item.id()),
+        }
+    }
+}
+
+/// Where alerts are delivered.
+#[async_trait(?Send)]
+pub trait Sink: Debug {
+    async fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>>;
+}
+
+/// Configuration of a [Sink] in the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Print a line per alert.
+    Stdout,
+    /// Append alerts to a file, as a JSON object per line.
+    File { path: PathBuf },
+    /// Run a shell command per alert, with the alert as JSON on stdin.
+    Command { command: String },
+    /// POST the alert as JSON to a URL.
+    Webhook { url: String },
+}
+
+impl SinkConfig {
+    pub fn build(&self) -> Box<dyn Sink> {
+        match self {
+            SinkConfig::Stdout => Box::new(StdoutSink),
+            SinkConfig::File { path } => Box::new(FileSink { path: path.clone() }),
+            SinkConfig::Command { command } => Box::new(CommandSink {
+                command: command.clone(),
+            }),
+            SinkConfig::Webhook { url } => Box::new(WebhookSink {
+                url: url.clone(),
+                http_client: reqwest::Client::new(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StdoutSink;
+
+#[async_trait(?Send)]
+impl Sink for StdoutSink {
+    async fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        println!(
+            "[{}] {}|{}|{}|{}",
+            alert.rule,
+            alert.id,
+            alert.kind,
+            alert.by.as_deref().unwrap_or(""),
+            alert.title.as_deref().unwrap_or(&alert.link),
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FileSink {
+    pub path: PathBuf,
+}
+
+#[async_trait(?Send)]
+impl Sink for FileSink {
+    async fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(alert)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandSink {
+    pub command: String,
+}
+
+#[async_trait(?Send)]
+impl Sink for CommandSink {
+    async fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("HN_ALERT_RULE", &alert.rule)
+            .env("HN_ALERT_ID", alert.id.to_string())
+            .env("HN_ALERT_LINK", &alert.link)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(serde_json::to_string(alert)?.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(format!("alert command exited with {}", status).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct WebhookSink {
+    pub url: String,
+    http_client: reqwest::Client,
+}
+
+#[async_trait(?Send)]
+impl Sink for WebhookSink {
+    async fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let resp = self.http_client.post(&self.url).json(alert).send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let err = crate::error::HttpError::new(status.as_u16(), self.url.clone());
+            return Err(Box::new(HnError::HttpError(err)));
+        }
+        Ok(())
+    }
+}
+
+/// Location of the log of sent alerts, in the data directory `data_dir`.
+pub fn sent_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("alerts-sent.json")
+}
+
+/// Checks items against rules and sends alerts.
+#[derive(Debug)]
+pub struct Alerter {
+    rules: Vec<CompiledRule>,
+    sinks: Vec<Box<dyn Sink>>,
+    /// Keys of sent alerts, oldest first.
+    sent: VecDeque<String>,
+    sent_keys: HashSet<String>,
+    sent_path: PathBuf,
+    /// Largest item id seen on the firehose.
+    last_max: Option<Id>,
+}
+
+impl Alerter {
+    /// Build an alerter for `config`, remembering sent alerts in `sent_path`.
+    pub fn new(
+        config: &AlertsConfig,
+        sent_path: impl Into<PathBuf>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let rules = config
+            .rules
+            .iter()
+            .cloned()
+            .map(CompiledRule::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut sinks: Vec<Box<dyn Sink>> = config.sinks.iter().map(SinkConfig::build).collect();
+        if sinks.is_empty() {
+            sinks.push(Box::new(StdoutSink));
+        }
+        let sent_path = sent_path.into();
+        let sent: VecDeque<String> = Self::load_sent(&sent_path)?;
+
+        Ok(Self {
+            rules,
+            sinks,
+            sent_keys: sent.iter().cloned().collect(),
+            sent,
+            sent_path,
+            last_max: None,
+        })
+    }
+
+    /// Use only `sinks`, instead of the configured ones.
+    pub fn with_sinks(mut self, sinks: Vec<Box<dyn Sink>>) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    fn load_sent(path: &Path) -> Result<VecDeque<String>, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(VecDeque::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save_sent(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.sent_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.sent_path, serde_json::to_string(&self.sent)?)?;
+        Ok(())
+    }
+
+    /// Check `items` against the rules, and send an alert for each new match.
+    /// Returns the alerts sent. A sink failing to send is logged, and does not
+    /// stop the other sinks. An alert no sink could send is not remembered,
+    /// so it is sent again when its item is checked again.
+    pub async fn check(&mut self, items: &[Item]) -> Result<Vec<Alert>, Box<dyn Error>> {
+        let mut matches = vec![];
+        for item in items {
+            for rule in self.rules.iter().filter(|rule| rule.matches(item)) {
+                let key = format!("{}:{}", rule.rule.name, item.id());
+                if !self.sent_keys.contains(&key) && !matches.iter().any(|(k, _)| *k == key) {
+                    matches.push((key, Alert::new(&rule.rule.name, item)));
+                }
+            }
+        }
+
+        let mut alerts = vec![];
+        for (key, alert) in matches {
+            let mut sent = false;
+            for sink in self.sinks.iter() {
+                match sink.send(&alert).await {
+                    Ok(()) => sent = true,
+                    Err(err) => {
+                        warn!(err = %err, sink = ?sink, id = alert.id, "failed to send alert")
+                    }
+                }
+            }
+            if sent {
+                self.sent_keys.insert(key.clone());
+                self.sent.push_back(key);
+                alerts.push(alert);
+            }
+        }
+        while self.sent.len() > SENT_CAPACITY {
+            if let Some(key) = self.sent.pop_front() {
+                self.sent_keys.remove(&key);
+            }
+        }
+        if !alerts.is_empty() {
+            self.save_sent()?;
+        }
+
+        Ok(alerts)
+    }
+
+    /// Check the items created since the last poll, and the items whose score
+    /// or comments changed according to `updates.json`. The first poll only
+    /// checks updates, and marks where the firehose starts. Items which fail
+    /// to load are skipped, and when the poll fails its new items are checked
+    /// again by the next one.
+    pub async fn poll(&mut self, client: &Client) -> Result<Vec<Alert>, Box<dyn Error>> {
+        let max = client.max_item().await?;
+        let mut ids: Vec<Id> = vec![];
+        if let Some(last) = self.last_max {
+            ids.extend((last + 1).max(max.saturating_sub(MAX_NEW_ITEMS))..=max);
+        }
+
+        let (updated, _profiles) = client.updates().await?;
+        let mut seen: HashSet<Id> = ids.iter().copied().collect();
+        ids.extend(updated.into_iter().filter(|id| seen.insert(*id)));
+        debug!(count = ids.len(), "checking items for alerts");
+
+        let items: Vec<Item> = stream::iter(ids)
+            .map(|id| async move { (id, client.item(id).await) })
+            .buffered(FETCH_CONCURRENCY)
+            .filter_map(|(id, item)| async move {
+                item.map_err(|err| warn!(err = %err, id, "skipping item failing to load"))
+                    .ok()
+            })
+            .collect()
+            .await;
+        let alerts = self.check(&items).await?;
+        self.last_max = Some(max);
+
+        Ok(alerts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Alert, Alerter, AlertsConfig, Rule, Sink, SinkConfig};
+    use crate::api::Item;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::error::Error;
+    use std::sync::{Arc, Mutex};
+    use wiremock::matchers::{body_partial_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[derive(Debug, Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<Alert>>>);
+
+    #[async_trait(?Send)]
+    impl Sink for Collect {
+        async fn send(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+            self.0.lock().unwrap().push(alert.clone());
+            Ok(())
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        serde_json::from_value(json!([
+            {"type": "story", "id": 1, "by": "pg", "time": 1, "title": "Tokio 2.0", "score": 150},
+            {"type": "story", "id": 2, "by": "pg", "time": 2, "title": "Tokio tips", "score": 5},
+            {"type": "comment", "id": 3, "by": "dang", "time": 3, "parent": 1, "text": "I use Acme daily"},
+        ]))
+        .unwrap()
+    }
+
+    fn config() -> AlertsConfig {
+        AlertsConfig {
+            rules: vec![
+                Rule {
+                    name: "popular".to_string(),
+                    title: Some("(?i)rust|tokio".to_string()),
+                    min_score: Some(100),
+                    ..Default::default()
+                },
+                Rule {
+                    name: "dang".to_string(),
+                    author: Some("dang".to_string()),
+                    ..Default::default()
+                },
+                Rule {
+                    name: "mention".to_string(),
+                    text: Some("Acme".to_string()),
+                    kind: Some("comment".to_string()),
+                    ..Default::default()
+                },
+            ],
+            sinks: vec![],
+        }
+    }
+
+    #[derive(Debug)]
+    struct Failing;
+
+    #[async_trait(?Send)]
+    impl Sink for Failing {
+        async fn send(&self, _alert: &Alert) -> Result<(), Box<dyn Error>> {
+            Err("unreachable".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_retries_undelivered_alerts() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let sent = dir.path().join("sent.json");
+        let collect = Collect::default();
+
+        let mut alerter = Alerter::new(&config(), &sent)?.with_sinks(vec![Box::new(Failing)]);
+        assert!(alerter.check(&items()).await?.is_empty());
+
+        let mut alerter = alerter.with_sinks(vec![Box::new(Failing), Box::new(collect.clone())]);
+        assert_eq!(alerter.check(&items()).await?.len(), 3);
+        assert!(alerter.check(&items()).await?.is_empty());
+        assert_eq!(collect.0.lock().unwrap().len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_dedupes_across_restarts() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let sent = dir.path().join("sent.json");
+        let collect = Collect::default();
+
+        let mut alerter =
+            Alerter::new(&config(), &sent)?.with_sinks(vec![Box::new(collect.clone())]);
+        let alerts = alerter.check(&items()).await?;
+        let matched: Vec<(&str, u64)> = alerts.iter().map(|a| (a.rule.as_str(), a.id)).collect();
+        assert_eq!(matched, vec![("popular", 1), ("dang", 3), ("mention", 3)]);
+        assert_eq!(collect.0.lock().unwrap().len(), 3);
+        assert!(alerter.check(&items()).await?.is_empty());
+
+        let mut alerter =
+            Alerter::new(&config(), &sent)?.with_sinks(vec![Box::new(collect.clone())]);
+        assert!(alerter.check(&items()).await?.is_empty());
+        assert_eq!(collect.0.lock().unwrap().len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sinks() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(body_partial_json(json!({"rule": "dang", "id": 3})))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("alerts.ndjson");
+        let piped = dir.path().join("piped.json");
+
+        let config: AlertsConfig = serde_json::from_value(json!({
+            "rules": [{"name": "dang", "author": "dang"}],
+            "sinks": [
+                {"type": "file", "path": file},
+                {"type": "command", "command": format!("cat > {}", piped.display())},
+                {"type": "webhook", "url": format!("{}/hook", server.uri())},
+            ],
+        }))?;
+        assert_eq!(config.sinks[0], SinkConfig::File { path: file.clone() });
+        let mut alerter = Alerter::new(&config, dir.path().join("sent.json"))?;
+        alerter.check(&items()).await?;
+
+        let line = std::fs::read_to_string(&file)?;
+        let alert: Alert = serde_json::from_str(line.trim())?;
+        assert_eq!(alert.id, 3);
+        let piped: Alert = serde_json::from_str(&std::fs::read_to_string(&piped)?)?;
+        assert_eq!(piped, alert);
+
+        Ok(())
+    }
+}
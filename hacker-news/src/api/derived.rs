@@ -28,6 +28,25 @@ pub struct Listing {
     pub title: String,
     /// The Listing's score.
     pub score: Option<Score>,
+    /// The total comment count of a story or poll.
+    pub descendants: Option<u32>,
+}
+
+impl Listing {
+    /// The number of comments, counting only direct replies when the total
+    /// is unknown.
+    pub fn comments(&self) -> usize {
+        self.descendants
+            .map(|count| count as usize)
+            .unwrap_or_else(|| self.kids.as_ref().map(Vec::len).unwrap_or(0))
+    }
+
+    /// The host of the listing's URL, without a leading `www.`.
+    pub fn domain(&self) -> Option<String> {
+        let url = reqwest::Url::parse(self.url.as_deref()?).ok()?;
+        let host = url.host_str()?;
+        Some(host.strip_prefix("www.").unwrap_or(host).to_string())
+    }
 }
 
 impl TryFrom<Item> for Listing {
@@ -46,6 +65,7 @@ impl TryFrom<Item> for Listing {
                 url: j.url,
                 title: j.title,
                 score: None,
+                descendants: None,
             }),
             Item::Story(s) => Ok(Listing {
                 id: s.id,
@@ -58,6 +78,7 @@ impl TryFrom<Item> for Listing {
                 url: s.url,
                 title: s.title,
                 score: s.score,
+                descendants: s.descendants,
             }),
             Item::Comment(_) => Err(HnError::ListingError(Some("A Comment cannot be a top level listing"))),
             Item::Poll(p) => Ok(Listing {
@@ -71,6 +92,7 @@ impl TryFrom<Item> for Listing {
                 url: None,
                 title: p.title,
                 score: p.score,
+                descendants: p.descendants,
             }),
             Item::PollOption(_) =>  Err(HnError::ListingError(Some("A PollOption cannot be a top level listing"))),
         }
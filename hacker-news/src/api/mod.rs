@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
 pub mod derived;
@@ -5,20 +6,21 @@ pub mod derived;
 pub type Score = u64;
 pub type Id = u64;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     /// The user's unique username. Case-sensitive.
-    id: String,
+    pub id: String,
     /// Delay in minutes between a comment's creation and its visibility to other users.
-    delay: Option<u32>,
-    /// Creation date of the user, in Unix Time.
-    created: u32,
+    pub delay: Option<u32>,
+    /// Creation date of the user, serialized as Unix Time.
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub created: DateTime<Utc>,
     /// The user's karma.
-    karma: Score,
+    pub karma: Score,
     /// The user's optional self-description. HTML.
-    about: Option<String>,
-    /// List of the user's stories, polls and comments.
-    submitted: Option<Vec<Id>>,
+    pub about: Option<String>,
+    /// List of the user's stories, polls and comments, newest first.
+    pub submitted: Option<Vec<Id>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -73,7 +75,7 @@ pub struct Story {
     pub text: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Comment {
     // Fields directly obtained from the response payload
     /// The item's unique id.
@@ -162,6 +164,18 @@ pub enum Item {
 }
 
 impl Item {
+    /// The type of item, as named by the API. One of "job", "story", "comment",
+    /// "poll", or "pollopt".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Item::Job(_) => "job",
+            Item::Story(_) => "story",
+            Item::Comment(_) => "comment",
+            Item::Poll(_) => "poll",
+            Item::PollOption(_) => "pollopt",
+        }
+    }
+
     pub fn id(&self) -> Id {
         match self {
             Item::Job(j) => j.id,
@@ -212,6 +226,37 @@ impl Item {
         }
     }
 
+    /// The title of a story, poll or job.
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            Item::Job(j) => Some(&j.title),
+            Item::Story(s) => Some(&s.title),
+            Item::Poll(p) => Some(&p.title),
+            Item::Comment(_) | Item::PollOption(_) => None,
+        }
+    }
+
+    /// The text of the item. HTML.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Item::Job(j) => j.text.as_deref(),
+            Item::Story(s) => s.text.as_deref(),
+            Item::Comment(c) => c.text.as_deref(),
+            Item::Poll(p) => p.text.as_deref(),
+            Item::PollOption(_) => None,
+        }
+    }
+
+    /// The score of a story or poll, or the votes for a pollopt.
+    pub fn score(&self) -> Option<Score> {
+        match self {
+            Item::Story(s) => s.score,
+            Item::Poll(p) => p.score,
+            Item::PollOption(po) => po.score,
+            Item::Job(_) | Item::Comment(_) => None,
+        }
+    }
+
     pub fn kids(&self) -> Option<&[Id]> {
         match self {
             Item::Job(j) => j.kids.as_deref(),
@@ -223,6 +268,66 @@ impl Item {
     }
 }
 
+/// One of the story lists published by the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StoryList {
+    Top,
+    New,
+    Best,
+    Ask,
+    Show,
+    Job,
+}
+
+impl StoryList {
+    /// All story lists.
+    pub const ALL: [StoryList; 6] = [
+        StoryList::Top,
+        StoryList::New,
+        StoryList::Best,
+        StoryList::Ask,
+        StoryList::Show,
+        StoryList::Job,
+    ];
+
+    /// The short name of this list, as used on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StoryList::Top => "top",
+            StoryList::New => "new",
+            StoryList::Best => "best",
+            StoryList::Ask => "ask",
+            StoryList::Show => "show",
+            StoryList::Job => "job",
+        }
+    }
+
+    /// The API endpoint publishing this list.
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            StoryList::Top => "topstories",
+            StoryList::New => "newstories",
+            StoryList::Best => "beststories",
+            StoryList::Ask => "askstories",
+            StoryList::Show => "showstories",
+            StoryList::Job => "jobstories",
+        }
+    }
+}
+
+impl std::str::FromStr for StoryList {
+    type Err = crate::error::HnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StoryList::ALL
+            .into_iter()
+            .find(|list| list.as_str() == s)
+            .ok_or(crate::error::HnError::ArgumentError(Some(
+                "story list must be one of top, new, best, ask, show or job",
+            )))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct ItemsAndProfiles {
     pub items: Vec<Id>,
@@ -254,8 +359,9 @@ impl Item {
 #[cfg(test)]
 mod tests {
 
+    use super::derived::Listing;
     use super::Item;
-    use super::Story;
+    use super::{Job, Poll, Story};
 
     #[test]
     fn test_item_type() {
@@ -275,4 +381,39 @@ mod tests {
 
         assert!(story.is_story());
     }
+
+    #[test]
+    fn test_listing_from_job_and_poll() {
+        let job = Listing::try_from(Item::Job(Job {
+            id: 1,
+            deleted: false,
+            by: None,
+            time: 0,
+            dead: false,
+            kids: None,
+            text: None,
+            url: Some("https://www.example.com/careers?id=1".to_string()),
+            title: "Example is hiring".to_string(),
+        }))
+        .unwrap();
+        assert_eq!(job.domain().as_deref(), Some("example.com"));
+        assert_eq!(job.comments(), 0);
+
+        let poll = Listing::try_from(Item::Poll(Poll {
+            id: 2,
+            deleted: false,
+            by: Some("pg".to_string()),
+            time: 0,
+            dead: false,
+            kids: Some(vec![3]),
+            parts: Some(vec![4, 5]),
+            descendants: Some(12),
+            score: Some(40),
+            title: "Poll: tabs or spaces?".to_string(),
+            text: None,
+        }))
+        .unwrap();
+        assert_eq!(poll.domain(), None);
+        assert_eq!(poll.comments(), 12);
+    }
 }
@@ -0,0 +1,588 @@
+//! A compressed, newline delimited JSON archive format for [Item]s.
+//!
+//! An archive is a directory containing an `index.json` file, and any number of
+//! gzip compressed segment files. Each segment holds the JSON serialization of
+//! one [Item] per line, exactly as it is returned by the Hacker News API, and
+//! covers a fixed range of ids:
+//!
+//! ```text
+//! archive/
+//!   index.json
+//!   items-000000000-000099999.ndjson.gz
+//!   items-000100000-000199999.ndjson.gz
+//!   items-000100000-000199999.1.ndjson.gz
+//! ```
+//!
+//! The id range of a segment is `[start, start + segment_size)`, where
+//! `segment_size` is recorded in the index. Items are not required to be written
+//! in id order; if an item falls in a range which already has a segment, a new
+//! "part" is created for that range. When an id appears more than once, the most
+//! recently written copy wins.
+//!
+//! The index records, for each segment, its file name, its id range, the number
+//! of items it contains, and the smallest and largest id actually written. The
+//! [ArchiveReader] uses this to only decompress the segments which could contain
+//! a requested id.
+//!
+//! A segment is written as a sequence of gzip members of up to [BLOCK_SIZE]
+//! items each, which together are still a valid gzip file. The index records
+//! the offset and id range of every block, so looking up an id only
+//! decompresses the blocks which could contain it. Segments of archives written
+//! before blocks were introduced have none recorded, and are scanned in full.
+
+use crate::api::Id;
+use crate::api::Item;
+use crate::error::HnError;
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Current version of the archive format.
+pub const ARCHIVE_VERSION: u32 = 1;
+/// Default number of ids covered by a single segment.
+pub const DEFAULT_SEGMENT_SIZE: Id = 100_000;
+/// Number of items compressed together in a block of a segment.
+pub const BLOCK_SIZE: u64 = 1000;
+
+/// Errors of reading an archive, which can be sent from the blocking thread
+/// pool.
+type ReadError = Box<dyn Error + Send + Sync>;
+
+const INDEX_FILE: &str = "index.json";
+
+/// The `index.json` file of an archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveIndex {
+    /// Version of the archive format.
+    pub version: u32,
+    /// Number of ids covered by a single segment.
+    pub segment_size: Id,
+    /// All segments of the archive, in the order they were written.
+    pub segments: Vec<Segment>,
+}
+
+/// An entry in the [ArchiveIndex] describing a single segment file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    /// File name of the segment, relative to the archive directory.
+    pub file: String,
+    /// First id of the range covered by this segment.
+    pub start: Id,
+    /// Last id of the range covered by this segment.
+    pub end: Id,
+    /// Number of items in this segment.
+    pub count: u64,
+    /// Smallest id written to this segment.
+    pub min_id: Id,
+    /// Largest id written to this segment.
+    pub max_id: Id,
+    /// The separately compressed blocks of this segment, in file order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<Block>,
+}
+
+/// A gzip member of a [Segment], holding up to [BLOCK_SIZE] items.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    /// Byte offset of the block in the segment file.
+    pub offset: u64,
+    /// Number of items in this block.
+    pub count: u64,
+    /// Smallest id written to this block.
+    pub min_id: Id,
+    /// Largest id written to this block.
+    pub max_id: Id,
+}
+
+impl Block {
+    fn add(&mut self, id: Id) {
+        if self.count == 0 {
+            self.min_id = id;
+            self.max_id = id;
+        } else {
+            self.min_id = self.min_id.min(id);
+            self.max_id = self.max_id.max(id);
+        }
+        self.count += 1;
+    }
+}
+
+impl Segment {
+    /// Whether this segment could contain the item `id`.
+    pub fn may_contain(&self, id: Id) -> bool {
+        self.count > 0 && self.min_id <= id && id <= self.max_id
+    }
+
+    fn overlaps(&self, range: &RangeInclusive<Id>) -> bool {
+        self.count > 0 && self.min_id <= *range.end() && *range.start() <= self.max_id
+    }
+}
+
+impl ArchiveIndex {
+    fn read(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let f = File::open(dir.join(INDEX_FILE))?;
+        let index: ArchiveIndex = serde_json::from_reader(BufReader::new(f))?;
+        if index.version != ARCHIVE_VERSION {
+            return Err(Box::new(HnError::SerializationError(Some(
+                "unsupported archive version",
+            ))));
+        }
+
+        Ok(index)
+    }
+
+    fn write(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        // Write to a temporary file and rename, so a crash never leaves a
+        // truncated index behind.
+        let tmp = dir.join(format!("{}.tmp", INDEX_FILE));
+        let mut f = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut f, self)?;
+        f.flush()?;
+        std::fs::rename(tmp, dir.join(INDEX_FILE))?;
+
+        Ok(())
+    }
+}
+
+/// The segment currently being written by an [ArchiveWriter].
+struct OpenSegment {
+    segment: Segment,
+    file: BufWriter<File>,
+    /// Number of bytes written to `file`.
+    written: u64,
+    /// The block being compressed, written to `file` once it is full.
+    block: Block,
+    encoder: GzEncoder<Vec<u8>>,
+}
+
+impl OpenSegment {
+    /// Write the block in progress to the file, and start a new one.
+    fn flush_block(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.block.count == 0 {
+            return Ok(());
+        }
+        let encoder = std::mem::replace(
+            &mut self.encoder,
+            GzEncoder::new(vec![], Compression::default()),
+        );
+        let compressed = encoder.finish()?;
+        self.file.write_all(&compressed)?;
+        let block = Block {
+            offset: self.written + compressed.len() as u64,
+            count: 0,
+            min_id: 0,
+            max_id: 0,
+        };
+        self.written = block.offset;
+        self.segment
+            .blocks
+            .push(std::mem::replace(&mut self.block, block));
+
+        Ok(())
+    }
+}
+
+/// Streaming writer of an item archive.
+///
+/// Items are compressed as they are written. The index is only updated when a
+/// segment is closed, so [ArchiveWriter::finish] must be called once all items
+/// are written.
+pub struct ArchiveWriter {
+    dir: PathBuf,
+    index: ArchiveIndex,
+    current: Option<OpenSegment>,
+}
+
+impl ArchiveWriter {
+    /// Create a new, empty archive in `dir` using [DEFAULT_SEGMENT_SIZE].
+    pub fn create(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::with_segment_size(dir, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Create a new, empty archive in `dir` where each segment covers
+    /// `segment_size` ids.
+    pub fn with_segment_size(
+        dir: impl AsRef<Path>,
+        segment_size: Id,
+    ) -> Result<Self, Box<dyn Error>> {
+        if segment_size == 0 {
+            return Err(Box::new(HnError::ArgumentError(Some(
+                "archive segment size must be greater than zero",
+            ))));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        if dir.join(INDEX_FILE).exists() {
+            return Err(Box::new(HnError::ArgumentError(Some(
+                "an archive already exists in this directory",
+            ))));
+        }
+        let index = ArchiveIndex {
+            version: ARCHIVE_VERSION,
+            segment_size,
+            segments: vec![],
+        };
+        index.write(&dir)?;
+
+        Ok(Self {
+            dir,
+            index,
+            current: None,
+        })
+    }
+
+    /// Open an existing archive in `dir` to append more items to it.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref().to_path_buf();
+        let index = ArchiveIndex::read(&dir)?;
+
+        Ok(Self {
+            dir,
+            index,
+            current: None,
+        })
+    }
+
+    /// Append an [Item] to the archive.
+    pub fn write(&mut self, item: &Item) -> Result<(), Box<dyn Error>> {
+        let id = item.id();
+        let start = id - id % self.index.segment_size;
+        let in_current = matches!(self.current, Some(ref open) if open.segment.start == start);
+        if !in_current {
+            self.close_segment()?;
+            self.current = Some(self.open_segment(start)?);
+        }
+
+        // SAFE: a segment for this id was opened above
+        let open = self.current.as_mut().unwrap();
+        serde_json::to_writer(&mut open.encoder, item)?;
+        open.encoder.write_all(b"\n")?;
+        open.block.add(id);
+        if open.block.count >= BLOCK_SIZE {
+            open.flush_block()?;
+        }
+        let segment = &mut open.segment;
+        if segment.count == 0 {
+            segment.min_id = id;
+            segment.max_id = id;
+        } else {
+            segment.min_id = segment.min_id.min(id);
+            segment.max_id = segment.max_id.max(id);
+        }
+        segment.count += 1;
+
+        Ok(())
+    }
+
+    /// Flush the open segment and write the final index.
+    pub fn finish(mut self) -> Result<ArchiveIndex, Box<dyn Error>> {
+        self.close_segment()?;
+        self.index.write(&self.dir)?;
+
+        Ok(self.index)
+    }
+
+    fn open_segment(&self, start: Id) -> Result<OpenSegment, Box<dyn Error>> {
+        let end = start + self.index.segment_size - 1;
+        let parts = self
+            .index
+            .segments
+            .iter()
+            .filter(|segment| segment.start == start)
+            .count();
+        let file = match parts {
+            0 => format!("items-{:09}-{:09}.ndjson.gz", start, end),
+            n => format!("items-{:09}-{:09}.{}.ndjson.gz", start, end, n),
+        };
+        tracing::debug!(file=?file, "opening archive segment");
+        let f = File::create(self.dir.join(&file))?;
+
+        Ok(OpenSegment {
+            segment: Segment {
+                file,
+                start,
+                end,
+                count: 0,
+                min_id: 0,
+                max_id: 0,
+                blocks: vec![],
+            },
+            file: BufWriter::new(f),
+            written: 0,
+            block: Block {
+                offset: 0,
+                count: 0,
+                min_id: 0,
+                max_id: 0,
+            },
+            encoder: GzEncoder::new(vec![], Compression::default()),
+        })
+    }
+
+    fn close_segment(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(mut open) = self.current.take() {
+            open.flush_block()?;
+            open.file.flush()?;
+            self.index.segments.push(open.segment);
+            self.index.write(&self.dir)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Only the `id` field of a serialized item, used to skip over lines without
+/// fully deserializing them.
+#[derive(Deserialize)]
+struct ItemId {
+    id: Id,
+}
+
+/// Reader of an item archive written by [ArchiveWriter].
+#[derive(Debug, Clone)]
+pub struct ArchiveReader {
+    dir: PathBuf,
+    index: Arc<ArchiveIndex>,
+}
+
+impl ArchiveReader {
+    /// Open the archive in `dir`.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref().to_path_buf();
+        let index = Arc::new(ArchiveIndex::read(&dir)?);
+
+        Ok(Self { dir, index })
+    }
+
+    /// The index of this archive.
+    pub fn index(&self) -> &ArchiveIndex {
+        &self.index
+    }
+
+    /// Look up a single [Item] by id. Returns `None` if the archive does not
+    /// contain the item.
+    pub fn get(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        self.find(id).map_err(|err| err as Box<dyn Error>)
+    }
+
+    /// Like [ArchiveReader::get], reading the archive on the blocking thread
+    /// pool so it does not stall other tasks.
+    pub async fn item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        let reader = self.clone();
+        tokio::task::spawn_blocking(move || reader.find(id))
+            .await?
+            .map_err(|err| err as Box<dyn Error>)
+    }
+
+    fn find(&self, id: Id) -> Result<Option<Item>, ReadError> {
+        // Later segments and blocks take precedence, so search them first.
+        for segment in self.index.segments.iter().rev() {
+            if !segment.may_contain(id) {
+                continue;
+            }
+            let found = if segment.blocks.is_empty() {
+                Self::find_line(self.lines(segment)?, id)?
+            } else {
+                let mut found = None;
+                for block in segment.blocks.iter().rev() {
+                    if block.count == 0 || id < block.min_id || block.max_id < id {
+                        continue;
+                    }
+                    let mut f = File::open(self.dir.join(&segment.file))?;
+                    f.seek(SeekFrom::Start(block.offset))?;
+                    // A single member decoder stops at the end of the block.
+                    found = Self::find_line(BufReader::new(GzDecoder::new(f)).lines(), id)?;
+                    if found.is_some() {
+                        break;
+                    }
+                }
+                found
+            };
+            if let Some(line) = found {
+                return Ok(Some(serde_json::from_str(&line)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The last of `lines` holding the item `id`.
+    fn find_line(lines: std::io::Lines<impl BufRead>, id: Id) -> Result<Option<String>, ReadError> {
+        let mut found = None;
+        for line in lines {
+            let line = line?;
+            let head: ItemId = serde_json::from_str(&line)?;
+            if head.id == id {
+                // Keep scanning, the last copy wins.
+                found = Some(line);
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Iterate over every [Item] in the archive, in the order it was written.
+    /// Items written more than once are yielded more than once.
+    pub fn iter(&self) -> impl Iterator<Item = Result<Item, Box<dyn Error>>> + '_ {
+        self.iter_range(0..=Id::MAX)
+    }
+
+    /// Iterate over the [Item]s with an id in `range`, in the order they were
+    /// written. Only segments overlapping `range` are decompressed.
+    pub fn iter_range(
+        &self,
+        range: RangeInclusive<Id>,
+    ) -> impl Iterator<Item = Result<Item, Box<dyn Error>>> + '_ {
+        let bounds = range.clone();
+        self.index
+            .segments
+            .iter()
+            .filter(move |segment| segment.overlaps(&bounds))
+            .flat_map(
+                move |segment| -> Box<dyn Iterator<Item = Result<Item, Box<dyn Error>>>> {
+                    let range = range.clone();
+                    match self.lines(segment) {
+                        Err(err) => Box::new(std::iter::once(Err(err.into()))),
+                        Ok(lines) => Box::new(lines.filter_map(move |line| {
+                            let item = line
+                                .map_err(|err| Box::new(err) as Box<dyn Error>)
+                                .and_then(|line| {
+                                    serde_json::from_str::<Item>(&line)
+                                        .map_err(|err| Box::new(err) as Box<dyn Error>)
+                                });
+                            match item {
+                                Ok(item) if !range.contains(&item.id()) => None,
+                                other => Some(other),
+                            }
+                        })),
+                    }
+                },
+            )
+    }
+
+    fn lines(
+        &self,
+        segment: &Segment,
+    ) -> Result<std::io::Lines<BufReader<MultiGzDecoder<File>>>, std::io::Error> {
+        let f = File::open(self.dir.join(&segment.file))?;
+
+        Ok(BufReader::new(MultiGzDecoder::new(f)).lines())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::ArchiveReader;
+    use super::ArchiveWriter;
+    use crate::api::{Item, Story};
+    use crate::testing::{comment, story};
+    use std::error::Error;
+
+    #[test]
+    fn test_archive_round_trip() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = ArchiveWriter::with_segment_size(dir.path(), 10)?;
+        writer.write(&Item::Story(Story {
+            title: "Y Combinator".to_string(),
+            descendants: Some(2),
+            ..story(1, vec![2, 25])
+        }))?;
+        writer.write(&comment(2, 1, vec![], "first"))?;
+        writer.write(&comment(25, 1, vec![], "second"))?;
+        // goes back to the first range, creating a second part
+        writer.write(&comment(3, 1, vec![], "third"))?;
+        let index = writer.finish()?;
+        assert_eq!(index.segments.len(), 3);
+        assert_eq!(
+            index.segments[2].file,
+            "items-000000000-000000009.1.ndjson.gz"
+        );
+
+        let reader = ArchiveReader::open(dir.path())?;
+        assert!(reader.get(1)?.unwrap().is_story());
+        match reader.get(25)? {
+            Some(Item::Comment(c)) => assert_eq!(c.text.as_deref(), Some("second")),
+            other => panic!("unexpected item {:?}", other),
+        }
+        assert!(reader.get(3)?.is_some());
+        assert!(reader.get(4)?.is_none());
+        assert_eq!(reader.iter().count(), 4);
+        let ids = reader
+            .iter_range(2..=3)
+            .map(|item| item.map(|item| item.id()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(ids, vec![2, 3]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_archive_blocks() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = ArchiveWriter::create(dir.path())?;
+        for id in 1..=2500 {
+            writer.write(&comment(id, 1, vec![], "text"))?;
+        }
+        writer.write(&comment(10, 1, vec![], "edited"))?;
+        let index = writer.finish()?;
+        let blocks = &index.segments[0].blocks;
+        assert_eq!(blocks.len(), 3);
+        assert_eq!((blocks[1].min_id, blocks[1].max_id), (1001, 2000));
+        assert_eq!((blocks[2].min_id, blocks[2].max_id), (10, 2500));
+
+        let reader = ArchiveReader::open(dir.path())?;
+        assert_eq!(reader.item(1500).await?.map(|item| item.id()), Some(1500));
+        match reader.item(10).await? {
+            Some(Item::Comment(c)) => assert_eq!(c.text.as_deref(), Some("edited")),
+            other => panic!("unexpected item {:?}", other),
+        }
+        assert!(reader.item(2501).await?.is_none());
+        assert_eq!(reader.iter().count(), 2501);
+
+        // archives written without blocks are scanned in full
+        let mut index = index;
+        index.segments[0].blocks.clear();
+        std::fs::write(
+            dir.path().join("index.json"),
+            serde_json::to_string(&index)?,
+        )?;
+        let reader = ArchiveReader::open(dir.path())?;
+        assert_eq!(reader.get(1500)?.map(|item| item.id()), Some(1500));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_append_overrides() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = ArchiveWriter::create(dir.path())?;
+        writer.write(&comment(7, 1, vec![], "original"))?;
+        writer.finish()?;
+
+        let mut writer = ArchiveWriter::open(dir.path())?;
+        writer.write(&comment(7, 1, vec![], "edited"))?;
+        writer.finish()?;
+
+        let reader = ArchiveReader::open(dir.path())?;
+        match reader.get(7)? {
+            Some(Item::Comment(c)) => assert_eq!(c.text.as_deref(), Some("edited")),
+            other => panic!("unexpected item {:?}", other),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Authenticated sessions on news.ycombinator.com.
+//!
+//! Logging in with [html_client::Client::login](crate::client::html_client::Client::login)
+//! yields an [AuthSession] holding the `user` cookie the site sets. The
+//! session is saved in a [CredentialStore] under the name of the config
+//! profile, so later invocations can act on behalf of the account until it is
+//! removed by `hn logout`.
+
+use crate::credentials::CredentialStore;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// The credentials of a logged in user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub username: String,
+    /// Value of the `user` cookie, i.e. `username&token`.
+    cookie: String,
+}
+
+impl AuthSession {
+    pub fn new(username: impl Into<String>, cookie: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            cookie: cookie.into(),
+        }
+    }
+
+    /// Value of the `Cookie` header authenticating requests.
+    pub(crate) fn cookie_header(&self) -> String {
+        format!("user={}", self.cookie)
+    }
+
+    /// Load the session saved for `account`. Returns `None` when no session
+    /// was saved.
+    pub fn load(
+        store: &dyn CredentialStore,
+        account: &str,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        match store.get(account)? {
+            Some(secret) => Ok(Some(serde_json::from_str(&secret)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Save the session for `account`, so the cookie is only ever stored
+    /// encrypted or in the system keyring.
+    pub fn save(&self, store: &dyn CredentialStore, account: &str) -> Result<(), Box<dyn Error>> {
+        store.set(account, &serde_json::to_string(self)?)
+    }
+
+    /// Remove the session saved for `account`. Returns false if there was
+    /// none.
+    pub fn remove(store: &dyn CredentialStore, account: &str) -> Result<bool, Box<dyn Error>> {
+        store.delete(account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::AuthSession;
+    use crate::credentials::EncryptedFile;
+    use std::error::Error;
+
+    #[test]
+    fn test_save_load_remove() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let store = EncryptedFile::with_passphrase(dir.path().join("session.json"), "secret");
+        assert_eq!(AuthSession::load(&store, "work")?, None);
+
+        let session = AuthSession::new("pg", "pg&abc123");
+        session.save(&store, "work")?;
+        assert_eq!(AuthSession::load(&store, "work")?, Some(session));
+        assert_eq!(AuthSession::load(&store, "home")?, None);
+
+        assert!(AuthSession::remove(&store, "work")?);
+        assert!(!AuthSession::remove(&store, "work")?);
+        assert_eq!(AuthSession::load(&store, "work")?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::alerts::{sent_path, Alerter};
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Watch new items and score updates for items matching alert rules.
+pub struct Alerts;
+
+impl HnCommand for Alerts {
+    const NAME: &'static str = "alerts";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Watch new items and score updates, and send alerts for items matching the rules of the config file")
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .value_name("duration")
+                    .help("Time between checks, i.e. 30s or 5m")
+                    .default_value("60s")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("sent")
+                    .long("sent")
+                    .value_name("path")
+                    .help("File remembering the alerts already sent. Defaults to alerts-sent.json in the data directory")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        if settings.alerts.rules.is_empty() {
+            return Err(Box::new(HnError::ArgumentError(Some(
+                "the config file defines no alert rules",
+            ))));
+        }
+        let interval = matches
+            .value_of("interval")
+            .map(humantime::parse_duration)
+            .transpose()
+            .map_err(|_| HnError::ArgumentError(Some("interval must be a duration such as 60s")))?
+            .unwrap_or(Duration::from_secs(60));
+        let sent = matches
+            .value_of("sent")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| sent_path(&settings.data_dir));
+
+        let client = settings.client();
+        let mut alerter = Alerter::new(&settings.alerts, sent).map_err(super::lib_err)?;
+        loop {
+            // Keep watching through transient failures, i.e. a dropped connection.
+            if let Err(err) = alerter.poll(&client).await {
+                warn!(err = %err, "failed to check for alerts");
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
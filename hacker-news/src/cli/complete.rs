@@ -0,0 +1,130 @@
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::api::derived::Listing;
+use crate::cli::HnCommand;
+use crate::config::Settings;
+use crate::error::HnError;
+use crate::store::Store;
+
+/// Print candidates for the dynamic shell completion of `hn completions`.
+pub struct Complete;
+
+impl Complete {
+    /// How long to wait for the front page, so a slow network does not hang
+    /// the shell.
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Number of recently seen usernames to suggest.
+    const USERS: usize = 100;
+
+    /// The stories of the front page, from the configured source or else the
+    /// last snapshot of the local database.
+    async fn front_page(settings: &Settings) -> Vec<Listing> {
+        let items = tokio::time::timeout(Self::TIMEOUT, async {
+            let source = super::source(settings).ok()?;
+            let ids = source.list_ids(settings.list).await.ok()??;
+            let ids = &ids[..settings.count.min(ids.len())];
+            source.get_items(ids).await.ok()
+        })
+        .await
+        .ok()
+        .flatten();
+        let items = match items {
+            Some(items) => items,
+            None => {
+                debug!("front page not available, reading the local database");
+                let store = match Self::store(settings) {
+                    Some(store) => store,
+                    None => return vec![],
+                };
+                let ids = store.latest_list(settings.list).ok().flatten();
+                ids.unwrap_or_default()
+                    .into_iter()
+                    .take(settings.count)
+                    .filter_map(|id| store.item(id).ok().flatten())
+                    .collect()
+            }
+        };
+
+        items
+            .into_iter()
+            .filter_map(|item| Listing::try_from(item).ok())
+            .collect()
+    }
+
+    /// The local database, when `hn sync` created one.
+    fn store(settings: &Settings) -> Option<Store> {
+        let db = settings.db_path();
+        if !db.exists() {
+            return None;
+        }
+
+        Store::open(db).ok()
+    }
+}
+
+impl HnCommand for Complete {
+    const NAME: &'static str = "complete";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Print completion candidates, one per line, for the scripts of `hn completions`")
+            .setting(AppSettings::Hidden)
+            .arg(
+                Arg::with_name("kind")
+                    .value_name("kind")
+                    .help("What to complete, story ids with their titles or usernames")
+                    .possible_values(&["ids", "users"])
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("prefix")
+                    .value_name("prefix")
+                    .help("The word being completed")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        let prefix = matches.value_of("prefix").unwrap_or("");
+
+        // Failures only leave out candidates, the shell has no place to show
+        // an error.
+        match matches.value_of("kind") {
+            Some("ids") => {
+                for listing in Self::front_page(&settings).await {
+                    let id = listing.id.to_string();
+                    if id.starts_with(prefix) {
+                        println!("{}\t{}", id, listing.title.replace(['\t', '\n'], " "));
+                    }
+                }
+            }
+            _ => {
+                let mut seen = HashSet::new();
+                let recent = Self::store(&settings)
+                    .and_then(|store| store.recent_users(prefix, Self::USERS).ok())
+                    .unwrap_or_default();
+                let front_page = Self::front_page(&settings)
+                    .await
+                    .into_iter()
+                    .filter_map(|listing| listing.by);
+                for name in recent.into_iter().chain(front_page) {
+                    if name.starts_with(prefix) && seen.insert(name.clone()) {
+                        println!("{}", name);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
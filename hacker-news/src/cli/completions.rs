@@ -0,0 +1,212 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::Shell;
+use clap::SubCommand;
+use std::io::Write;
+
+use crate::cli::complete::Complete;
+use crate::cli::fave::Fave;
+use crate::cli::hacker_news::HackerNews;
+use crate::cli::hide::Hide;
+use crate::cli::output::write_err;
+use crate::cli::query::Query;
+use crate::cli::replies::Replies;
+use crate::cli::thread::Thread;
+use crate::cli::tree::Tree;
+use crate::cli::user::User;
+use crate::cli::vote::Vote;
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Name of the binary the completion scripts are for.
+const BIN: &str = "hn";
+
+/// Print shell completion scripts.
+pub struct Completions;
+
+impl Completions {
+    /// Subcommands completing their arguments with story ids of the front page.
+    const ID_COMMANDS: [&'static str; 6] = [
+        Query::NAME,
+        Thread::NAME,
+        Tree::NAME,
+        Vote::NAME,
+        Fave::NAME,
+        Hide::NAME,
+    ];
+
+    /// Subcommands completing their arguments with recently seen usernames.
+    const USER_COMMANDS: [&'static str; 2] = [User::NAME, Replies::NAME];
+
+    /// The script clap generates from the parsers of the subcommands, which
+    /// completes subcommands, options and their possible values.
+    fn generated(shell: Shell) -> String {
+        let mut script = vec![];
+        HackerNews::parser().gen_completions_to(BIN, shell, &mut script);
+
+        String::from_utf8_lossy(&script).into_owned()
+    }
+
+    /// Bash can not show descriptions, so only the ids are offered.
+    fn bash() -> String {
+        format!(
+            r#"{generated}
+_{bin}_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}" word kind
+    if [[ ${{cur}} != -* && ${{prev}} != -* ]]; then
+        for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+            case "${{word}}" in
+                {ids}) kind=ids; break ;;
+                {users}) kind=users; break ;;
+            esac
+        done
+    fi
+    if [[ -n ${{kind}} ]]; then
+        COMPREPLY=($(compgen -W "$("${{COMP_WORDS[0]}}" {complete} ${{kind}} "${{cur}}" 2>/dev/null | cut -f1)" -- "${{cur}}"))
+        return 0
+    fi
+    _{bin} "$@"
+}}
+
+complete -F _{bin}_dynamic -o bashdefault -o default {bin}
+"#,
+            generated = Self::generated(Shell::Bash),
+            bin = BIN,
+            complete = Complete::NAME,
+            ids = Self::ID_COMMANDS.join("|"),
+            users = Self::USER_COMMANDS.join("|"),
+        )
+    }
+
+    /// The generated `_hn` is renamed, so the `_hn` zsh autoloads first
+    /// offers ids and usernames, with the titles of the stories.
+    fn zsh() -> String {
+        let generated = Self::generated(Shell::Zsh);
+        let generated = generated
+            .replacen(
+                &format!("\n_{}() {{", BIN),
+                &format!("\n_{}_static() {{", BIN),
+                1,
+            )
+            .replace(&format!("\n_{} \"$@\"", BIN), "");
+
+        format!(
+            r#"{generated}
+_{bin}_candidates() {{
+    local -a candidates
+    candidates=(${{(f)"$(_call_program $1 ${{words[1]}} {complete} $1 ${{(q)PREFIX}} 2>/dev/null)"}})
+    candidates=(${{candidates/$'\t'/:}})
+    _describe -t $1 $2 candidates
+}}
+
+_{bin}() {{
+    local word
+    if [[ ${{words[CURRENT]}} != -* && ${{words[CURRENT-1]}} != -* ]]; then
+        for word in ${{words[2,CURRENT-1]}}; do
+            case $word in
+                ({ids}) _{bin}_candidates ids 'story id'; return ;;
+                ({users}) _{bin}_candidates users 'username'; return ;;
+            esac
+        done
+    fi
+    _{bin}_static "$@"
+}}
+
+_{bin} "$@"
+"#,
+            generated = generated.trim_end(),
+            bin = BIN,
+            complete = Complete::NAME,
+            ids = Self::ID_COMMANDS.join("|"),
+            users = Self::USER_COMMANDS.join("|"),
+        )
+    }
+
+    /// Fish shows the title after a tab as the description of an id.
+    fn fish() -> String {
+        format!(
+            r#"{generated}
+complete -c {bin} -n "__fish_seen_subcommand_from {ids}" -f -a "({bin} {complete} ids (commandline -ct) 2>/dev/null)"
+complete -c {bin} -n "__fish_seen_subcommand_from {users}" -f -a "({bin} {complete} users (commandline -ct) 2>/dev/null)"
+"#,
+            generated = Self::generated(Shell::Fish),
+            bin = BIN,
+            complete = Complete::NAME,
+            ids = Self::ID_COMMANDS.join(" "),
+            users = Self::USER_COMMANDS.join(" "),
+        )
+    }
+}
+
+impl HnCommand for Completions {
+    const NAME: &'static str = "completions";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Print a shell completion script, which also completes story ids of the front page and recently seen usernames")
+            .after_help(
+                "INSTALL:
+    bash    hn completions bash > ~/.local/share/bash-completion/completions/hn
+    zsh     hn completions zsh > ~/.zfunc/_hn, with ~/.zfunc in $fpath
+    fish    hn completions fish > ~/.config/fish/completions/hn.fish",
+            )
+            .arg(
+                Arg::with_name("shell")
+                    .value_name("shell")
+                    .help("Shell to complete in")
+                    .possible_values(&["bash", "zsh", "fish"])
+                    .required(true)
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let script = match matches.value_of("shell") {
+            Some("zsh") => Self::zsh(),
+            Some("fish") => Self::fish(),
+            _ => Self::bash(),
+        };
+        std::io::stdout()
+            .write_all(script.as_bytes())
+            .map_err(write_err)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Completions;
+    use std::error::Error;
+    use std::io::ErrorKind;
+    use std::process::Command;
+
+    #[test]
+    fn test_zsh() {
+        let script = Completions::zsh();
+        assert!(script.starts_with("#compdef hn"));
+        // the generated _hn is renamed, and only called by the dynamic one
+        assert_eq!(script.matches("\n_hn() {").count(), 1);
+        assert_eq!(script.matches("\n_hn_static() {").count(), 1);
+        assert_eq!(script.matches("_hn_static \"$@\"").count(), 1);
+        assert_eq!(script.matches("\n_hn \"$@\"").count(), 1);
+        assert!(script.trim_end().ends_with("\n_hn \"$@\""));
+    }
+
+    #[test]
+    fn test_bash_syntax() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("hn.bash");
+        std::fs::write(&path, Completions::bash())?;
+        match Command::new("bash").arg("-n").arg(&path).status() {
+            Ok(status) => assert!(status.success(), "bash -n rejected the script"),
+            // Nothing to check the script with
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+}
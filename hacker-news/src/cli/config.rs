@@ -0,0 +1,68 @@
+use clap::App;
+use clap::AppSettings;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Show the resolved configuration.
+pub struct Config;
+
+impl HnCommand for Config {
+    const NAME: &'static str = "config";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Show the configuration, and where each setting comes from")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("show")
+                    .about("Print every setting with its value and origin. Flags override HN_* environment variables, which override the selected profile, which overrides the top level of the config file"),
+            )
+            .subcommand(SubCommand::with_name("path").about("Print the location of the config file"))
+            .subcommand(
+                SubCommand::with_name("profiles")
+                    .about("List the profiles of the config file, marking the selected one with *"),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        match matches.subcommand() {
+            ("path", _) => println!("{}", settings.config_path.display()),
+            ("profiles", _) => {
+                for (name, profile) in settings.profiles.iter() {
+                    let selected = settings.profile.as_deref() == Some(name.as_str());
+                    println!(
+                        "{}{}|{}",
+                        if selected { "*" } else { "" },
+                        name,
+                        profile.username.as_deref().unwrap_or("")
+                    );
+                }
+            }
+            _ => {
+                let exists = if settings.config_path.exists() {
+                    ""
+                } else {
+                    " (not found)"
+                };
+                println!(
+                    "# config file: {}{}",
+                    settings.config_path.display(),
+                    exists
+                );
+                if let Some(ref profile) = settings.profile {
+                    println!("# profile: {}", profile);
+                }
+                println!("# precedence: flag > env > profile > file > default");
+                for (key, value, origin) in settings.explain() {
+                    println!("{} = {}  # {}", key, value, origin);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
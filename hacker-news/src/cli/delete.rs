@@ -0,0 +1,56 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use std::io::{BufRead, Write};
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Delete a comment.
+pub struct Delete;
+
+impl HnCommand for Delete {
+    const NAME: &'static str = "delete";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Delete one of your recent comments")
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Id or item URL of the comment, or - to read it from stdin")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("yes")
+                    .long("yes")
+                    .short("y")
+                    .help("Do not ask for confirmation"),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let id = super::ids::id(matches, "id")?;
+
+        if !matches.is_present("yes") {
+            print!("Delete item {}? [y/N] ", id);
+            let _ = std::io::stdout().flush();
+            let mut answer = String::new();
+            std::io::stdin()
+                .lock()
+                .read_line(&mut answer)
+                .map_err(|_| HnError::ArgumentError(Some("failed to read confirmation")))?;
+            if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                return Ok(());
+            }
+        }
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        client.delete(id).await.map_err(super::lib_err)?;
+
+        Ok(())
+    }
+}
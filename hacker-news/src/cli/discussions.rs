@@ -0,0 +1,101 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::cli::output::{Output, Record};
+use crate::cli::HnCommand;
+use crate::discussions::{find_discussions, find_discussions_local, normalize_url, Discussion};
+use crate::error::HnError;
+use crate::search::SearchClient;
+use crate::store::Store;
+
+/// List the Hacker News discussions of a URL.
+pub struct Discussions;
+
+impl Discussions {
+    fn record(discussion: &Discussion) -> Record {
+        let date =
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(discussion.time))
+                .to_string();
+        Record::new()
+            .field("id", discussion.id)
+            .field("points", discussion.points)
+            .field("comments", discussion.num_comments)
+            .field("date", &date[..10])
+            .field("title", discussion.title.as_str())
+            .detail("time", discussion.time)
+    }
+}
+
+impl HnCommand for Discussions {
+    const NAME: &'static str = "discussions";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("List the stories which submitted a URL, newest first. Open one with `hn query <id>`")
+            .arg(
+                Arg::with_name("url")
+                    .value_name("url")
+                    .help("URL of the article. Tracking parameters, fragments and www. are ignored")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("local")
+                    .long("local")
+                    .help("Look up the local SQLite store instead of hn.algolia.com"),
+            )
+            .arg(
+                Arg::with_name("db")
+                    .long("db")
+                    .value_name("path")
+                    .help("SQLite store to look up with --local")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("search-url")
+                    .long("search-url")
+                    .value_name("url")
+                    .help("Base URL of an Algolia compatible search API")
+                    .env("HN_SEARCH_URL")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        // SAFE: url is a required argument
+        let url = matches.value_of("url").unwrap();
+        // Report a malformed url as such, rather than as a lookup failure
+        normalize_url(url)?;
+
+        let settings = super::settings(matches)?;
+        let discussions = if matches.is_present("local") {
+            let db = matches
+                .value_of("db")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| settings.db_path());
+            let store = Store::open(db).map_err(|err| HnError::StorageError(Some(err)))?;
+            find_discussions_local(&store, url).map_err(|err| HnError::StorageError(Some(err)))?
+        } else {
+            let client = match matches.value_of("search-url") {
+                Some(search_url) => SearchClient::with_base_url(search_url),
+                None => SearchClient::new(),
+            };
+            find_discussions(&client, url)
+                .await
+                .map_err(super::lib_err)?
+        };
+
+        if discussions.is_empty() {
+            eprintln!("No discussions found");
+        }
+        let mut output = Output::new(matches, &settings)?;
+        for discussion in discussions.iter() {
+            output.print(Self::record(discussion))?;
+        }
+
+        output.flush()
+    }
+}
@@ -0,0 +1,52 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+use crate::util::compose;
+
+/// Edit a comment.
+pub struct Edit;
+
+impl HnCommand for Edit {
+    const NAME: &'static str = "edit";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Edit one of your recent comments")
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Id or item URL of the comment, or - to read it from stdin")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("text")
+                    .long("text")
+                    .value_name("text")
+                    .help("New text of the comment. The current text is edited in $EDITOR when not given")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let id = super::ids::id(matches, "id")?;
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        let text = match matches.value_of("text") {
+            Some(text) => text.to_string(),
+            None => {
+                let current = client.edit_text(id).await.map_err(super::lib_err)?;
+                compose(&current)?
+            }
+        };
+        client.edit(id, &text).await.map_err(super::lib_err)?;
+        println!("{}", id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Favorite items, or with --un remove them from your favorites.
+pub struct Fave;
+
+impl HnCommand for Fave {
+    const NAME: &'static str = "fave";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Favorite items, or with --un remove them from your favorites, as the logged in user")
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Ids or item URLs of the items, or - to read them from stdin")
+                    .required(true)
+                    .multiple(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("un")
+                    .long("un")
+                    .help("Undo the favorite"),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let ids = super::ids::ids(matches, "id")?;
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        for id in ids {
+            if matches.is_present("un") {
+                client.unfavorite(id).await
+            } else {
+                client.favorite(id).await
+            }
+            .map_err(|err| {
+                super::lib_err(err).context(format!("could not favorite item {}", id))
+            })?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,45 @@
-// use crate::cli::login::Login;
-// use crate::cli::tree::Tree;
-// use crate::cli::thread::Thread;
+use crate::cli::alerts::Alerts;
+use crate::cli::complete::Complete;
+use crate::cli::completions::Completions;
+use crate::cli::config::Config;
+use crate::cli::delete::Delete;
+use crate::cli::discussions::Discussions;
+use crate::cli::edit::Edit;
+use crate::cli::fave::Fave;
+use crate::cli::hide::Hide;
+use crate::cli::login::Login;
+use crate::cli::logout::Logout;
+use crate::cli::me::Me;
 use crate::cli::news::News;
 use crate::cli::query::Query;
+use crate::cli::replies::Replies;
+use crate::cli::reply::Reply;
+use crate::cli::search::Search;
+use crate::cli::submit::Submit;
+use crate::cli::sync::SyncCmd;
+use crate::cli::thread::Thread;
+use crate::cli::tree::Tree;
+use crate::cli::user::User;
+use crate::cli::vote::Vote;
 use crate::cli::HnCommand;
+use crate::config;
 use crate::error::HnError;
 use clap::App;
+use clap::Arg;
 use clap::ArgMatches;
 
+/// The exit codes of [HnError::exit_code], listed in the help.
+const EXIT_CODES: &str = "EXIT CODES:
+    0    Success
+    1    Unexpected error
+    2    Incorrect arguments or configuration
+    3    The requested data does not exist
+    4    Not, or failed to get, authenticated
+    5    Hacker News refused the action, i.e. rate limited or a duplicate
+    6    Network failure or unsuccessful HTTP response
+    7    Failure reading or writing local storage
+    8    Data could not be parsed, i.e. a changed HTML page";
+
 /// Top level parser/cmd for the cli
 pub struct HackerNews;
 
@@ -16,33 +48,184 @@ impl HnCommand for HackerNews {
 
     fn parser<'a, 'b>() -> App<'a, 'b> {
         App::new(Self::NAME)
+            .after_help(EXIT_CODES)
+            .arg(
+                Arg::with_name("source")
+                    .long("source")
+                    .value_name("source")
+                    .help("Where to read data from, a comma separated list of network, cache[=dir], archive=dir or db[=path], tried in order. Overrides HN_SOURCE and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .value_name("path")
+                    .help("Config file to read. Defaults to HN_CONFIG, or hacker-news/config.toml in the config directory")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .value_name("name")
+                    .help("Profile of the config file to use, with its own account, session and defaults. Overrides HN_PROFILE")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .value_name("format")
+                    .help("How to print results. Overrides HN_FORMAT and the config file")
+                    .possible_values(&config::FORMATS)
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("template")
+                    .long("template")
+                    .value_name("template")
+                    .help("Print each result by filling in the {field} placeholders of this template, i.e. '{id} {title}'. Fields are named as in --format json")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("color")
+                    .long("color")
+                    .value_name("when")
+                    .help("When to color output. Overrides HN_COLOR and the config file, auto also honours NO_COLOR")
+                    .possible_values(&config::COLORS)
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("list")
+                    .long("list")
+                    .value_name("list")
+                    .help("Story list shown by hn news and the completions. Overrides HN_LIST and the config file")
+                    .possible_values(&["top", "new", "best", "ask", "show", "job"])
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("count")
+                    .long("count")
+                    .value_name("N")
+                    .help("Number of stories shown by hn news and the completions. Overrides HN_COUNT and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("concurrency")
+                    .long("concurrency")
+                    .value_name("N")
+                    .help("Maximum number of concurrent API requests. Overrides HN_CONCURRENCY and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("rate-limit")
+                    .long("rate-limit")
+                    .value_name("N")
+                    .help("Maximum number of API requests per second, 0 for unlimited. Overrides HN_RATE_LIMIT and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("data-dir")
+                    .long("data-dir")
+                    .value_name("dir")
+                    .help("Directory of the SQLite store, search index and saved sessions. Overrides HN_DATA_DIR and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("cache-dir")
+                    .long("cache-dir")
+                    .value_name("dir")
+                    .help("Directory of the cache source. Overrides HN_CACHE_DIR and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                // Named apart from the username argument of the subcommands
+                Arg::with_name("as-user")
+                    .long("username")
+                    .value_name("name")
+                    .help("Hacker News account of the selected profile. Overrides HN_USERNAME and the config file")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("credentials")
+                    .long("credentials")
+                    .value_name("store")
+                    .help("Where the session of hn login is kept. Overrides HN_CREDENTIALS and the config file")
+                    .possible_values(&config::CREDENTIAL_STORES)
+                    .global(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("hn-url")
+                    .long("hn-url")
+                    .value_name("url")
+                    .help("Base URL of the Hacker News website, for commands scraping it or acting as the logged in user")
+                    .env("HN_URL")
+                    .global(true)
+                    .takes_value(true),
+            )
             .subcommand(News::parser())
             .subcommand(Query::parser())
-        // .subcommand(Tree::parser())
-        // .subcommand(Login::parser())
-        // .subcommand(Thread::parser())
+            .subcommand(Search::parser())
+            .subcommand(Discussions::parser())
+            .subcommand(SyncCmd::parser())
+            .subcommand(Login::parser())
+            .subcommand(Logout::parser())
+            .subcommand(Vote::parser())
+            .subcommand(Fave::parser())
+            .subcommand(Hide::parser())
+            .subcommand(Reply::parser())
+            .subcommand(Edit::parser())
+            .subcommand(Delete::parser())
+            .subcommand(Submit::parser())
+            .subcommand(Me::parser())
+            .subcommand(Replies::parser())
+            .subcommand(Alerts::parser())
+            .subcommand(User::parser())
+            .subcommand(Config::parser())
+            .subcommand(Thread::parser())
+            .subcommand(Tree::parser())
+            .subcommand(Completions::parser())
+            .subcommand(Complete::parser())
     }
 
     async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
         match matches.subcommand() {
-            (Query::NAME, Some(matches)) => Query::cmd(matches).await.map_err(|err| {
-                tracing::error!(err=?err, cmd=Query::NAME, "subcommand failed");
-                Box::new(HnError::Unknown)
-            }),
-            // (Tree::NAME, Some(matches)) => Tree::cmd(matches).map_err(|e| {
-            //     log::error!("hackernews subcommand {:?} failed", Tree::NAME);
-            //     e
-            // }),
-            // (Login::NAME, Some(matches)) => Login::cmd(matches).map_err(|e| {
-            //     log::error!("hackernews subcommand {:?} failed", Login::NAME);
-            //     e
-            // }),
-            // (Thread::NAME, Some(matches)) => Thread::cmd(matches).map_err(|e| {
-            //     log::error!("hackernews subcommand {:?} failed", Thread::NAME);
-            //     e
-            // }),
-            // // Lack of a subcommand defaults to listing the current HN front page
-            // (News::NAME, Some(matches)) => News::cmd(matches).await,
+            (Query::NAME, Some(matches)) => Query::cmd(matches).await,
+            (Search::NAME, Some(matches)) => Search::cmd(matches).await,
+            (Discussions::NAME, Some(matches)) => Discussions::cmd(matches).await,
+            (SyncCmd::NAME, Some(matches)) => SyncCmd::cmd(matches).await,
+            (Login::NAME, Some(matches)) => Login::cmd(matches).await,
+            (Logout::NAME, Some(matches)) => Logout::cmd(matches).await,
+            (Vote::NAME, Some(matches)) => Vote::cmd(matches).await,
+            (Fave::NAME, Some(matches)) => Fave::cmd(matches).await,
+            (Hide::NAME, Some(matches)) => Hide::cmd(matches).await,
+            (Reply::NAME, Some(matches)) => Reply::cmd(matches).await,
+            (Edit::NAME, Some(matches)) => Edit::cmd(matches).await,
+            (Delete::NAME, Some(matches)) => Delete::cmd(matches).await,
+            (Submit::NAME, Some(matches)) => Submit::cmd(matches).await,
+            (Me::NAME, Some(matches)) => Me::cmd(matches).await,
+            (Replies::NAME, Some(matches)) => Replies::cmd(matches).await,
+            (Alerts::NAME, Some(matches)) => Alerts::cmd(matches).await,
+            (User::NAME, Some(matches)) => User::cmd(matches).await,
+            (Config::NAME, Some(matches)) => Config::cmd(matches).await,
+            (Thread::NAME, Some(matches)) => Thread::cmd(matches).await,
+            (Tree::NAME, Some(matches)) => Tree::cmd(matches).await,
+            (Completions::NAME, Some(matches)) => Completions::cmd(matches).await,
+            (Complete::NAME, Some(matches)) => Complete::cmd(matches).await,
+            (News::NAME, Some(matches)) => News::cmd(matches).await,
+            // Lack of a subcommand defaults to listing the current HN front page
             (_, _) => News::cmd(matches).await,
         }
     }
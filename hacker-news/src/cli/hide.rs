@@ -0,0 +1,49 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Hide items from your listings, or with --un show them again.
+pub struct Hide;
+
+impl HnCommand for Hide {
+    const NAME: &'static str = "hide";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Hide items from your listings, or with --un show them again, as the logged in user")
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Ids or item URLs of the items, or - to read them from stdin")
+                    .required(true)
+                    .multiple(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("un")
+                    .long("un")
+                    .help("Undo the hide"),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let ids = super::ids::ids(matches, "id")?;
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        for id in ids {
+            if matches.is_present("un") {
+                client.unhide(id).await
+            } else {
+                client.hide(id).await
+            }
+            .map_err(|err| super::lib_err(err).context(format!("could not hide item {}", id)))?;
+        }
+
+        Ok(())
+    }
+}
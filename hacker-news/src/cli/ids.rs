@@ -0,0 +1,161 @@
+//! Parsing of the item ids and usernames given to subcommands.
+//!
+//! An item id may be given as:
+//!
+//! * a bare id, `8863`, or a comment anchor, `#8863`
+//! * an item URL, `https://news.ycombinator.com/item?id=8863`, where an
+//!   anchor such as `item?id=8863#8952` selects the comment
+//! * `-`, reading whitespace separated ids in any of the above forms from
+//!   stdin
+//!
+//! A username may be given bare, as a user URL such as
+//! `https://news.ycombinator.com/user?id=pg`, or as `-` reading it from stdin.
+
+use clap::ArgMatches;
+use reqwest::Url;
+use std::io::Read;
+
+use crate::api::Id;
+use crate::error::HnError;
+
+fn id_err() -> Box<HnError> {
+    Box::new(HnError::ArgumentError(Some(
+        "id must be a number, #number or an item URL such as https://news.ycombinator.com/item?id=1",
+    )))
+}
+
+/// Parse a URL, allowing the scheme to be left out.
+fn url(arg: &str) -> Option<Url> {
+    Url::parse(arg)
+        .or_else(|_| Url::parse(&format!("https://{}", arg)))
+        .ok()
+        .filter(|url| url.host_str().is_some())
+}
+
+/// The value of the `id` query parameter of a URL on `path`.
+fn query_id(url: &Url, paths: &[&str]) -> Option<String> {
+    if !paths.contains(&url.path()) {
+        return None;
+    }
+    url.query_pairs()
+        .find(|(key, _)| key == "id")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Parse an item id in any of the forms of the module documentation, except
+/// `-`.
+pub(crate) fn parse_id(arg: &str) -> Result<Id, Box<HnError>> {
+    let arg = arg.trim();
+    if let Ok(id) = arg.trim_start_matches('#').parse() {
+        return Ok(id);
+    }
+    let url = url(arg).ok_or_else(id_err)?;
+    // An anchor points at a comment of the item's page.
+    if let Some(id) = url.fragment().and_then(|anchor| anchor.parse().ok()) {
+        return Ok(id);
+    }
+
+    query_id(&url, &["/item"])
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(id_err)
+}
+
+/// Parse a username, bare or in a user URL.
+pub(crate) fn parse_username(arg: &str) -> Result<String, Box<HnError>> {
+    let arg = arg.trim();
+    if !arg.contains('/') {
+        return Ok(arg.to_string());
+    }
+
+    url(arg)
+        .and_then(|url| query_id(&url, &["/user", "/submitted", "/threads", "/favorites"]))
+        .filter(|username| !username.is_empty())
+        .ok_or_else(|| {
+            Box::new(HnError::ArgumentError(Some(
+                "username must be a name or a user URL such as https://news.ycombinator.com/user?id=pg",
+            )))
+        })
+}
+
+fn stdin() -> Result<String, Box<HnError>> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|_| HnError::ArgumentError(Some("failed to read ids from stdin")))?;
+
+    Ok(input)
+}
+
+/// The item ids of the argument `name`, in order, with `-` replaced by the
+/// ids read from stdin.
+pub(crate) fn ids(matches: &ArgMatches, name: &str) -> Result<Vec<Id>, Box<HnError>> {
+    let mut ids = vec![];
+    for arg in matches.values_of(name).into_iter().flatten() {
+        if arg == "-" {
+            for arg in stdin()?.split_whitespace() {
+                ids.push(parse_id(arg)?);
+            }
+        } else {
+            ids.push(parse_id(arg)?);
+        }
+    }
+
+    Ok(ids)
+}
+
+/// The item id of the argument `name`. With `-` the first id on stdin.
+pub(crate) fn id(matches: &ArgMatches, name: &str) -> Result<Id, Box<HnError>> {
+    ids(matches, name)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Box::new(HnError::ArgumentError(Some("id argument not supplied"))))
+}
+
+/// The username of the argument `name`. With `-` the first word on stdin.
+pub(crate) fn username(matches: &ArgMatches, name: &str) -> Result<Option<String>, Box<HnError>> {
+    match matches.value_of(name) {
+        Some("-") => {
+            let input = stdin()?;
+            let arg = input
+                .split_whitespace()
+                .next()
+                .ok_or(HnError::ArgumentError(Some("no username on stdin")))?;
+            parse_username(arg).map(Some)
+        }
+        Some(arg) => parse_username(arg).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{parse_id, parse_username};
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(parse_id("8863").unwrap(), 8863);
+        assert_eq!(parse_id("#8952").unwrap(), 8952);
+        assert_eq!(
+            parse_id("https://news.ycombinator.com/item?id=8863").unwrap(),
+            8863
+        );
+        assert_eq!(
+            parse_id("news.ycombinator.com/item?id=8863#8952").unwrap(),
+            8952
+        );
+        assert!(parse_id("https://news.ycombinator.com/user?id=pg").is_err());
+        assert!(parse_id("pg").is_err());
+
+        assert_eq!(parse_username("pg").unwrap(), "pg");
+        assert_eq!(
+            parse_username("https://news.ycombinator.com/user?id=pg").unwrap(),
+            "pg"
+        );
+        assert_eq!(
+            parse_username("https://news.ycombinator.com/threads?id=dang").unwrap(),
+            "dang"
+        );
+        assert!(parse_username("https://news.ycombinator.com/item?id=1").is_err());
+    }
+}
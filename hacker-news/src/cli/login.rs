@@ -1,54 +1,76 @@
-use crate::client::Client;
 use clap::App;
-use clap::SubCommand;
 use clap::Arg;
 use clap::ArgMatches;
+use clap::SubCommand;
+use std::io::BufRead;
 
 use crate::cli::HnCommand;
 use crate::error::HnError;
 
-/// Login with a given username and password
+/// Login with a given username, prompting for the password.
+pub struct Login;
 
+impl Login {
+    fn password(matches: &ArgMatches) -> Result<String, Box<HnError>> {
+        let password = if matches.is_present("password-stdin") {
+            let mut line = String::new();
+            std::io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|_| HnError::ArgumentError(Some("failed to read password from stdin")))?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        } else {
+            rpassword::prompt_password("Password: ")
+                .map_err(|_| HnError::ArgumentError(Some("failed to read password")))?
+        };
+        if password.is_empty() {
+            return Err(Box::new(HnError::ArgumentError(Some("password is empty"))));
+        }
 
-pub struct Login;
+        Ok(password)
+    }
+}
 
 impl HnCommand for Login {
     const NAME: &'static str = "login";
 
     fn parser<'a, 'b>() -> App<'a, 'b> {
         SubCommand::with_name(Self::NAME)
+            .about("Log in to Hacker News and save the session for later commands")
             .arg(
                 Arg::with_name("username")
                     .value_name("username")
-                    .required(true)
-                    .takes_value(true)
-                    // .min_values(1),
+                    .help("Account to log in to. Defaults to the username of the selected profile")
+                    .takes_value(true),
             )
-            // TODO: Ideally this should be a prompted input with no display
             .arg(
-                Arg::with_name("password")
-                    .value_name("password")
-                    .required(true)
-                    .takes_value(true)
-                    // .min_values(1),
+                Arg::with_name("password-stdin")
+                    .long("password-stdin")
+                    .help("Read the password from the first line of stdin instead of prompting"),
             )
     }
 
-    fn cmd(matches: &ArgMatches) -> Result<(), Box<HnError>> {
-        let username = matches
-            .value_of("username")
-            .ok_or(Box::new(HnError::ArgumentError(Some("username not received"))))?;
-        let password = matches
-            .value_of("password")
-            .ok_or(Box::new(HnError::ArgumentError(Some("password not received"))))?;
-        
-        let client = Client::new();
-        client.login(username, password)
-            .map_err(|_src| HnError::AuthenticationError)?;
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        let username = super::ids::username(matches, "username")?
+            .or(settings.username.clone())
+            .ok_or(HnError::ArgumentError(Some(
+                "no username given, and the selected profile has none",
+            )))?;
+        let username = username.as_str();
+        let password = Self::password(matches)?;
+
+        let mut client = super::html_client(matches, &settings)?;
+        let session = client
+            .login(username, &password)
+            .await
+            .map_err(super::lib_err)?;
+        let store = settings.credential_store()?;
+        session
+            .save(store.as_ref(), settings.account())
+            .map_err(|err| HnError::StorageError(Some(err)))?;
+        println!("Logged in as {}", session.username);
 
         Ok(())
     }
-
 }
-
-    
@@ -0,0 +1,32 @@
+use clap::App;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::auth::AuthSession;
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Forget the saved login session.
+pub struct Logout;
+
+impl HnCommand for Logout {
+    const NAME: &'static str = "logout";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME).about("Remove the session saved by `hn login`")
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        let store = settings.credential_store()?;
+        let removed = AuthSession::remove(store.as_ref(), settings.account())
+            .map_err(|err| HnError::StorageError(Some(err)))?;
+        if removed {
+            println!("Logged out");
+        } else {
+            println!("Not logged in");
+        }
+
+        Ok(())
+    }
+}
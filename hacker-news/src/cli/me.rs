@@ -0,0 +1,131 @@
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::api::{Id, Item};
+use crate::cli::output::{item_record, Output};
+use crate::cli::HnCommand;
+use crate::client::html_client::Listed;
+use crate::error::HnError;
+use crate::util::now;
+
+/// List a user's favorites, upvotes, hidden stories and replies.
+pub struct Me;
+
+impl Me {
+    fn pages_arg<'a, 'b>() -> Arg<'a, 'b> {
+        Arg::with_name("pages")
+            .long("pages")
+            .value_name("N")
+            .help("Maximum number of pages to fetch, 30 items each")
+            .default_value("10")
+            .takes_value(true)
+    }
+
+    fn comments_arg<'a, 'b>() -> Arg<'a, 'b> {
+        Arg::with_name("comments")
+            .long("comments")
+            .help("List comments instead of stories")
+    }
+
+    fn listed(matches: &ArgMatches) -> Listed {
+        if matches.is_present("comments") {
+            Listed::Comments
+        } else {
+            Listed::Stories
+        }
+    }
+}
+
+impl HnCommand for Me {
+    const NAME: &'static str = "me";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("List your favorites, upvotes, hidden stories or replies")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .arg(
+                Arg::with_name("scraped")
+                    .long("scraped")
+                    .help("Print the items as scraped, instead of loading them from --source")
+                    .global(true),
+            )
+            .subcommand(
+                SubCommand::with_name("favorites")
+                    .about("Items marked as favorite. Public, so any --user may be given")
+                    .arg(
+                        Arg::with_name("user")
+                            .long("user")
+                            .value_name("username")
+                            .help("Whose favorites to list. Defaults to the logged in user")
+                            .takes_value(true),
+                    )
+                    .arg(Self::comments_arg())
+                    .arg(Self::pages_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("upvoted")
+                    .about("Items you upvoted")
+                    .arg(Self::comments_arg())
+                    .arg(Self::pages_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("hidden")
+                    .about("Stories you hid")
+                    .arg(Self::pages_arg()),
+            )
+            .subcommand(
+                SubCommand::with_name("replies")
+                    .about("Replies to your comments")
+                    .arg(Self::pages_arg()),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        let (name, sub) = match matches.subcommand() {
+            (name, Some(sub)) => (name, sub),
+            _ => unreachable!("clap requires a subcommand"),
+        };
+        let pages: u32 = sub
+            .value_of("pages")
+            .unwrap_or("10")
+            .parse()
+            .map_err(|_| HnError::ArgumentError(Some("pages must be a number")))?;
+
+        let items = match name {
+            "favorites" => {
+                let username = match sub.value_of("user") {
+                    Some(user) => user.to_string(),
+                    None => client.require_session()?.username.clone(),
+                };
+                client.favorites(&username, Self::listed(sub), pages).await
+            }
+            "upvoted" => client.upvoted(Self::listed(sub), pages).await,
+            "hidden" => client.hidden(pages).await,
+            "replies" => client.replies(pages).await,
+            _ => unreachable!("clap only accepts known subcommands"),
+        }
+        .map_err(super::lib_err)?;
+
+        // Scraped items lack fields such as the text of stories and the ids of
+        // replies, so load the complete items unless asked not to.
+        let items: Vec<Item> = if sub.is_present("scraped") {
+            items
+        } else {
+            let ids: Vec<Id> = items.iter().map(|item| item.id()).collect();
+            let source = super::source(&settings)?;
+            source.get_items(&ids).await.map_err(super::lib_err)?
+        };
+        let mut output = Output::new(matches, &settings)?;
+        let now = now();
+        for item in items.iter() {
+            output.print(item_record(item, now))?;
+        }
+
+        output.flush()
+    }
+}
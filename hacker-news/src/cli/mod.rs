@@ -1,14 +1,47 @@
 use clap::App;
 use clap::ArgMatches;
+use std::env;
+use std::error::Error;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Arc;
+use termcolor::ColorChoice;
 
+use crate::auth::AuthSession;
+use crate::client::html_client;
+use crate::config::{Flags, PartialSettings, Settings};
 use crate::error::HnError;
+use crate::render::Terminal;
+use crate::source::ItemSource;
+use crate::store::Store;
 
-// pub(crate) mod login;
+pub(crate) mod alerts;
+pub(crate) mod complete;
+pub(crate) mod completions;
+pub(crate) mod config;
+pub(crate) mod delete;
+pub(crate) mod discussions;
+pub(crate) mod edit;
+pub(crate) mod fave;
+pub mod hacker_news;
+pub(crate) mod hide;
+pub(crate) mod ids;
+pub(crate) mod login;
+pub(crate) mod logout;
+pub(crate) mod me;
 pub(crate) mod news;
+pub(crate) mod output;
+pub(crate) mod pager;
 pub(crate) mod query;
-// pub(crate) mod thread;
-// pub(crate) mod tree;
-pub mod hacker_news;
+pub(crate) mod replies;
+pub(crate) mod reply;
+pub(crate) mod search;
+pub(crate) mod submit;
+pub(crate) mod sync;
+pub(crate) mod thread;
+pub(crate) mod tree;
+pub(crate) mod user;
+pub(crate) mod vote;
 
 /// A trait defining the interface to add a subcommand to the command line
 /// application.
@@ -26,3 +59,198 @@ pub trait HnCommand {
     // fn cmd(matches: &ArgMatches) -> Result<(), Box<dyn Error>>;
     async fn cmd(matches: &ArgMatches) -> Result<(), Box<HnError>>;
 }
+
+/// Parse the number given to the argument `name`, if any, failing with `err`
+/// when it is not one.
+pub(crate) fn number<T: std::str::FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    err: &'static str,
+) -> Result<Option<T>, Box<HnError>> {
+    matches
+        .value_of(name)
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| Box::new(HnError::ArgumentError(Some(err))))
+}
+
+/// Resolve the [Settings] of the config file selected by the global
+/// `--config` argument, the environment and the global flags.
+///
+/// Commands resolve them once, and pass them to [source], [html_client],
+/// [terminal] and [output::Output::new].
+pub(crate) fn settings(matches: &ArgMatches) -> Result<Settings, Box<HnError>> {
+    let flags = Flags {
+        config: matches.value_of("config").map(PathBuf::from),
+        profile: matches.value_of("profile").map(str::to_string),
+        settings: PartialSettings {
+            list: matches.value_of("list").map(str::to_string),
+            count: number(matches, "count", "count must be a number")?,
+            format: matches.value_of("format").map(str::to_string),
+            color: matches.value_of("color").map(str::to_string),
+            concurrency: number(matches, "concurrency", "concurrency must be a number")?,
+            rate_limit: number(matches, "rate-limit", "rate limit must be a number")?,
+            source: matches.value_of("source").map(str::to_string),
+            data_dir: matches.value_of("data-dir").map(PathBuf::from),
+            cache_dir: matches.value_of("cache-dir").map(PathBuf::from),
+            username: matches.value_of("as-user").map(str::to_string),
+            credentials: matches.value_of("credentials").map(str::to_string),
+        },
+    };
+
+    Settings::load(&flags).map_err(|err| match err.downcast::<HnError>() {
+        Ok(err) => err,
+        Err(err) => Box::new(HnError::StorageError(Some(err))),
+    })
+}
+
+/// Whether to color a stream, from the `color` setting. `auto` colors only a
+/// terminal, and not when `NO_COLOR` is set.
+fn use_color(color: &str, is_terminal: bool) -> bool {
+    match color {
+        "always" => true,
+        "never" => false,
+        _ => is_terminal && env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty()),
+    }
+}
+
+/// How to color what is printed to stderr.
+pub fn color_choice(matches: &ArgMatches) -> ColorChoice {
+    // Global arguments are only seen by the matches of the subcommand.
+    let matches = match matches.subcommand() {
+        (_, Some(matches)) => matches,
+        (_, None) => matches,
+    };
+
+    // The settings may be what failed, so fall back to the flag alone.
+    let color = match settings(matches) {
+        Ok(settings) => settings.color,
+        Err(_) => matches.value_of("color").unwrap_or("auto").to_string(),
+    };
+
+    match use_color(&color, io::stderr().is_terminal()) {
+        true => ColorChoice::Always,
+        false => ColorChoice::Never,
+    }
+}
+
+/// The [Terminal] to render item text printed to stdout with, styled when
+/// stdout is colored.
+pub(crate) fn terminal(settings: &Settings) -> Terminal {
+    Terminal::new(
+        Terminal::stdout_width(),
+        use_color(&settings.color, io::stdout().is_terminal()),
+    )
+}
+
+/// Build the [ItemSource] selected by the `source` setting, falling back to
+/// the Hacker News API.
+pub(crate) fn source(settings: &Settings) -> Result<Arc<dyn ItemSource>, Box<HnError>> {
+    let source = settings
+        .source_spec()
+        .build_with_client(settings.client())
+        .map_err(|err| HnError::StorageError(Some(err)))?;
+
+    Ok(source)
+}
+
+/// Build the HTML client for the site at the global `--hn-url`, authenticated
+/// with the session `hn login` saved for the selected profile if there is one.
+pub(crate) fn html_client(
+    matches: &ArgMatches,
+    settings: &Settings,
+) -> Result<html_client::Client, Box<HnError>> {
+    let client = match matches.value_of("hn-url") {
+        Some(url) => html_client::Client::with_base_url(url),
+        None => html_client::Client::new(),
+    };
+    let store = settings.credential_store()?;
+    let session = AuthSession::load(store.as_ref(), settings.account())
+        .map_err(|err| HnError::StorageError(Some(err)))?;
+
+    Ok(match session {
+        Some(session) => client.with_session(session),
+        None => client,
+    })
+}
+
+/// Remember the users a command showed in the local store, so their names
+/// are completed. Failing to only costs completions, so errors are logged.
+pub(crate) fn remember_users<'a>(settings: &Settings, names: impl IntoIterator<Item = &'a str>) {
+    let result =
+        Store::open(settings.db_path()).and_then(|mut store| store.record_seen_users(names));
+    if let Err(err) = result {
+        tracing::debug!(err = %err, "could not remember the users seen");
+    }
+}
+
+/// Recover the [HnError] of a failed library call, classifying the errors of
+/// the libraries it uses: reqwest errors as network failures, JSON errors as
+/// data which could not be parsed, and IO errors as local storage failures.
+pub(crate) fn lib_err(err: Box<dyn Error>) -> Box<HnError> {
+    let err = match err.downcast::<HnError>() {
+        Ok(err) => return err,
+        Err(err) => err,
+    };
+    let err = match err.downcast::<reqwest::Error>() {
+        Ok(err) if err.is_decode() => {
+            return Box::new(HnError::SerializationError(None)).context(err.to_string())
+        }
+        Ok(err) => return Box::new(HnError::NetworkError(Some(err))),
+        Err(err) => err,
+    };
+    let err = match err.downcast::<serde_json::Error>() {
+        Ok(err) => return Box::new(HnError::SerializationError(None)).context(err.to_string()),
+        Err(err) => err,
+    };
+    match err.downcast::<io::Error>() {
+        Ok(err) => Box::new(HnError::StorageError(Some(err))),
+        Err(err) => Box::new(HnError::Unknown).context(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::hacker_news::HackerNews;
+    use super::HnCommand;
+    use crate::api::StoryList;
+    use crate::config::Origin;
+    use std::path::Path;
+
+    #[test]
+    fn test_settings_from_flags() {
+        let matches = HackerNews::parser().get_matches_from(vec![
+            "hn",
+            "news",
+            "--config",
+            "/nonexistent/config.toml",
+            "--list",
+            "best",
+            "--count",
+            "5",
+            "--rate-limit",
+            "0",
+            "--data-dir",
+            "/tmp/hn",
+            "--username",
+            "pg",
+        ]);
+        let (_, Some(matches)) = matches.subcommand() else {
+            panic!("no subcommand");
+        };
+        let settings = super::settings(matches).unwrap();
+        assert_eq!(settings.list, StoryList::Best);
+        assert_eq!(settings.count, 5);
+        assert_eq!(settings.rate_limit, 0);
+        assert_eq!(settings.data_dir, Path::new("/tmp/hn"));
+        assert_eq!(settings.username.as_deref(), Some("pg"));
+        assert_eq!(settings.origin("username"), Origin::Flag("username"));
+
+        let matches = HackerNews::parser().get_matches_from(vec!["hn", "news", "--count", "x"]);
+        let (_, Some(matches)) = matches.subcommand() else {
+            panic!("no subcommand");
+        };
+        assert_eq!(super::settings(matches).unwrap_err().exit_code(), 2);
+    }
+}
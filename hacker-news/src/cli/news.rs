@@ -1,48 +1,109 @@
-use crate::client::Client;
 use clap::App;
+use clap::Arg;
 use clap::ArgMatches;
 use clap::SubCommand;
 
+use crate::api::derived::Listing;
+use crate::cli::output::{Output, Record};
 use crate::cli::HnCommand;
 use crate::error::HnError;
-
-// TODO: Should put item -> story conversion within Client code
-use crate::api::{Item, Story};
+use crate::util::{format_age, now};
 
 /// Get front page listings of Hacker News.
 pub struct News;
 
+impl News {
+    fn listing_record(rank: usize, listing: &Listing, now: u64) -> Record {
+        Record::new()
+            .field("rank", rank)
+            .field("id", listing.id)
+            .field("score", listing.score)
+            .field("comments", listing.comments())
+            .field("age", format_age(listing.time, now))
+            .field("domain", listing.domain())
+            .field("title", listing.title.as_str())
+            .field("by", listing.by.as_deref())
+            .detail("time", listing.time)
+            .detail("url", listing.url.as_deref())
+    }
+}
+
 impl HnCommand for News {
     const NAME: &'static str = "news";
 
     fn parser<'a, 'b>() -> App<'a, 'b> {
         SubCommand::with_name(Self::NAME)
+            .about("List stories with their rank, score, comment count, age and domain")
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .short("n")
+                    .value_name("N")
+                    .help("Number of stories to show. Defaults to the count setting, or 30")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("page")
+                    .long("page")
+                    .value_name("P")
+                    .help("One-based page of --limit stories to show")
+                    .conflicts_with("offset")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("offset")
+                    .long("offset")
+                    .value_name("N")
+                    .help("Number of stories to skip")
+                    .takes_value(true),
+            )
     }
 
-    async fn cmd(_matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
-        let hn_client = Client::new();
-        let top = hn_client.top_stories().await.unwrap();
-        let stories: Vec<Story> = hn_client
-            .items(&top[..30])
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        let list = settings.list;
+        let limit =
+            super::number(matches, "limit", "limit must be a number")?.unwrap_or(settings.count);
+        let offset = match super::number::<usize>(matches, "page", "page must be a number")? {
+            Some(0) => return Err(Box::new(HnError::ArgumentError(Some("page starts at 1")))),
+            Some(page) => (page - 1)
+                .checked_mul(limit)
+                .ok_or(HnError::ArgumentError(Some(
+                    "page is too large for the limit",
+                )))?,
+            None => super::number(matches, "offset", "offset must be a number")?.unwrap_or(0),
+        };
+
+        let source = crate::cli::source(&settings)?;
+        let ids = source
+            .list_ids(list)
             .await
-            .unwrap()
-            .into_iter()
-            .filter_map(|item| match item {
-                Item::Story(story) => Some(story),
-                _ => None,
-            })
-            .collect();
-        tracing::debug!("stories: {:?}", stories);
-
-        for story in stories {
-            println!(
-                "{id}|{title}|{by}",
-                id = story.id,
-                title = story.title,
-                by = story.by.unwrap()
-            );
+            .map_err(|err| {
+                crate::cli::lib_err(err)
+                    .context(format!("could not load the {} stories", list.as_str()))
+            })?
+            .ok_or_else(|| {
+                HnError::NotFoundError(format!("{} stories in the source", list.as_str()))
+            })?;
+        let start = offset.min(ids.len());
+        let end = start.saturating_add(limit).min(ids.len());
+        let items = source.get_items(&ids[start..end]).await.map_err(|err| {
+            crate::cli::lib_err(err)
+                .context(format!("could not load the {} stories", list.as_str()))
+        })?;
+        tracing::debug!("items: {:?}", items);
+
+        let mut output = Output::new(matches, &settings)?;
+        let now = now();
+        for (rank, item) in (start + 1..).zip(items) {
+            // Lists only hold stories, jobs and polls, but skip anything else
+            // rather than failing the whole page.
+            match Listing::try_from(item) {
+                Ok(listing) => output.print(Self::listing_record(rank, &listing, now))?,
+                Err(err) => tracing::warn!(err = %err, "skipping unlisted item"),
+            }
         }
 
-        Ok(())
+        output.flush()
     }
 }
@@ -0,0 +1,400 @@
+//! The output layer shared by every subcommand printing records.
+//!
+//! A subcommand builds a [Record] per result and hands it to an [Output],
+//! which prints it in the format selected by the global `--format` flag, the
+//! `format` setting or `HN_FORMAT`:
+//!
+//! * `table`, aligned columns under a header, for people
+//! * `json`, a single array of objects
+//! * `ndjson`, one object per line, printed as soon as it is known
+//! * `csv` and `tsv`, a header and one row per record
+//!
+//! A `--template` such as `'{rank}. {title} ({domain})'` overrides the format
+//! and prints each record by filling in its fields.
+
+use clap::ArgMatches;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
+use std::io::{self, Write};
+
+use crate::api::Item;
+use crate::config::Settings;
+use crate::error::HnError;
+use crate::render::to_plain;
+use crate::util::format_age;
+
+/// Named fields, in column order.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Record {
+    fields: Vec<(&'static str, Value)>,
+    /// Fields left out of tables and delimited formats, i.e. full URLs and
+    /// Unix Times, which are still printed as JSON and usable in templates.
+    details: Vec<(&'static str, Value)>,
+    /// Nesting depth, shown as indentation of the first column of a table.
+    indent: usize,
+}
+
+impl Record {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a field. `None` is printed as an empty cell, or `null` in JSON.
+    pub(crate) fn field(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push((name, value.into()));
+        self
+    }
+
+    /// Add a field which is only printed as JSON or through a template.
+    pub(crate) fn detail(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        self.details.push((name, value.into()));
+        self
+    }
+
+    pub(crate) fn indent(mut self, depth: usize) -> Self {
+        self.indent = depth;
+        self
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        self.fields.iter().map(|(name, _)| *name).collect()
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .chain(self.details.iter())
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// The fields as single line text, for table and delimited formats.
+    fn cells(&self) -> Vec<String> {
+        self.fields.iter().map(|(_, value)| plain(value)).collect()
+    }
+}
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len() + self.details.len()))?;
+        for (name, value) in self.fields.iter().chain(self.details.iter()) {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+/// A value as text on a single line, with whitespace runs collapsed.
+fn plain(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(plain).collect::<Vec<_>>().join(","),
+        value => value.to_string(),
+    };
+    if text.contains(char::is_control) {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    } else {
+        text
+    }
+}
+
+/// The record of an [Item]: its id, type, author, age, score, title and
+/// text as plain text.
+pub(crate) fn item_record(item: &Item, now: u64) -> Record {
+    let url = match item {
+        Item::Story(story) => story.url.clone(),
+        Item::Job(job) => job.url.clone(),
+        _ => None,
+    };
+    Record::new()
+        .field("id", item.id())
+        .field("type", item.kind())
+        .field("by", item.by())
+        .field("age", format_age(item.time(), now))
+        .field("score", item.score())
+        .field("title", item.title())
+        .field("text", item.text().map(to_plain))
+        .detail("time", item.time())
+        .detail("url", url)
+}
+
+/// Escape a TSV cell, so tabs and newlines can not break the columns.
+fn tsv_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Format {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+    Template(String),
+}
+
+/// Fill in the `{field}` placeholders of `template`. `{{` and `}}` are
+/// literal braces, and `\t` and `\n` a tab and a newline.
+fn render_template(template: &str, record: &Record) -> Result<String, Box<HnError>> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let value = record.get(name.trim()).ok_or(HnError::ArgumentError(Some(
+                    "template names a field the command does not print",
+                )))?;
+                out.push_str(&plain(value));
+            }
+            '\\' if chars.peek() == Some(&'t') => {
+                chars.next();
+                out.push('\t');
+            }
+            '\\' if chars.peek() == Some(&'n') => {
+                chars.next();
+                out.push('\n');
+            }
+            c => out.push(c),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Prints [Record]s to stdout in the selected format.
+pub(crate) struct Output {
+    format: Format,
+    /// Records held back until [Output::flush], for formats which need to
+    /// see every record first.
+    pending: Vec<Record>,
+    header_written: bool,
+}
+
+/// Classify a failure to write the output as a failure of local IO. The
+/// reader closing the pipe, i.e. `hn news | head -1`, is told apart with
+/// [HnError::is_broken_pipe] and ends the output without an error.
+pub(crate) fn write_err(err: io::Error) -> Box<HnError> {
+    super::lib_err(Box::new(err)).context("failed to write output")
+}
+
+fn csv_err(err: csv::Error) -> Box<HnError> {
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => write_err(err),
+        _ => Box::new(HnError::SerializationError(Some("failed to write csv"))),
+    }
+}
+
+impl Output {
+    /// The output selected by the global `--template` flag, or else the
+    /// `format` setting.
+    pub(crate) fn new(matches: &ArgMatches, settings: &Settings) -> Result<Self, Box<HnError>> {
+        let format = match matches.value_of("template") {
+            Some(template) => Format::Template(template.to_string()),
+            None => match settings.format.as_str() {
+                "json" => Format::Json,
+                "ndjson" => Format::Ndjson,
+                "csv" => Format::Csv,
+                "tsv" => Format::Tsv,
+                _ => Format::Table,
+            },
+        };
+
+        Ok(Self {
+            format,
+            pending: vec![],
+            header_written: false,
+        })
+    }
+
+    /// Print records as soon as they are known, for commands which run until
+    /// interrupted. JSON is printed as NDJSON instead.
+    pub(crate) fn streaming(mut self) -> Self {
+        if self.format == Format::Json {
+            self.format = Format::Ndjson;
+        }
+        self
+    }
+
+    /// Whether records are printed as a table, the format meant for people.
+    pub(crate) fn is_table(&self) -> bool {
+        self.format == Format::Table
+    }
+
+    /// Whether records are printed as a JSON array, in which case commands
+    /// with a nested result may print it with [Output::print_document].
+    pub(crate) fn is_json(&self) -> bool {
+        self.format == Format::Json
+    }
+
+    /// Print `value` as a single JSON document rather than as records.
+    pub(crate) fn print_document(&mut self, value: &impl Serialize) -> Result<(), Box<HnError>> {
+        let json = serde_json::to_string(value)
+            .map_err(|_| HnError::SerializationError(Some("failed to serialize output")))?;
+        writeln!(io::stdout().lock(), "{}", json).map_err(write_err)
+    }
+
+    /// Print a record, or hold it back until [Output::flush].
+    pub(crate) fn print(&mut self, record: Record) -> Result<(), Box<HnError>> {
+        let mut out = io::stdout().lock();
+        match self.format {
+            Format::Table | Format::Json => self.pending.push(record),
+            Format::Ndjson => {
+                let json = serde_json::to_string(&record)
+                    .map_err(|_| HnError::SerializationError(Some("failed to serialize record")))?;
+                writeln!(out, "{}", json).map_err(write_err)?;
+            }
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(out);
+                if !self.header_written {
+                    writer.write_record(record.names()).map_err(csv_err)?;
+                    self.header_written = true;
+                }
+                writer.write_record(record.cells()).map_err(csv_err)?;
+                writer.flush().map_err(write_err)?;
+            }
+            Format::Tsv => {
+                if !self.header_written {
+                    writeln!(out, "{}", record.names().join("\t")).map_err(write_err)?;
+                    self.header_written = true;
+                }
+                let cells: Vec<String> = record.cells().iter().map(|c| tsv_escape(c)).collect();
+                writeln!(out, "{}", cells.join("\t")).map_err(write_err)?;
+            }
+            Format::Template(ref template) => {
+                writeln!(out, "{}", render_template(template, &record)?).map_err(write_err)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Print the table rows held back so far, for commands streaming results
+    /// as they arrive. JSON is still printed as one array by [Output::flush].
+    pub(crate) fn flush_table(&mut self) -> Result<(), Box<HnError>> {
+        match self.format {
+            Format::Table => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Print the records held back so far. Tables are aligned over the
+    /// records of one flush.
+    pub(crate) fn flush(&mut self) -> Result<(), Box<HnError>> {
+        let records = std::mem::take(&mut self.pending);
+        let mut out = io::stdout().lock();
+        match self.format {
+            Format::Json => {
+                let json = serde_json::to_string_pretty(&records).map_err(|_| {
+                    HnError::SerializationError(Some("failed to serialize records"))
+                })?;
+                writeln!(out, "{}", json).map_err(write_err)?;
+            }
+            Format::Table if !records.is_empty() => {
+                let mut rows: Vec<Vec<String>> = records
+                    .iter()
+                    .map(|record| {
+                        let mut cells = record.cells();
+                        if let Some(first) = cells.first_mut() {
+                            *first = format!("{}{}", "  ".repeat(record.indent), first);
+                        }
+                        cells
+                    })
+                    .collect();
+                if !self.header_written {
+                    let header = records[0]
+                        .names()
+                        .iter()
+                        .map(|n| n.to_uppercase())
+                        .collect();
+                    rows.insert(0, header);
+                    self.header_written = true;
+                }
+                for line in table(&rows) {
+                    writeln!(out, "{}", line).map_err(write_err)?;
+                }
+            }
+            _ => {}
+        }
+        out.flush().map_err(write_err)?;
+
+        Ok(())
+    }
+}
+
+/// Lay out rows as columns padded to the widest cell, leaving the last column
+/// unpadded.
+fn table(rows: &[Vec<String>]) -> Vec<String> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                if i + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    line.push_str(&" ".repeat(widths[i] - cell.chars().count() + 2));
+                }
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{plain, render_template, table, tsv_escape, Record};
+    use serde_json::Value;
+
+    #[test]
+    fn test_record_formats() {
+        let record = Record::new()
+            .field("id", 1)
+            .field("by", Value::Null)
+            .field("text", "a | b\nc\td")
+            .detail("time", 1000);
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"id":1,"by":null,"text":"a | b\nc\td","time":1000}"#
+        );
+        assert_eq!(record.cells(), vec!["1", "", "a | b c d"]);
+        assert_eq!(tsv_escape("a\tb\nc\\"), "a\\tb\\nc\\\\");
+        assert_eq!(plain(&Value::from(vec![1, 2])), "1,2");
+
+        assert_eq!(
+            render_template("{id}:{{{ text }}}\\t{time}", &record).unwrap(),
+            "1:{a | b c d}\t1000"
+        );
+        assert!(render_template("{score}", &record).is_err());
+
+        let rows = vec![
+            vec!["ID".to_string(), "TITLE".to_string()],
+            vec!["12345".to_string(), "Hello".to_string()],
+        ];
+        assert_eq!(table(&rows), vec!["ID     TITLE", "12345  Hello"]);
+    }
+}
@@ -0,0 +1,137 @@
+//! Paging of long output, i.e. the comments of a thread, through `$PAGER`.
+
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+use termcolor::{Ansi, ColorChoice, ColorSpec, NoColor, StandardStream, WriteColor};
+
+use crate::cli::output::write_err;
+use crate::error::HnError;
+
+/// Where long output is written: the stdin of a pager, or stdout.
+pub(crate) struct Pager {
+    child: Option<Child>,
+    out: Box<dyn WriteColor>,
+    /// Whether the reader quit the pager, so nothing more needs writing.
+    closed: bool,
+}
+
+impl Pager {
+    /// Pipe output through `$PAGER`, falling back to `less -R`, when `enabled`
+    /// and stdout is a terminal. An empty `$PAGER` disables paging. Output is
+    /// colored when `color`.
+    pub(crate) fn start(enabled: bool, color: bool) -> Self {
+        let mut child = if enabled && io::stdout().is_terminal() {
+            Self::spawn()
+        } else {
+            None
+        };
+
+        let stdin = child.as_mut().and_then(|child| child.stdin.take());
+        let out: Box<dyn WriteColor> = match (stdin, color) {
+            (Some(stdin), true) => Box::new(Ansi::new(stdin)),
+            (Some(stdin), false) => Box::new(NoColor::new(stdin)),
+            (None, true) => Box::new(StandardStream::stdout(ColorChoice::Always)),
+            (None, false) => Box::new(StandardStream::stdout(ColorChoice::Never)),
+        };
+
+        Self {
+            child,
+            out,
+            closed: false,
+        }
+    }
+
+    fn spawn() -> Option<Child> {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+        if pager.trim().is_empty() {
+            return None;
+        }
+        // Run through the shell, like git does, so the pager may carry arguments.
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(&pager)
+            .stdin(Stdio::piped())
+            .spawn();
+        match child {
+            Ok(child) => Some(child),
+            Err(err) => {
+                tracing::warn!(err = %err, pager = %pager, "failed to start the pager");
+                None
+            }
+        }
+    }
+
+    /// Whether the reader quit the pager before all output was written.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Wait for the reader to quit the pager.
+    pub(crate) fn finish(mut self) -> Result<(), Box<HnError>> {
+        self.flush().map_err(write_err)?;
+        // Close the pager's stdin, so it sees the end of the output.
+        self.out = Box::new(NoColor::new(io::sink()));
+        if let Some(mut child) = self.child.take() {
+            child
+                .wait()
+                .map_err(|_| HnError::ArgumentError(Some("failed to wait for the pager")))?;
+        }
+
+        Ok(())
+    }
+
+    /// Treat the reader quitting the pager as the end of the output, rather
+    /// than an error.
+    fn closed_ok<T: Default>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        match result {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                self.closed = true;
+                Ok(T::default())
+            }
+            result => result,
+        }
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed {
+            return Ok(buf.len());
+        }
+        let result = self.out.write(buf);
+        match self.closed_ok(result) {
+            Ok(0) if self.closed => Ok(buf.len()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let result = self.out.flush();
+        self.closed_ok(result)
+    }
+}
+
+impl WriteColor for Pager {
+    fn supports_color(&self) -> bool {
+        self.out.supports_color()
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let result = self.out.set_color(spec);
+        self.closed_ok(result)
+    }
+
+    fn reset(&mut self) -> io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let result = self.out.reset();
+        self.closed_ok(result)
+    }
+}
@@ -4,33 +4,243 @@ use clap::ArgMatches;
 use clap::SubCommand;
 use futures::pin_mut;
 use futures::stream::StreamExt;
+use regex::Regex;
+use std::collections::HashSet;
+use std::io::Write;
+use termcolor::{Color, ColorSpec, WriteColor};
 use tracing::{error, info};
 
-use crate::api::Id;
+use crate::api::{Id, Story};
+use crate::cli::output::{write_err, Output, Record};
+use crate::cli::pager::Pager;
 use crate::cli::HnCommand;
-use crate::client::Client;
 use crate::client::CommentNode;
-use crate::error::HnError;
+use crate::client::{CommentFilter, LazyThread, LoadOptions, Thread};
+use crate::error::{HnError, Style};
+use crate::render::{to_markdown, to_plain, Terminal};
+use crate::util::{format_age, now, parse_since};
 
 pub struct Query;
 
-impl Query {
-    /// Format a [CommentNode] for printing in the terminal as an ASCII thread.
-    fn fmt_comment(cnode: &CommentNode, tree_mode: bool) -> String {
-        let author = cnode.comment.by.as_ref().map(|s| s.as_str()).unwrap_or("");
+/// How comments are printed.
+enum View {
+    /// Comment text word wrapped to the terminal, indented by depth.
+    Reader(Reader),
+    /// A [Record] per comment, with the text as Markdown or plain text.
+    Records { tree_mode: bool, markdown: bool },
+}
+
+impl View {
+    /// Whether the reader quit the pager, so no more comments are needed.
+    fn is_closed(&self) -> bool {
+        match self {
+            View::Reader(reader) => reader.pager.is_closed(),
+            View::Records { .. } => false,
+        }
+    }
+}
+
+/// Readable ANSI 256 colors for author names, leaving out the red, green and
+/// yellow of [Style].
+const AUTHOR_COLORS: [u8; 12] = [33, 39, 45, 69, 75, 99, 105, 135, 141, 171, 177, 208];
+
+/// Prints comments for people: author names colored by user with the OP
+/// highlighted, depth guides, dimmed metadata and word wrapped text.
+struct Reader {
+    terminal: Terminal,
+    pager: Pager,
+    /// The author of the story.
+    op: Option<String>,
+    now: u64,
+}
+
+impl Reader {
+    /// Width of the guide drawn per level of depth.
+    const GUIDE_WIDTH: usize = 2;
+
+    /// The color of an author's name, the same for a user on every run.
+    fn author_spec(name: &str) -> ColorSpec {
+        // FNV-1a, as the hash of std may change between releases
+        let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(Color::Ansi256(
+            AUTHOR_COLORS[(hash % AUTHOR_COLORS.len() as u64) as usize],
+        )))
+        .set_bold(true);
+
+        spec
+    }
+
+    fn styled(&mut self, text: &str, spec: &ColorSpec) -> std::io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.pager.set_color(spec)?;
+        write!(self.pager, "{}", text)?;
+        self.pager.reset()
+    }
+
+    /// Draw the guides of `depth` levels, followed by `line`.
+    fn guided(&mut self, depth: usize, line: &str) -> std::io::Result<()> {
+        let guides = "│ ".repeat(depth);
+        let guides = if line.is_empty() {
+            guides.trim_end()
+        } else {
+            &guides
+        };
+        self.styled(guides, &Style::Meta.spec())?;
+        writeln!(self.pager, "{}", line)
+    }
+
+    /// Print the title and id of a story, above its comments.
+    fn header(&mut self, story: &Story) -> std::io::Result<()> {
+        let mut spec = ColorSpec::new();
+        spec.set_bold(true);
+        self.styled(&story.title, &spec)?;
+        self.styled(&format!(" [{}]", story.id), &Style::Meta.spec())?;
+        writeln!(self.pager)?;
+        writeln!(self.pager)
+    }
+
+    /// Print a comment: its author, age and id, followed by its text and a
+    /// blank line. Dead and deleted comments are collapsed to their header.
+    fn print(&mut self, cnode: &CommentNode) -> std::io::Result<()> {
+        let comment = &cnode.comment;
+        let guides = "│ ".repeat(cnode.depth);
+        self.styled(&guides, &Style::Meta.spec())?;
+        match comment.by {
+            Some(ref by) if !comment.deleted => {
+                self.styled(by, &Self::author_spec(by))?;
+                if self.op.as_deref() == Some(by.as_str()) {
+                    let mut spec = Style::Good.spec();
+                    spec.set_bold(true);
+                    write!(self.pager, " ")?;
+                    self.styled("OP", &spec)?;
+                }
+            }
+            _ => self.styled("[deleted]", &Style::Meta.spec())?,
+        }
+        let mut meta = format!(
+            " {} ago [{}]",
+            format_age(comment.time, self.now),
+            comment.id
+        );
+        if comment.dead {
+            meta.push_str(" [dead]");
+        }
+        if cnode.collapsed > 0 {
+            meta.push_str(&format!(" [{} more replies]", cnode.collapsed));
+        }
+        self.styled(&meta, &Style::Meta.spec())?;
+        writeln!(self.pager)?;
 
-        let indent: String = vec!["  "; cnode.depth].into_iter().collect();
+        if let (Some(ref text), false) = (&comment.text, comment.dead || comment.deleted) {
+            let guides = cnode.depth * Self::GUIDE_WIDTH;
+            let terminal = Terminal {
+                width: self.terminal.width.saturating_sub(guides),
+                ..self.terminal
+            };
+            for line in terminal.render(text, "") {
+                self.guided(cnode.depth, &line)?;
+            }
+        }
+        self.guided(cnode.depth, "")
+    }
+}
 
-        if tree_mode {
-            format!("{}{}|{}", indent, author, cnode.comment.id)
+impl Query {
+    /// The record of a [CommentNode], indented by its depth in tables so the
+    /// thread reads as a tree.
+    fn comment_record(story: Id, cnode: &CommentNode, tree_mode: bool, markdown: bool) -> Record {
+        let record = Record::new()
+            .indent(cnode.depth)
+            .field("by", cnode.comment.by.as_deref())
+            .field("id", cnode.comment.id);
+        let record = if tree_mode {
+            record
         } else {
-            let text = cnode
-                .comment
-                .text
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or("");
-            format!("{}{}|{}|{}", indent, author, cnode.comment.id, text)
+            let render = if markdown { to_markdown } else { to_plain };
+            record.field("text", cnode.comment.text.as_deref().map(render))
+        };
+
+        record
+            .detail("story", story)
+            .detail("depth", cnode.depth)
+            .detail("parent", cnode.comment.parent)
+            .detail("time", cnode.comment.time)
+            .detail("collapsed", cnode.collapsed)
+    }
+
+    /// The limits on loading the thread, from the `--max-depth`,
+    /// `--top-level-only` and `--collapse-after` arguments.
+    fn load_options(matches: &ArgMatches) -> Result<LoadOptions, Box<HnError>> {
+        let max_depth = match matches.is_present("top-level-only") {
+            true => Some(1),
+            false => super::number(matches, "max-depth", "max-depth must be a number")?,
+        };
+
+        Ok(LoadOptions {
+            max_depth,
+            collapse_after: super::number(
+                matches,
+                "collapse-after",
+                "collapse-after must be a number",
+            )?,
+            ..LoadOptions::default()
+        })
+    }
+
+    /// The comments to show, from the `--author`, `--grep`, `--min-replies`,
+    /// `--since` and `--hide-dead` arguments.
+    fn filter(matches: &ArgMatches) -> Result<CommentFilter, Box<HnError>> {
+        let mut filter = CommentFilter::new().with_hide_dead(matches.is_present("hide-dead"));
+        if let Some(author) = matches.value_of("author") {
+            filter = filter.with_author(author);
+        }
+        if let Some(grep) = matches.value_of("grep") {
+            let grep = Regex::new(grep)
+                .map_err(|_| HnError::ArgumentError(Some("grep must be a regular expression")))?;
+            filter = filter.with_grep(grep);
+        }
+        if let Some(min_replies) =
+            super::number(matches, "min-replies", "min-replies must be a number")?
+        {
+            filter = filter.with_min_replies(min_replies);
+        }
+        if let Some(since) = matches.value_of("since") {
+            filter = filter.with_since(parse_since(since)?);
+        }
+
+        Ok(filter)
+    }
+
+    /// Begin printing the thread of `story`, with a header naming it when
+    /// several threads are printed.
+    fn start(view: &mut View, story: &Story, header: bool) -> Result<(), Box<HnError>> {
+        if let View::Reader(ref mut reader) = view {
+            reader.op = story.by.clone();
+            if header {
+                reader.header(story).map_err(write_err)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print(
+        output: &mut Output,
+        view: &mut View,
+        story: Id,
+        cnode: &CommentNode,
+    ) -> Result<(), Box<HnError>> {
+        match *view {
+            View::Reader(ref mut reader) => reader.print(cnode).map_err(write_err),
+            View::Records {
+                tree_mode,
+                markdown,
+            } => output.print(Self::comment_record(story, cnode, tree_mode, markdown)),
         }
     }
 }
@@ -43,9 +253,10 @@ impl HnCommand for Query {
         .arg(
             Arg::with_name("id")
                 .value_name("id")
+                .help("Ids or item URLs of the stories, or - to read them from stdin")
                 .required(true)
                 .takes_value(true)
-                .min_values(1),
+                .multiple(true),
         )
         .arg(
             Arg::with_name("lazy")
@@ -63,21 +274,105 @@ impl HnCommand for Query {
                 .required(false)
                 .takes_value(false)
         )
+        .arg(
+            Arg::with_name("markdown")
+                .long("markdown")
+                .help("Print comment text as Markdown rather than plain text. Comments are printed as records, also with --format table")
+                .conflicts_with("tree")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("Only show this many levels of comments, i.e. 1 for top level comments. Deeper comments are not fetched")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("top-level-only")
+                .long("top-level-only")
+                .help("Only show top level comments, like --max-depth 1")
+                .conflicts_with("max-depth")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("author")
+                .long("author")
+                .value_name("username")
+                .help("Only show comments by this user")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("grep")
+                .long("grep")
+                .value_name("regex")
+                .help("Only show comments with text matching this regular expression, along with the comments they reply to")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("min-replies")
+                .long("min-replies")
+                .value_name("N")
+                .help("Only show comments with at least this many direct replies")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .value_name("time")
+                .help("Only show comments made after this Unix Time, or this long ago, i.e. 12h or 2days")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("hide-dead")
+                .long("hide-dead")
+                .help("Leave out dead and deleted comments")
+                .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("collapse-after")
+                .long("collapse-after")
+                .value_name("N")
+                .help("Only show the first N replies to each comment, counting the rest. Collapsed replies are not fetched")
+                .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("no-pager")
+                .long("no-pager")
+                .help("Print the thread directly, rather than through $PAGER or less -R when stdout is a terminal")
+                .takes_value(false)
+        )
     }
 
     async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
-        // SAFE: The clap App will guarantee required arguments are received
-        let id = matches
-            .value_of("id")
-            .expect("clap failed to produce default value");
-        let id: Id = id
-            .parse()
-            .map_err(|_| HnError::ArgumentError(Some("id argument not parseable as numeric")))?;
-
-        let client = Client::new();
+        let ids = super::ids::ids(matches, "id")?;
+
+        let options = Self::load_options(matches)?;
+        let settings = super::settings(matches)?;
+        let source = super::source(&settings)?;
         let lazy = matches.is_present("lazy");
         let tree_mode = matches.is_present("tree");
         info!(lazy=?lazy, tree_mode=?tree_mode, "query cmd");
+        let mut output = Output::new(matches, &settings)?;
+        // People reading a table get the comments word wrapped instead.
+        let reader = output.is_table() && !tree_mode && !matches.is_present("markdown");
+        let mut view = if reader {
+            let terminal = super::terminal(&settings);
+            View::Reader(Reader {
+                terminal,
+                pager: Pager::start(!matches.is_present("no-pager"), terminal.styled),
+                op: None,
+                now: now(),
+            })
+        } else {
+            View::Records {
+                tree_mode,
+                markdown: matches.is_present("markdown"),
+            }
+        };
+
+        // Authors of the threads, remembered for completing usernames
+        let mut authors = HashSet::new();
 
         // todo:
         //  - attempt to combine the two approaches below
@@ -88,34 +383,113 @@ impl HnCommand for Query {
             // - lazily fetches comments while walking the thread
             // - requires the calls to stream.next() to actually initiate new request I/O
             // - slower to get the entire thread, but faster to yield initial results.
-            let thread = client
-                .lazy_thread(id)
-                .await
-                .expect("failed to create lazy_thread");
-            let stream = thread.walk();
-            pin_mut!(stream);
-            while let Some(result) = stream.next().await {
-                match result {
-                    Ok(cnode) => {
-                        let s = Self::fmt_comment(&cnode, tree_mode);
-                        println!("{}", s);
-                    }
-                    Err(e) => {
-                        error!("error fetching comment, will retry later: {}", e);
+            for &id in ids.iter() {
+                let thread = LazyThread::load(source.clone(), id).await.map_err(|err| {
+                    super::lib_err(err).context(format!("could not load the thread of item {}", id))
+                })?;
+                Self::start(&mut view, &thread.top, ids.len() > 1)?;
+                authors.extend(thread.top.by.clone());
+                // A comment id loads the thread of its story
+                let story = thread.top.id;
+                let mut filter = Self::filter(matches)?;
+                let stream = thread.walk_with(options);
+                pin_mut!(stream);
+                while let Some(result) = stream.next().await {
+                    match result {
+                        Ok(cnode) => {
+                            authors.extend(cnode.comment.by.clone());
+                            for cnode in filter.select(&cnode) {
+                                Self::print(&mut output, &mut view, story, &cnode)?;
+                            }
+                            // Stream comments as they arrive, rather than
+                            // aligning them at the end.
+                            output.flush_table()?;
+                            if view.is_closed() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            error!("error fetching comment: {}", e);
+                        }
                     }
                 }
+                if view.is_closed() {
+                    break;
+                }
             }
         } else {
             // - pre-fetches all comments before the walk() call
             // - initiates as many request I/Os as possible to maximize concurrency
             // - faster to get all comments, but only gives first comment once all are loaded.
-            let thread = client.thread(id).await.expect("failed to create thread");
-            for cnode in thread.walk() {
-                let s = Self::fmt_comment(&cnode, tree_mode);
-                println!("{}", s);
+            let threads = futures::future::join_all(
+                ids.iter()
+                    .map(|&id| Thread::load_with(source.clone(), id, options)),
+            )
+            .await;
+            'threads: for (thread, &id) in threads.into_iter().zip(ids.iter()) {
+                let thread = thread.map_err(|err| {
+                    super::lib_err(err).context(format!("could not load the thread of item {}", id))
+                })?;
+                Self::start(&mut view, thread.top(), ids.len() > 1)?;
+                authors.extend(thread.top().by.clone());
+                let story = thread.top().id;
+                let mut filter = Self::filter(matches)?;
+                for cnode in thread.walk() {
+                    authors.extend(cnode.comment.by.clone());
+                    for cnode in filter.select(cnode) {
+                        Self::print(&mut output, &mut view, story, &cnode)?;
+                    }
+                    if view.is_closed() {
+                        break 'threads;
+                    }
+                }
             }
         }
 
+        super::remember_users(&settings, authors.iter().map(String::as_str));
+
+        match view {
+            View::Reader(reader) => reader.pager.finish(),
+            View::Records { .. } => output.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Query;
+    use crate::api::Item;
+    use crate::client::{LazyThread, Thread};
+    use crate::testing::{comment, store_source, story};
+    use futures::stream::StreamExt;
+    use serde_json::{json, Value};
+    use std::error::Error;
+
+    #[tokio::test]
+    async fn test_query_comment_id() -> Result<(), Box<dyn Error>> {
+        let source = store_source([
+            Item::Story(story(1, vec![2])),
+            comment(2, 1, vec![3], "first"),
+            comment(3, 2, vec![], "a reply"),
+        ])
+        .await?;
+        let story_of =
+            |record| serde_json::to_value(record).map(|record: Value| record["story"].clone());
+
+        // Records of the thread of a comment name its story, not the comment
+        let thread = Thread::load(source.clone(), 3).await?;
+        let stories = thread
+            .walk()
+            .map(|cnode| story_of(Query::comment_record(thread.top().id, cnode, false, false)))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(stories, vec![json!(1), json!(1)]);
+
+        let thread = LazyThread::load(source, 3).await?;
+        assert_eq!(thread.top.id, 1);
+        let cnodes: Vec<_> = thread.walk().collect().await;
+        assert_eq!(cnodes.len(), 2);
+
         Ok(())
     }
 }
@@ -0,0 +1,177 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::cli::output::{Output, Record};
+use crate::cli::HnCommand;
+use crate::error::HnError;
+use crate::notify::{state_path, Notifier, Reply, DEFAULT_TRACKED};
+use crate::render::to_plain;
+
+/// Report new replies to a user's stories and comments.
+pub struct Replies;
+
+impl Replies {
+    fn record(reply: &Reply) -> Record {
+        Record::new()
+            .field("id", reply.comment.id)
+            .field("by", reply.comment.by.as_deref())
+            .field("parent", reply.parent)
+            .field("text", reply.comment.text.as_deref().map(to_plain))
+            .detail("time", reply.comment.time)
+    }
+
+    /// Run the hook command for a reply, passing the reply in environment
+    /// variables.
+    fn exec(command: &str, reply: &Reply) -> Result<(), Box<HnError>> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("HN_REPLY_ID", reply.comment.id.to_string())
+            .env("HN_REPLY_BY", reply.comment.by.as_deref().unwrap_or(""))
+            .env("HN_REPLY_PARENT", reply.parent.to_string())
+            .env("HN_REPLY_TEXT", reply.comment.text.as_deref().unwrap_or(""))
+            .status()
+            .map_err(|_| HnError::ArgumentError(Some("failed to run the --exec command")))?;
+        if !status.success() {
+            warn!(status = ?status, id = reply.comment.id, "--exec command failed");
+        }
+
+        Ok(())
+    }
+
+    fn report(
+        output: &mut Output,
+        matches: &ArgMatches,
+        replies: &[Reply],
+    ) -> Result<(), Box<HnError>> {
+        for reply in replies {
+            match matches.value_of("exec") {
+                Some(command) => Self::exec(command, reply)?,
+                None => output.print(Self::record(reply))?,
+            }
+        }
+
+        output.flush_table()
+    }
+}
+
+impl HnCommand for Replies {
+    const NAME: &'static str = "replies";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Report replies to a user's recent stories and comments made since the last check")
+            .arg(
+                Arg::with_name("username")
+                    .value_name("username")
+                    .help("Name or user URL of the user, or - to read it from stdin")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("watch")
+                    .long("watch")
+                    .short("w")
+                    .help("Keep checking for new replies instead of exiting"),
+            )
+            .arg(
+                Arg::with_name("interval")
+                    .long("interval")
+                    .value_name("duration")
+                    .help("Time between checks with --watch, i.e. 30s or 5m")
+                    .default_value("60s")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("updates")
+                    .long("updates")
+                    .help("With --watch, only refresh the items updates.json lists as changed"),
+            )
+            .arg(
+                Arg::with_name("exec")
+                    .long("exec")
+                    .value_name("command")
+                    .help("Run this shell command per new reply instead of printing it. The reply is passed in HN_REPLY_ID, HN_REPLY_BY, HN_REPLY_PARENT and HN_REPLY_TEXT")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("tracked")
+                    .long("tracked")
+                    .value_name("N")
+                    .help("Number of the user's most recent submissions to watch")
+                    .default_value("50")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("state")
+                    .long("state")
+                    .value_name("path")
+                    .help("File remembering the replies already reported. Defaults to notify/<username>.json in the data directory")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let username = super::ids::username(matches, "username")?.ok_or(HnError::ArgumentError(
+            Some("username argument not supplied"),
+        ))?;
+        let username = username.as_str();
+        let interval = matches
+            .value_of("interval")
+            .map(humantime::parse_duration)
+            .transpose()
+            .map_err(|_| HnError::ArgumentError(Some("interval must be a duration such as 60s")))?
+            .unwrap_or(Duration::from_secs(60));
+        let tracked = super::number(matches, "tracked", "tracked must be a number")?
+            .unwrap_or(DEFAULT_TRACKED);
+        if tracked == 0 {
+            return Err(Box::new(HnError::ArgumentError(Some(
+                "tracked must be at least 1",
+            ))));
+        }
+        let settings = super::settings(matches)?;
+        let path = match matches.value_of("state") {
+            Some(path) => PathBuf::from(path),
+            None => state_path(&settings.data_dir, username)?,
+        };
+
+        // Replies only show up in fresh data, so always ask the API.
+        let client = settings.client();
+        let mut notifier = Notifier::open(Arc::new(client.clone()), username, path)
+            .map_err(|err| HnError::StorageError(Some(err)))?
+            .with_tracked(tracked);
+
+        let mut output = Output::new(matches, &settings)?;
+        let replies = notifier.poll().await.map_err(super::lib_err)?;
+        Self::report(&mut output, matches, &replies)?;
+        if !matches.is_present("watch") {
+            return output.flush();
+        }
+        // A JSON array would never be closed, print replies as they arrive
+        let mut output = output.streaming();
+
+        loop {
+            tokio::time::sleep(interval).await;
+            let replies = if matches.is_present("updates") {
+                match client.updates().await {
+                    Ok((items, profiles)) => notifier.poll_updates(&items, &profiles).await,
+                    Err(err) => Err(err),
+                }
+            } else {
+                notifier.poll().await
+            };
+            // Keep watching through transient failures, i.e. a dropped connection.
+            match replies {
+                Ok(replies) => Self::report(&mut output, matches, &replies)?,
+                Err(err) => warn!(err = %err, "failed to check for replies"),
+            }
+        }
+    }
+}
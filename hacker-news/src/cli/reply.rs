@@ -0,0 +1,52 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+use crate::util::compose;
+
+/// Reply to a story or comment.
+pub struct Reply;
+
+impl HnCommand for Reply {
+    const NAME: &'static str = "reply";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about(
+                "Reply to a story or comment as the logged in user, and print the new comment's id",
+            )
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Id or item URL of the story or comment to reply to, or - to read it from stdin")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("text")
+                    .long("text")
+                    .value_name("text")
+                    .help("Text of the reply. Composed in $EDITOR when not given")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let id = super::ids::id(matches, "id")?;
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        client.require_session()?;
+        let text = match matches.value_of("text") {
+            Some(text) => text.to_string(),
+            None => compose("")?,
+        };
+        let reply = client.reply(id, &text).await.map_err(super::lib_err)?;
+        println!("{}", reply);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use futures::pin_mut;
+use futures::stream::StreamExt;
+use serde_json::Value;
+use std::path::PathBuf;
+use tracing::info;
+
+use crate::archive::ArchiveReader;
+use crate::cli::output::{Output, Record};
+use crate::cli::HnCommand;
+use crate::config::Settings;
+use crate::error::HnError;
+use crate::index::{LocalHit, LocalQuery, Ranking, SearchIndex};
+use crate::render::to_plain;
+use crate::search::{SearchClient, SearchHit, SearchOrder, SearchQuery, Tag};
+use crate::store::Store;
+use crate::util::{format_age, now, parse_since};
+
+/// Search stories and comments.
+pub struct Search;
+
+impl Search {
+    /// Rebuild the local index from the SQLite store and/or an archive.
+    fn rebuild(
+        index: &SearchIndex,
+        settings: &Settings,
+        matches: &ArgMatches<'_>,
+    ) -> Result<usize, Box<HnError>> {
+        let mut count = 0;
+        let mut batch = Vec::with_capacity(1000);
+        let mut flush = |batch: &mut Vec<_>| -> Result<(), Box<HnError>> {
+            count += index
+                .add_items(batch.iter())
+                .map_err(|err| HnError::StorageError(Some(err)))?;
+            batch.clear();
+            Ok(())
+        };
+
+        if let Some(dir) = matches.value_of("from-archive") {
+            let archive =
+                ArchiveReader::open(dir).map_err(|err| HnError::StorageError(Some(err)))?;
+            for item in archive.iter() {
+                batch.push(item.map_err(|err| HnError::StorageError(Some(err)))?);
+                if batch.len() == batch.capacity() {
+                    flush(&mut batch)?;
+                }
+            }
+        } else {
+            let db = matches
+                .value_of("db")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| settings.db_path());
+            let store = Store::open(db).map_err(|err| HnError::StorageError(Some(err)))?;
+            store
+                .for_each_item(|item| {
+                    batch.push(item);
+                    if batch.len() == batch.capacity() {
+                        flush(&mut batch).map_err(|err| err as Box<dyn std::error::Error>)?;
+                    }
+                    Ok(())
+                })
+                .map_err(|err| HnError::StorageError(Some(err)))?;
+        }
+        flush(&mut batch)?;
+
+        Ok(count)
+    }
+
+    fn record(
+        id: Value,
+        kind: &str,
+        by: Option<&str>,
+        time: u64,
+        score: Option<u64>,
+        title: Option<&str>,
+        text: Option<&str>,
+    ) -> Record {
+        Record::new()
+            .field("id", id)
+            .field("type", kind)
+            .field("by", by)
+            .field("age", format_age(time, now()))
+            .field("score", score)
+            .field("title", title)
+            .field("text", text)
+            .detail("time", time)
+    }
+
+    fn hit_record(hit: &LocalHit) -> Record {
+        Self::record(
+            hit.id.into(),
+            &hit.kind,
+            hit.by.as_deref(),
+            hit.time,
+            hit.score,
+            hit.title.as_deref(),
+            hit.text.as_deref(),
+        )
+    }
+
+    fn remote_hit_record(hit: &SearchHit) -> Record {
+        let text = hit
+            .comment_text
+            .as_deref()
+            .or(hit.story_text.as_deref())
+            .map(to_plain);
+        // Algolia ids are numeric strings, print them as numbers like local ids
+        let id = hit
+            .id()
+            .map(Value::from)
+            .unwrap_or_else(|| hit.object_id.clone().into());
+        Self::record(
+            id,
+            hit.kind(),
+            hit.author.as_deref(),
+            hit.created_at_i,
+            hit.points,
+            hit.title.as_deref(),
+            text.as_deref(),
+        )
+        .detail("url", hit.url.as_deref())
+    }
+
+    fn limit(matches: &ArgMatches<'_>) -> Result<usize, Box<HnError>> {
+        let limit = matches
+            .value_of("limit")
+            .unwrap_or("20")
+            .parse()
+            .map_err(|_| HnError::ArgumentError(Some("limit must be a number")))?;
+
+        Ok(limit)
+    }
+
+    async fn remote(matches: &ArgMatches<'_>, settings: &Settings) -> Result<(), Box<HnError>> {
+        let text = matches
+            .values_of("query")
+            .map(|words| words.collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        let limit = Self::limit(matches)?;
+        let mut query = SearchQuery::new(text).hits_per_page(limit.clamp(1, 1000) as u32);
+        if let Some(kind) = matches.value_of("type") {
+            query = query.tag(kind.parse()?);
+        }
+        if let Some(author) = matches.value_of("author") {
+            query = query.tag(Tag::Author(author.to_string()));
+        }
+        for tag in matches.values_of("tag").into_iter().flatten() {
+            query = query.tag(tag.parse()?);
+        }
+        if let Some(since) = matches.value_of("since") {
+            query = query.created_after(parse_since(since)?);
+        }
+        if let Some(until) = matches.value_of("until") {
+            query = query.created_before(parse_since(until)?);
+        }
+        if let Some(points) = matches.value_of("min-score") {
+            let points = points
+                .parse()
+                .map_err(|_| HnError::ArgumentError(Some("min-score must be a number")))?;
+            query = query.min_points(points);
+        }
+        if let Some(comments) = matches.value_of("min-comments") {
+            let comments = comments
+                .parse()
+                .map_err(|_| HnError::ArgumentError(Some("min-comments must be a number")))?;
+            query = query.min_comments(comments);
+        }
+        if let Some(page) = matches.value_of("page") {
+            let page = page
+                .parse()
+                .map_err(|_| HnError::ArgumentError(Some("page must be a number")))?;
+            query = query.page(page);
+        }
+        if matches.value_of("sort") == Some("date") {
+            query = query.order(SearchOrder::Date);
+        }
+
+        let client = match matches.value_of("search-url") {
+            Some(url) => SearchClient::with_base_url(url),
+            None => SearchClient::new(),
+        };
+        let mut output = Output::new(matches, settings)?;
+        let stream = client.search_stream(query).take(limit);
+        pin_mut!(stream);
+        while let Some(hit) = stream.next().await {
+            let hit = hit.map_err(super::lib_err)?;
+            output.print(Self::remote_hit_record(&hit))?;
+        }
+
+        output.flush()
+    }
+
+    async fn local(matches: &ArgMatches<'_>, settings: &Settings) -> Result<(), Box<HnError>> {
+        let dir = matches
+            .value_of("index")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| settings.index_dir());
+        let index = SearchIndex::open(&dir).map_err(|err| HnError::StorageError(Some(err)))?;
+        if matches.is_present("rebuild") {
+            let count = Self::rebuild(&index, settings, matches)?;
+            info!(count = count, "rebuilt local search index");
+        }
+
+        let text = matches
+            .values_of("query")
+            .map(|words| words.collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        let mut query = LocalQuery::new(text);
+        query.author = matches.value_of("author").map(str::to_string);
+        query.kind = matches.value_of("type").map(str::to_string);
+        query.since = matches.value_of("since").map(parse_since).transpose()?;
+        query.until = matches.value_of("until").map(parse_since).transpose()?;
+        query.min_score = matches
+            .value_of("min-score")
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|_| HnError::ArgumentError(Some("min-score must be a number")))?;
+        query.ranking = match matches.value_of("sort") {
+            Some("date") => Ranking::Date,
+            _ => Ranking::Relevance,
+        };
+        query.limit = Self::limit(matches)?;
+
+        // Only an invalid query is reported as an HnError, anything else is
+        // a failure of the index.
+        let hits = index
+            .search(&query)
+            .map_err(|err| match err.downcast::<HnError>() {
+                Ok(err) => err.context(format!("could not parse the query {:?}", query.text)),
+                Err(err) => Box::new(HnError::StorageError(Some(err))),
+            })?;
+        let mut output = Output::new(matches, settings)?;
+        for hit in hits.iter() {
+            output.print(Self::hit_record(hit))?;
+        }
+
+        output.flush()
+    }
+}
+
+impl HnCommand for Search {
+    const NAME: &'static str = "search";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Search the title and text of stories and comments")
+            .arg(
+                Arg::with_name("query")
+                    .value_name("query")
+                    .help("Words to search for")
+                    .multiple(true),
+            )
+            .arg(
+                Arg::with_name("local")
+                    .long("local")
+                    .help("Search the local full-text index instead of hn.algolia.com"),
+            )
+            .arg(
+                Arg::with_name("author")
+                    .long("author")
+                    .value_name("username")
+                    .help("Only match items by this user")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("type")
+                    .long("type")
+                    .value_name("type")
+                    .help("Only match items of this type")
+                    .possible_values(&["story", "comment", "job", "poll", "pollopt"])
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .value_name("time")
+                    .help("Only match items created after this Unix Time, or this long ago, i.e. 7days")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("until")
+                    .long("until")
+                    .value_name("time")
+                    .help("Only match items created before this Unix Time, or this long ago")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("tag")
+                    .long("tag")
+                    .value_name("tag")
+                    .help("Only match items with this tag, i.e. ask_hn, show_hn, front_page, author_<user> or story_<id>. Not supported with --local")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .conflicts_with("local")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("min-score")
+                    .long("min-score")
+                    .value_name("points")
+                    .help("Only match items with at least this many points")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("min-comments")
+                    .long("min-comments")
+                    .value_name("N")
+                    .help("Only match stories with at least this many comments. Not supported with --local")
+                    .conflicts_with("local")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("page")
+                    .long("page")
+                    .value_name("page")
+                    .help("Zero-based page of results to start from. Not supported with --local")
+                    .conflicts_with("local")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("search-url")
+                    .long("search-url")
+                    .value_name("url")
+                    .help("Base URL of an Algolia compatible search API")
+                    .env("HN_SEARCH_URL")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("sort")
+                    .long("sort")
+                    .value_name("order")
+                    .possible_values(&["relevance", "date"])
+                    .default_value("relevance")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .short("n")
+                    .value_name("N")
+                    .default_value("20")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("index")
+                    .long("index")
+                    .value_name("dir")
+                    .help("Directory of the local index. Defaults to index in the data directory")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("rebuild")
+                    .long("rebuild")
+                    .help("Index every item of the SQLite store, or of --from-archive, before searching")
+                    .requires("local"),
+            )
+            .arg(
+                Arg::with_name("db")
+                    .long("db")
+                    .value_name("path")
+                    .help("SQLite store to index with --rebuild")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("from-archive")
+                    .long("from-archive")
+                    .value_name("dir")
+                    .help("Item archive to index with --rebuild, instead of the SQLite store")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        if matches.is_present("local") {
+            Self::local(matches, &settings).await
+        } else {
+            Self::remote(matches, &settings).await
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+use crate::util::compose;
+
+/// Submit a story.
+pub struct Submit;
+
+impl HnCommand for Submit {
+    const NAME: &'static str = "submit";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Submit a link or a text post as the logged in user, and print the new story's id")
+            .arg(
+                Arg::with_name("title")
+                    .value_name("title")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("url")
+                    .long("url")
+                    .value_name("url")
+                    .help("URL of the story")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("text")
+                    .long("text")
+                    .value_name("text")
+                    .help("Text of the story. Composed in $EDITOR when neither --url nor --text is given")
+                    .conflicts_with("url")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        // SAFE: title is a required argument
+        let title = matches.value_of("title").unwrap();
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        client.require_session()?;
+        let url_or_text = match (matches.value_of("url"), matches.value_of("text")) {
+            (Some(url), _) => url.to_string(),
+            (None, Some(text)) => text.to_string(),
+            (None, None) => compose("")?,
+        };
+        let id = client
+            .submit(title, &url_or_text)
+            .await
+            .map_err(super::lib_err)?;
+        println!("{}", id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use futures::stream::{self, StreamExt};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::api::{Id, Item, StoryList};
+use crate::cli::HnCommand;
+use crate::error::HnError;
+use crate::store::Store;
+
+/// Mirror Hacker News into a local SQLite database.
+pub struct SyncCmd;
+
+impl HnCommand for SyncCmd {
+    const NAME: &'static str = "sync";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Mirror the story lists and recently updated items into a local SQLite database")
+            .arg(
+                Arg::with_name("db")
+                    .long("db")
+                    .value_name("path")
+                    .help(
+                        "Path of the SQLite database. Defaults to hn.sqlite3 in the data directory",
+                    )
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("lists")
+                    .long("lists")
+                    .value_name("list")
+                    .help("Story lists to snapshot")
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .possible_values(&["top", "new", "best", "ask", "show", "job"])
+                    .default_value("top,new,best,ask,show,job"),
+            )
+            .arg(
+                Arg::with_name("no-updates")
+                    .long("no-updates")
+                    .help("Do not fetch the items and profiles from updates.json")
+                    .takes_value(false),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let settings = super::settings(matches)?;
+        let db = matches
+            .value_of("db")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| settings.db_path());
+        let lists = matches
+            .values_of("lists")
+            .into_iter()
+            .flatten()
+            .map(|list| list.parse::<StoryList>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut store = Store::open(&db).map_err(|err| HnError::StorageError(Some(err)))?;
+        let client = settings.client();
+
+        // Ids are synced in the order they are first seen, so the front page is
+        // stored before the long tail of updates.
+        let mut seen = HashSet::new();
+        let mut ids: Vec<Id> = vec![];
+        for list in lists {
+            let list_ids = client.stories(list).await.map_err(super::lib_err)?;
+            store
+                .snapshot_list(list, &list_ids)
+                .map_err(|err| HnError::StorageError(Some(err)))?;
+            info!(
+                list = list.as_str(),
+                count = list_ids.len(),
+                "snapshot story list"
+            );
+            ids.extend(list_ids.into_iter().filter(|id| seen.insert(*id)));
+        }
+
+        let mut profiles = vec![];
+        if !matches.is_present("no-updates") {
+            let (items, updated_profiles) = client.updates().await.map_err(super::lib_err)?;
+            ids.extend(items.into_iter().filter(|id| seen.insert(*id)));
+            profiles = updated_profiles;
+        }
+
+        // An item or profile failing to load, such as one deleted since it
+        // was listed, is skipped rather than failing the whole sync.
+        let mut stored = 0;
+        for chunk in ids.chunks(100) {
+            let items: Vec<Item> = stream::iter(chunk)
+                .map(|&id| {
+                    let client = &client;
+                    async move { (id, client.item(id).await) }
+                })
+                .buffered(settings.concurrency)
+                .filter_map(|(id, item)| async move {
+                    item.map_err(|err| warn!(err = %err, id, "skipping item failing to load"))
+                        .ok()
+                })
+                .collect()
+                .await;
+            for item in items.iter() {
+                store
+                    .upsert_item(item)
+                    .map_err(|err| HnError::StorageError(Some(err)))?;
+            }
+            stored += items.len();
+            info!(count = items.len(), "stored items");
+        }
+
+        for username in profiles {
+            let user = match client.user(username.clone()).await {
+                Ok(user) => user,
+                Err(err) => {
+                    warn!(err = %err, username, "skipping profile failing to load");
+                    continue;
+                }
+            };
+            store
+                .upsert_user(&user)
+                .map_err(|err| HnError::StorageError(Some(err)))?;
+        }
+
+        match ids.len() - stored {
+            0 => println!("synced {} items into {}", stored, db.display()),
+            skipped => println!(
+                "synced {} items into {}, skipped {} failing to load",
+                stored,
+                db.display(),
+                skipped
+            ),
+        }
+
+        Ok(())
+    }
+}
@@ -1,47 +1,96 @@
-use serde_json;
 use clap::App;
 use clap::Arg;
 use clap::ArgMatches;
 use clap::SubCommand;
-use crate::client::html_client::Client;
-use crate::api::Id;
+
+use crate::api::{Comment, Story};
+use crate::cli::output::{Output, Record};
 use crate::cli::HnCommand;
+use crate::client::Thread as CommentThread;
 use crate::error::HnError;
+use crate::render::to_plain;
+use crate::util::{format_age, now};
 
+/// Dump a full comment thread as JSON.
 pub struct Thread;
 
+impl Thread {
+    fn story_record(story: &Story, now: u64) -> Record {
+        Record::new()
+            .field("id", story.id)
+            .field("type", "story")
+            .field("by", story.by.as_deref())
+            .field("age", format_age(story.time, now))
+            .field("score", story.score)
+            .field("title", story.title.as_str())
+            .field("text", story.text.as_deref().map(to_plain))
+            .detail("time", story.time)
+            .detail("url", story.url.as_deref())
+            .detail("depth", None::<usize>)
+            .detail("parent", None::<u64>)
+    }
+
+    fn comment_record(comment: &Comment, depth: usize, now: u64) -> Record {
+        Record::new()
+            .indent(depth)
+            .field("id", comment.id)
+            .field("type", "comment")
+            .field("by", comment.by.as_deref())
+            .field("age", format_age(comment.time, now))
+            .field("score", None::<u64>)
+            .field("title", None::<String>)
+            .field("text", comment.text.as_deref().map(to_plain))
+            .detail("time", comment.time)
+            .detail("url", None::<String>)
+            .detail("depth", depth)
+            .detail("parent", comment.parent)
+    }
+
+    /// The story of `thread` followed by its comments, depth first.
+    fn records(thread: &CommentThread, now: u64) -> Vec<Record> {
+        std::iter::once(Self::story_record(thread.top(), now))
+            .chain(
+                thread
+                    .walk()
+                    .map(|node| Self::comment_record(&node.comment, node.depth, now)),
+            )
+            .collect()
+    }
+}
+
 impl HnCommand for Thread {
     const NAME: &'static str = "thread";
 
     fn parser<'a, 'b>() -> App<'a, 'b> {
-        SubCommand::with_name(Self::NAME).arg(
-            Arg::with_name("id")
-                .value_name("id")
-                .required(true)
-                .takes_value(true)
-                .min_values(1),
-        )
+        SubCommand::with_name(Self::NAME)
+            .about("Print a story and all of its comments, as nested JSON with --format json, or as one record per item with its depth")
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Id or item URL of the story, or - to read it from stdin")
+                    .required(true)
+                    .takes_value(true),
+            )
     }
 
-    fn cmd(matches: &ArgMatches) -> Result<(), Box<HnError>> {
-        log::debug!("Begin hackernews subcommand command");
-
-        let id = match matches.value_of("id") {
-            None => unreachable!("clap will require an argument value"),
-            Some(id) => id,
-        };
-        let id: Id = id.parse()
-            .map_err(|_| HnError::ArgumentError(Some("Thread id not parseable as u32")))?;
-
-        let client = Client::new();
-        let thread = client.thread(id)?;
-        let json = serde_json::to_string(&thread)
-            .map_err(|_src| HnError::SerializationError(
-                Some("Failed to serialize thread using serde derived implementations")
-        ))?;
-        println!("{}", json);
-
-        Ok(())
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let id = super::ids::id(matches, "id")?;
+        let settings = super::settings(matches)?;
+        let mut output = Output::new(matches, &settings)?;
+
+        let source = super::source(&settings)?;
+        let thread = CommentThread::load(source, id).await.map_err(|err| {
+            super::lib_err(err).context(format!("could not load the thread of item {}", id))
+        })?;
+
+        // JSON keeps the nesting of the thread, other formats list its items
+        if output.is_json() {
+            return output.print_document(&thread);
+        }
+        for record in Self::records(&thread, now()) {
+            output.print(record)?;
+        }
+
+        output.flush()
     }
 }
-
@@ -2,49 +2,179 @@ use clap::App;
 use clap::Arg;
 use clap::ArgMatches;
 use clap::SubCommand;
-// use crate::api::Id;
+
+use crate::cli::output::{Output, Record};
 use crate::cli::HnCommand;
+use crate::client::{CommentNode, LoadOptions, Thread};
 use crate::error::HnError;
 
+/// Draw the comments of a thread like the unix `tree` command.
 pub struct Tree;
 
+impl Tree {
+    /// Draw `nodes` and their children below a line prefixed with `prefix`,
+    /// as a [Record] per comment whose `tree` field holds its branch.
+    fn draw(nodes: &[CommentNode], prefix: &str, records: &mut Vec<Record>) {
+        for (i, node) in nodes.iter().enumerate() {
+            let last = i + 1 == nodes.len();
+            let (branch, indent) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let by = node.comment.by.as_deref();
+            records.push(
+                Record::new()
+                    .field(
+                        "tree",
+                        format!("{}{}{}", prefix, branch, by.unwrap_or("[deleted]")),
+                    )
+                    .field("id", node.comment.id)
+                    .detail("by", by)
+                    .detail("depth", node.depth)
+                    .detail("parent", node.comment.parent),
+            );
+            Self::draw(&node.children, &format!("{}{}", prefix, indent), records);
+        }
+    }
+
+    fn records(thread: &Thread) -> Vec<Record> {
+        let top = thread.top();
+        let by = top.by.as_deref();
+        let mut records = vec![Record::new()
+            .field(
+                "tree",
+                format!("{} ({})", top.title, by.unwrap_or("[deleted]")),
+            )
+            .field("id", top.id)
+            .detail("by", by)
+            .detail("depth", None::<usize>)
+            .detail("parent", None::<u64>)];
+        Self::draw(thread.comments(), "", &mut records);
+
+        records
+    }
+}
+
 impl HnCommand for Tree {
     const NAME: &'static str = "tree";
 
     fn parser<'a, 'b>() -> App<'a, 'b> {
         SubCommand::with_name(Self::NAME)
+            .about("Draw who replied to whom in a thread, like the unix tree command")
             .arg(
                 Arg::with_name("id")
                     .value_name("id")
+                    .help("Id or item URL of the story, or - to read it from stdin")
                     .required(true)
-                    .takes_value(true)
-                    .min_values(1),
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("depth")
+                    .long("depth")
+                    .short("L")
+                    .value_name("levels")
+                    .help("Only draw this many levels of comments")
+                    .takes_value(true),
             )
             .arg(
                 Arg::with_name("timeout")
-                    .value_name("timeout")
                     .long("timeout")
                     .short("t")
-                    .required(false)
-                    .takes_value(true)
-                    .min_values(1),
+                    .value_name("duration")
+                    .help("Stop loading after this long, i.e. 5s, and draw the comments loaded so far")
+                    .takes_value(true),
             )
     }
 
-    fn cmd(_matches: &ArgMatches) -> Result<(), Box<HnError>> {
-        // let _id: Id = matches
-        //     .value_of("id")
-        //     .ok_or("Id is required for query")?
-        //     .parse()?;
-        // Instantiate client, and retrieve comment data
-        // let mut replies: Vec<Comment> = vec![];
-        // let client = Client::new();
-        // for reply in client.iter_replies(id)? {
-        //     let reply = reply?;
-        //     replies.push(reply);
-        // }
-        // println!("{:#?}", replies);
-        // Ok(())
-        unimplemented!("Re-implement this with HTML based client");
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let id = super::ids::id(matches, "id")?;
+        let options = LoadOptions {
+            max_depth: matches
+                .value_of("depth")
+                .map(str::parse)
+                .transpose()
+                .map_err(|_| HnError::ArgumentError(Some("depth must be a number")))?,
+            timeout: matches
+                .value_of("timeout")
+                .map(humantime::parse_duration)
+                .transpose()
+                .map_err(|_| {
+                    HnError::ArgumentError(Some("timeout must be a duration such as 5s"))
+                })?,
+            ..LoadOptions::default()
+        };
+
+        let settings = super::settings(matches)?;
+        let mut output = Output::new(matches, &settings)?;
+        let source = super::source(&settings)?;
+        let thread = Thread::load_with(source, id, options)
+            .await
+            .map_err(|err| {
+                super::lib_err(err).context(format!("could not load the thread of item {}", id))
+            })?;
+        for record in Self::records(&thread) {
+            output.print(record)?;
+        }
+        output.flush()?;
+        let loaded = thread.walk().count();
+        match thread.top().descendants {
+            Some(total) if options.max_depth.is_none() && loaded < total as usize => {
+                eprintln!("{} of {} comments loaded", loaded, total)
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Tree;
+    use crate::api::{Item, Story};
+    use crate::client::Thread;
+    use crate::testing::{comment, store_source, story};
+    use serde_json::Value;
+    use std::error::Error;
+
+    #[tokio::test]
+    async fn test_draw_tree() -> Result<(), Box<dyn Error>> {
+        let source = store_source([
+            Item::Story(Story {
+                title: "Ask HN: Trees?".to_string(),
+                descendants: Some(3),
+                ..story(1, vec![2, 4])
+            }),
+            comment(2, 1, vec![3], "a"),
+            comment(3, 2, vec![], "b"),
+            comment(4, 1, vec![], "c"),
+        ])
+        .await?;
+
+        let thread = Thread::load(source, 1).await?;
+        let drawn = Tree::records(&thread)
+            .into_iter()
+            .map(|record| {
+                let record: Value = serde_json::to_value(record)?;
+                Ok(format!(
+                    "{} {}",
+                    record["tree"].as_str().unwrap_or(""),
+                    record["id"]
+                ))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()?;
+        assert_eq!(
+            drawn,
+            vec![
+                "Ask HN: Trees? (pg) 1",
+                "├── user2 2",
+                "│   └── user3 3",
+                "└── user4 4",
+            ]
+        );
+
+        Ok(())
     }
 }
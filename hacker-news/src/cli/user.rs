@@ -0,0 +1,217 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::api;
+use crate::api::Item;
+use crate::cli::output::{item_record, Output, Record};
+use crate::cli::HnCommand;
+use crate::error::HnError;
+use crate::render::to_plain;
+use crate::source::ItemSource;
+use crate::util::{format_age, now};
+
+/// Show a user's profile and submissions.
+pub struct User;
+
+impl User {
+    fn record(user: &api::User, now: u64) -> Record {
+        let created = user.created.timestamp().max(0) as u64;
+        Record::new()
+            .field("user", user.id.as_str())
+            .field("karma", user.karma)
+            .field("created", user.created.format("%Y-%m-%d").to_string())
+            .field("age", format_age(created, now))
+            .field(
+                "submissions",
+                user.submitted.as_ref().map(Vec::len).unwrap_or(0),
+            )
+            .field("about", user.about.as_deref().map(to_plain))
+            .detail("time", created)
+            .detail("delay", user.delay)
+    }
+
+    /// Pass the first `limit` of `user`'s submissions of type `kind`, newest
+    /// first, to `page` a page at a time. Submissions are resolved a page at a
+    /// time too, so a filtered listing of a prolific user stops fetching as
+    /// soon as it has enough items.
+    async fn submissions(
+        source: &dyn ItemSource,
+        user: &api::User,
+        kind: Option<&str>,
+        limit: usize,
+        mut page: impl FnMut(Vec<Item>) -> Result<(), Box<HnError>>,
+    ) -> Result<(), Box<HnError>> {
+        let submitted = user.submitted.as_deref().unwrap_or_default();
+        let mut left = limit;
+        for ids in submitted.chunks(limit.clamp(10, 100)) {
+            let items = source.get_items(ids).await.map_err(|err| {
+                super::lib_err(err)
+                    .context(format!("could not load the submissions of {}", user.id))
+            })?;
+            let items: Vec<Item> = items
+                .into_iter()
+                .filter(|item| !item.deleted() && kind.is_none_or(|kind| kind == item.kind()))
+                .take(left)
+                .collect();
+            left -= items.len();
+            page(items)?;
+            if left == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl HnCommand for User {
+    const NAME: &'static str = "user";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Show a user's karma, age and about, or their submissions")
+            .arg(
+                Arg::with_name("username")
+                    .value_name("username")
+                    .help("Name or user URL of the user, or - to read it from stdin")
+                    .required(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("submissions")
+                    .long("submissions")
+                    .help("List the user's submissions, newest first, instead of the profile"),
+            )
+            .arg(
+                Arg::with_name("type")
+                    .long("type")
+                    .value_name("type")
+                    .help("Only list submissions of this type")
+                    .possible_values(&["story", "comment", "job", "poll", "pollopt"])
+                    .requires("submissions")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .short("n")
+                    .value_name("N")
+                    .help("Maximum number of submissions to list. Defaults to 30")
+                    .requires("submissions")
+                    .takes_value(true),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let username = super::ids::username(matches, "username")?.ok_or(HnError::ArgumentError(
+            Some("username argument not supplied"),
+        ))?;
+        let username = username.as_str();
+        let limit = super::number(matches, "limit", "limit must be a number")?.unwrap_or(30);
+        if limit == 0 {
+            return Err(Box::new(HnError::ArgumentError(Some(
+                "limit must be at least 1",
+            ))));
+        }
+        let settings = super::settings(matches)?;
+        let source = super::source(&settings)?;
+        let user = source
+            .get_user(username)
+            .await
+            .map_err(|err| {
+                super::lib_err(err).context(format!("could not load user {}", username))
+            })?
+            .ok_or_else(|| HnError::NotFoundError(format!("user {}", username)))?;
+        super::remember_users(&settings, [user.id.as_str()]);
+
+        let mut output = Output::new(matches, &settings)?;
+        let now = now();
+        if !matches.is_present("submissions") {
+            output.print(Self::record(&user, now))?;
+            return output.flush();
+        }
+
+        let kind = matches.value_of("type");
+        Self::submissions(source.as_ref(), &user, kind, limit, |items| {
+            for item in items.iter() {
+                output.print(item_record(item, now))?;
+            }
+            output.flush_table()
+        })
+        .await?;
+
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::User;
+    use crate::api::{self, Item, Story};
+    use crate::cli::hacker_news::HackerNews;
+    use crate::cli::HnCommand;
+    use crate::testing::{comment, store_source, story};
+    use chrono::DateTime;
+    use std::error::Error;
+
+    #[tokio::test]
+    async fn test_submissions() -> Result<(), Box<dyn Error>> {
+        // Every third submission is a story, and story 3 is deleted
+        let source = store_source((1..=30).map(|id| match id % 3 {
+            0 => Item::Story(Story {
+                deleted: id == 3,
+                ..story(id, vec![])
+            }),
+            _ => comment(id, 100, vec![], "text"),
+        }))
+        .await?;
+        let user = api::User {
+            id: "pg".to_string(),
+            delay: None,
+            created: DateTime::from_timestamp(0, 0).unwrap(),
+            karma: 1,
+            about: None,
+            submitted: Some((1..=30).rev().collect()),
+        };
+
+        let list = |kind, limit| {
+            let source = source.as_ref();
+            let user = &user;
+            async move {
+                let mut listed = vec![];
+                User::submissions(source, user, kind, limit, |items| {
+                    listed.extend(items.iter().map(Item::id));
+                    Ok(())
+                })
+                .await
+                .map(|_| listed)
+            }
+        };
+        assert_eq!(list(None, 3).await?, vec![30, 29, 28]);
+        assert_eq!(list(Some("story"), 4).await?, vec![30, 27, 24, 21]);
+        // deleted submissions are skipped, and a short history ends early
+        assert_eq!(
+            list(Some("story"), 100).await?,
+            vec![30, 27, 24, 21, 18, 15, 12, 9, 6]
+        );
+        assert_eq!(list(Some("comment"), 25).await?.len(), 20);
+
+        let matches = HackerNews::parser().get_matches_from(vec![
+            "hn",
+            "user",
+            "pg",
+            "--submissions",
+            "-n",
+            "0",
+        ]);
+        let (_, Some(matches)) = matches.subcommand() else {
+            panic!("no subcommand");
+        };
+        assert_eq!(User::cmd(matches).await.unwrap_err().exit_code(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use clap::App;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+
+use crate::cli::HnCommand;
+use crate::error::HnError;
+
+/// Upvote items, or with --down or --un downvote them or remove the votes.
+pub struct Vote;
+
+impl HnCommand for Vote {
+    const NAME: &'static str = "vote";
+
+    fn parser<'a, 'b>() -> App<'a, 'b> {
+        SubCommand::with_name(Self::NAME)
+            .about("Upvote items as the logged in user, or with --down or --un downvote them or remove the votes")
+            .arg(
+                Arg::with_name("id")
+                    .value_name("id")
+                    .help("Ids or item URLs of the items, or - to read them from stdin")
+                    .required(true)
+                    .multiple(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("down")
+                    .long("down")
+                    .help("Downvote the item instead, which needs enough karma")
+                    .conflicts_with("un"),
+            )
+            .arg(
+                Arg::with_name("un")
+                    .long("un")
+                    .help("Remove the vote instead"),
+            )
+    }
+
+    async fn cmd(matches: &ArgMatches<'_>) -> Result<(), Box<HnError>> {
+        let ids = super::ids::ids(matches, "id")?;
+
+        let settings = super::settings(matches)?;
+        let client = super::html_client(matches, &settings)?;
+        for id in ids {
+            if matches.is_present("un") {
+                client.unvote(id).await
+            } else if matches.is_present("down") {
+                client.downvote(id).await
+            } else {
+                client.upvote(id).await
+            }
+            .map_err(|err| super::lib_err(err).context(format!("could not vote on item {}", id)))?;
+        }
+
+        Ok(())
+    }
+}
@@ -3,36 +3,202 @@ use crate::api::Id;
 use crate::api::Item;
 use crate::api::ItemsAndProfiles;
 use crate::api::Story;
+use crate::api::StoryList;
 use crate::api::User;
 use crate::error::HnError;
 use crate::error::HttpError;
+use crate::source::ItemSource;
 use futures::stream::FuturesUnordered;
 use futures::stream::{self, Stream, StreamExt};
+use regex::Regex;
 use reqwest;
 use reqwest::Client as ReqwestClient;
 use reqwest::Request;
 use reqwest::Response;
+use serde::Serialize;
 use serde_json;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::error::Error;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 use tracing::{debug, info, warn};
 
+pub mod html_client;
+
 #[derive(Debug, Clone)]
 pub struct Client {
     http_client: ReqwestClient,
+    concurrency: usize,
+    throttle: Option<Arc<Throttle>>,
+}
+
+/// Spaces out requests so no more than a fixed number are sent per second.
+#[derive(Debug)]
+struct Throttle {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    /// Wait until the next request may be sent.
+    async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        let now = Instant::now();
+        if *next > now {
+            tokio::time::sleep_until(*next).await;
+        }
+        *next = (*next).max(now) + self.interval;
+    }
 }
 
 type CommentMap = HashMap<Id, Comment>;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Thread {
     top: Story,
     comments: Vec<CommentNode>,
 }
 
+/// Limits on loading a [Thread].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    /// Only load comments this many levels deep, i.e. 1 loads only the
+    /// top level comments.
+    pub max_depth: Option<usize>,
+    /// Stop loading after this long, keeping the comments loaded so far.
+    pub timeout: Option<Duration>,
+    /// Only load the first this many replies of each comment, counting the
+    /// rest in [CommentNode::collapsed]. Top level comments are all loaded.
+    pub collapse_after: Option<usize>,
+}
+
+impl LoadOptions {
+    /// The replies to load of a comment at `depth` with `kids`, and the
+    /// number of replies collapsed.
+    fn replies<'a>(&self, depth: usize, kids: &'a [Id]) -> (&'a [Id], usize) {
+        if self.max_depth.is_some_and(|max| depth + 1 >= max) {
+            return (&[], 0);
+        }
+        let loaded = self.collapse_after.unwrap_or(kids.len()).min(kids.len());
+
+        (&kids[..loaded], kids.len() - loaded)
+    }
+}
+
+/// Selects the comments of a thread to show, by author, text, age, number of
+/// replies and whether they are dead. Comments must be passed to
+/// [CommentFilter::select] parent before child, as both [Thread::walk] and
+/// [LazyThread::walk] yield them.
+#[derive(Debug, Default)]
+pub struct CommentFilter {
+    author: Option<String>,
+    grep: Option<Regex>,
+    min_replies: usize,
+    since: Option<u64>,
+    hide_dead: bool,
+    /// Comments passed over, in case a reply matching `grep` needs them as
+    /// context.
+    held: HashMap<Id, CommentNode>,
+    shown: HashSet<Id>,
+}
+
+impl CommentFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only show comments by `author`.
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Only show comments with plain text matching `grep`, preceded by the
+    /// comments they reply to.
+    pub fn with_grep(mut self, grep: Regex) -> Self {
+        self.grep = Some(grep);
+        self
+    }
+
+    /// Only show comments with at least `min_replies` direct replies.
+    pub fn with_min_replies(mut self, min_replies: usize) -> Self {
+        self.min_replies = min_replies;
+        self
+    }
+
+    /// Only show comments made at or after the Unix Time `since`.
+    pub fn with_since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Leave out dead and deleted comments.
+    pub fn with_hide_dead(mut self, hide_dead: bool) -> Self {
+        self.hide_dead = hide_dead;
+        self
+    }
+
+    fn matches(&self, comment: &Comment) -> bool {
+        let replies = comment.kids.as_ref().map(Vec::len).unwrap_or(0);
+        let text = comment.text.as_deref().map(crate::render::to_plain);
+
+        self.author
+            .as_ref()
+            .is_none_or(|author| comment.by.as_ref() == Some(author))
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|grep| text.as_deref().is_some_and(|text| grep.is_match(text)))
+            && replies >= self.min_replies
+            && self.since.is_none_or(|since| comment.time >= since)
+            && !(self.hide_dead && (comment.dead || comment.deleted))
+    }
+
+    /// The comments to show for `node`, without their children: none, the
+    /// node, or with a `grep`, the node preceded by the comments it replies
+    /// to which were not shown yet.
+    pub fn select(&mut self, node: &CommentNode) -> Vec<CommentNode> {
+        let shallow = CommentNode {
+            children: vec![],
+            comment: node.comment.clone(),
+            ..*node
+        };
+        if !self.matches(&node.comment) {
+            if self.grep.is_some() {
+                self.held.insert(node.comment.id, shallow);
+            }
+            return vec![];
+        }
+        if self.grep.is_none() {
+            return vec![shallow];
+        }
+
+        let mut selected = vec![shallow];
+        let mut parent = node.comment.parent;
+        while let Some(id) = parent {
+            if self.shown.contains(&id) {
+                break;
+            }
+            match self.held.remove(&id) {
+                Some(ancestor) => {
+                    parent = ancestor.comment.parent;
+                    selected.push(ancestor);
+                }
+                None => break,
+            }
+        }
+        selected.reverse();
+        self.shown
+            .extend(selected.iter().map(|node| node.comment.id));
+
+        selected
+    }
+}
+
 #[derive(Debug)]
 pub struct CommentWalker<'a> {
     stack: Vec<&'a CommentNode>,
@@ -42,7 +208,8 @@ impl<'a> CommentWalker<'a> {
     #[allow(dead_code)]
     fn new(thread: &'a Thread) -> Self {
         let mut stack = Vec::new();
-        stack.extend(thread.comments.iter());
+        // popped from the end, so push in reverse to walk in ranked order
+        stack.extend(thread.comments.iter().rev());
 
         CommentWalker { stack }
     }
@@ -57,7 +224,7 @@ impl<'a> Iterator for CommentWalker<'a> {
             self.stack.push(child);
         }
 
-        Some(&node)
+        Some(node)
     }
 }
 
@@ -66,6 +233,196 @@ impl Thread {
     pub fn walk(&self) -> CommentWalker<'_> {
         CommentWalker::new(self)
     }
+
+    /// The story the thread belongs to.
+    pub fn top(&self) -> &Story {
+        &self.top
+    }
+
+    /// The top level comments, in ranked display order.
+    pub fn comments(&self) -> &[CommentNode] {
+        &self.comments
+    }
+
+    /// Load the full comment thread of the story `id` from an [ItemSource].
+    /// Comments the source does not have are left out of the thread.
+    #[tracing::instrument(skip(source))]
+    pub async fn load(source: Arc<dyn ItemSource>, id: Id) -> Result<Thread, Box<dyn Error>> {
+        Self::load_with(source, id, LoadOptions::default()).await
+    }
+
+    /// Like [Thread::load], stopping at the limits of `options`.
+    #[tracing::instrument(skip(source))]
+    pub async fn load_with(
+        source: Arc<dyn ItemSource>,
+        id: Id,
+        options: LoadOptions,
+    ) -> Result<Thread, Box<dyn Error>> {
+        let top = load_story(source.as_ref(), id).await?;
+        let thread = load_thread(source, top, options).await;
+
+        Ok(thread)
+    }
+}
+
+/// Retrieve the top level [Story] of a thread, following the parents of a
+/// comment up to the story it was made on.
+async fn load_story(source: &dyn ItemSource, id: Id) -> Result<Story, Box<dyn Error>> {
+    let mut next = id;
+    let mut visited = HashSet::new();
+    while visited.insert(next) {
+        let item = source
+            .get_item(next)
+            .await?
+            .ok_or_else(|| HnError::NotFoundError(format!("item {}", next)))?;
+        match item {
+            Item::Story(story) => return Ok(story),
+            Item::Comment(Comment {
+                parent: Some(parent),
+                ..
+            }) => next = parent,
+            _ => break,
+        }
+    }
+
+    Err(Box::new(HnError::ListingError(Some(
+        "threads can only be loaded from a story, or a comment on one",
+    ))))
+}
+
+fn build_thread(
+    mut root: CommentNode,
+    comment_map: &mut CommentMap,
+    collapsed: &HashMap<Id, usize>,
+) -> CommentNode {
+    root.collapsed = collapsed.get(&root.comment.id).copied().unwrap_or(0);
+    if let Some(ref kids) = root.comment.kids {
+        for kid in kids.iter() {
+            let comment = match comment_map.remove(kid) {
+                Some(comment) => comment,
+                None => continue,
+            };
+            let child = CommentNode::new(root.depth + 1, comment, vec![]);
+            let child = build_thread(child, comment_map, collapsed);
+            root.children.push(child);
+        }
+    }
+
+    root
+}
+
+/// Times a comment is fetched again after a transient failure, before it is
+/// left out of the thread.
+const MAX_RETRIES: usize = 3;
+
+/// Whether a failed fetch may succeed when retried, i.e. a dropped connection
+/// or an overloaded server rather than a corrupt cache file.
+fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    if let Some(err) = err.downcast_ref::<HnError>() {
+        return match err {
+            HnError::NetworkError(_) => true,
+            HnError::HttpError(err) => err.code == 429 || err.code >= 500,
+            HnError::Context(_context, err) => is_transient(err.as_ref()),
+            _ => false,
+        };
+    }
+
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(|err| !err.is_decode())
+}
+
+async fn load_thread(source: Arc<dyn ItemSource>, top: Story, options: LoadOptions) -> Thread {
+    let comments = Arc::new(Mutex::new(CommentMap::new()));
+    let mut collapsed = HashMap::new();
+    // ids to fetch, with their depth and the number of failed attempts
+    let mut queue = VecDeque::new();
+    if options.max_depth != Some(0) {
+        if let Some(ref kids) = top.kids {
+            queue.extend(kids.iter().map(|kid| (*kid, 0, 0)));
+        }
+    }
+    let mut in_flight = FuturesUnordered::new();
+    let deadline = options
+        .timeout
+        .map(|timeout| tokio::time::Instant::now() + timeout);
+
+    loop {
+        while let Some((id, depth, attempts)) = queue.pop_front() {
+            debug!(id=?id, "initiating request");
+            let source = source.clone();
+            in_flight.push(async move { (id, depth, attempts, source.get_item(id).await) });
+        }
+
+        let next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, in_flight.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    info!(pending = in_flight.len(), "timed out loading thread");
+                    break;
+                }
+            },
+            None => in_flight.next().await,
+        };
+        match next {
+            Some((_id, depth, _attempts, Ok(Some(item)))) => {
+                let id = item.id();
+                debug!(item_id=?item.id(), "fetched item");
+                let comment = match item {
+                    Item::Comment(comment) => comment,
+                    _ => {
+                        warn!(item=?item, "while loading comment thread, got non-comment item. discarding.");
+                        continue;
+                    }
+                };
+                if let Some(ref kids) = comment.kids {
+                    let (replies, hidden) = options.replies(depth, kids);
+                    for kid in replies {
+                        debug!(kid=?kid, "queueing new id");
+                        queue.push_back((*kid, depth + 1, 0));
+                    }
+                    if hidden > 0 {
+                        collapsed.insert(id, hidden);
+                    }
+                }
+                comments.lock().await.insert(id, comment);
+            }
+            Some((id, _depth, _attempts, Ok(None))) => {
+                warn!(id=?id, "comment not found in source, skipping");
+            }
+            Some((id, depth, attempts, Err(err))) => {
+                if attempts < MAX_RETRIES && is_transient(err.as_ref()) {
+                    warn!(err=?err, id=?id, "fetch comment failed, requeue");
+                    queue.push_back((id, depth, attempts + 1));
+                } else {
+                    warn!(err=?err, id=?id, "fetch comment failed, skipping");
+                }
+            }
+            None => {
+                debug!("exhausted in_flight, breaking");
+                break;
+            }
+        }
+    }
+
+    let mut comment_map = Arc::try_unwrap(comments).unwrap().into_inner();
+    let mut thread = Thread {
+        top,
+        comments: vec![],
+    };
+    if let Some(ref kids) = thread.top.kids {
+        for kid in kids {
+            let comment = match comment_map.remove(kid) {
+                Some(comment) => comment,
+                None => continue,
+            };
+            // todo: kind of weird mechanics around create and build
+            let child = CommentNode::new(0, comment, vec![]);
+            let child = build_thread(child, &mut comment_map, &collapsed);
+            thread.comments.push(child);
+        }
+    }
+
+    thread
 }
 
 #[derive(Debug)]
@@ -73,72 +430,103 @@ pub struct LazyThread {
     pub top: Story,
     // now store fully‑formed nodes so callers can get depth later
     pub comment_map: Arc<Mutex<HashMap<Id, Arc<CommentNode>>>>,
-    client: Arc<Client>,
+    source: Arc<dyn ItemSource>,
 }
 
 impl LazyThread {
-    pub fn new(top: Story, client: Arc<Client>) -> Self {
+    pub fn new(top: Story, source: Arc<dyn ItemSource>) -> Self {
         Self {
             top,
-            client,
+            source,
             comment_map: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Retrieve the story `id` from an [ItemSource], without loading any of
+    /// its comments yet.
+    #[tracing::instrument(skip(source))]
+    pub async fn load(source: Arc<dyn ItemSource>, id: Id) -> Result<LazyThread, Box<dyn Error>> {
+        let top = load_story(source.as_ref(), id).await?;
+
+        Ok(LazyThread::new(top, source))
+    }
+
     /// walk the thread breadth‑first, lazily fetching comments.
     /// every item carries its depth so callers can indent / pretty‑print.
     pub fn walk(self) -> impl Stream<Item = Result<Arc<CommentNode>, Box<dyn Error>>> {
+        self.walk_with(LoadOptions::default())
+    }
+
+    /// Like [LazyThread::walk], never fetching the comments beyond the
+    /// `max_depth` and `collapse_after` limits of `options`. The `timeout` is
+    /// left to the caller, which may stop walking at any time.
+    pub fn walk_with(
+        self,
+        options: LoadOptions,
+    ) -> impl Stream<Item = Result<Arc<CommentNode>, Box<dyn Error>>> {
         // clone shared state for the unfolding stream
-        let client = self.client.clone();
+        let client = self.source.clone();
         let comment_map = self.comment_map.clone();
 
-        // queue holds (comment_id, depth) so we can build CommentNode on the fly
-        let mut q: VecDeque<(Id, usize)> = VecDeque::new();
-        if let Some(ref kids) = self.top.kids {
-            q.extend(kids.iter().map(|id| (*id, 0)));
+        // queue holds (comment_id, depth, failed attempts) so we can build
+        // CommentNode on the fly
+        let mut q: VecDeque<(Id, usize, usize)> = VecDeque::new();
+        if let (Some(ref kids), false) = (&self.top.kids, options.max_depth == Some(0)) {
+            q.extend(kids.iter().map(|id| (*id, 0, 0)));
         }
 
         stream::unfold(
             (client, comment_map, q),
-            |(client, comment_map, mut q)| async move {
-                loop {
-                    // grab next work item
-                    let (next_id, depth) = match q.pop_front() {
-                        Some(t) => t,
-                        None => return None, // finished
-                    };
-
-                    match client.item(next_id).await {
-                        Ok(item) => match item {
-                            Item::Comment(comment) => {
-                                // enqueue children with depth+1
-                                if let Some(ref kids) = comment.kids {
-                                    q.extend(kids.iter().map(|kid| (*kid, depth + 1)));
-                                }
-
-                                // wrap into a CommentNode so callers get depth
-                                let node = Arc::new(CommentNode::new(depth, comment, vec![]));
-
-                                // remember it for any later tree‑building needs
-                                {
-                                    let mut guard = comment_map.lock().await;
-                                    guard.insert(next_id, node.clone());
-                                }
-
-                                return Some((Ok(node), (client.clone(), comment_map.clone(), q)));
+            move |(client, comment_map, mut q)| async move {
+                // grab next work item
+                let (next_id, depth, attempts) = match q.pop_front() {
+                    Some(t) => t,
+                    None => return None, // finished
+                };
+
+                match client.get_item(next_id).await {
+                    Ok(None) => {
+                        let err: Box<dyn Error> =
+                            Box::new(HnError::NotFoundError(format!("comment {}", next_id)));
+                        Some((Err(err), (client.clone(), comment_map.clone(), q)))
+                    }
+                    Ok(Some(item)) => match item {
+                        Item::Comment(comment) => {
+                            // enqueue children with depth+1
+                            let mut collapsed = 0;
+                            if let Some(ref kids) = comment.kids {
+                                let (replies, hidden) = options.replies(depth, kids);
+                                q.extend(replies.iter().map(|kid| (*kid, depth + 1, 0)));
+                                collapsed = hidden;
                             }
-                            // should never happen inside a comment thread
-                            other => {
-                                let err: Box<dyn Error> =
-                                    format!("expected comment, got {:?}", other).into();
-                                return Some((Err(err), (client.clone(), comment_map.clone(), q)));
+
+                            // wrap into a CommentNode so callers get depth
+                            let mut node = CommentNode::new(depth, comment, vec![]);
+                            node.collapsed = collapsed;
+                            let node = Arc::new(node);
+
+                            // remember it for any later tree‑building needs
+                            {
+                                let mut guard = comment_map.lock().await;
+                                guard.insert(next_id, node.clone());
                             }
-                        },
-                        // network / parse error: re‑queue and yield the error
-                        Err(e) => {
-                            q.push_back((next_id, depth));
-                            return Some((Err(e), (client.clone(), comment_map.clone(), q)));
+
+                            Some((Ok(node), (client.clone(), comment_map.clone(), q)))
+                        }
+                        // should never happen inside a comment thread
+                        other => {
+                            let err: Box<dyn Error> =
+                                format!("expected comment, got {:?}", other).into();
+                            Some((Err(err), (client.clone(), comment_map.clone(), q)))
                         }
+                    },
+                    // yield the error, re‑queueing the comment when the
+                    // failure may be transient
+                    Err(e) => {
+                        if attempts < MAX_RETRIES && is_transient(e.as_ref()) {
+                            q.push_back((next_id, depth, attempts + 1));
+                        }
+                        Some((Err(e), (client.clone(), comment_map.clone(), q)))
                     }
                 }
             },
@@ -146,11 +534,18 @@ impl LazyThread {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct CommentNode {
     pub depth: usize,
     pub comment: Comment,
     pub children: Vec<CommentNode>,
+    /// The number of replies left unloaded by [LoadOptions::collapse_after].
+    #[serde(skip_serializing_if = "is_zero")]
+    pub collapsed: usize,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
 }
 
 impl CommentNode {
@@ -159,6 +554,7 @@ impl CommentNode {
             depth,
             comment,
             children,
+            collapsed: 0,
         }
     }
 }
@@ -170,11 +566,38 @@ impl Client {
     pub fn new() -> Self {
         Self {
             http_client: ReqwestClient::new(),
+            concurrency: 10,
+            throttle: None,
         }
     }
 
+    /// Send at most `concurrency` requests at a time from [Client::items].
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How many requests [Client::items] sends at a time.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Send at most `per_second` requests per second. Zero is unlimited.
+    pub fn with_rate_limit(mut self, per_second: u32) -> Self {
+        self.throttle = (per_second > 0).then(|| {
+            Arc::new(Throttle {
+                interval: Duration::from_secs(1) / per_second,
+                next: Mutex::new(Instant::now()),
+            })
+        });
+        self
+    }
+
     /// Send an HTTP request.
     async fn send(&self, req: Request) -> Result<Response, Box<dyn Error>> {
+        if let Some(ref throttle) = self.throttle {
+            throttle.wait().await;
+        }
         let resp = self.http_client.execute(req).await?;
         let status = resp.status().as_u16();
         if status != 200 {
@@ -208,6 +631,10 @@ impl Client {
         let resp = self.get(&url).await?;
         let text = resp.text().await?;
         log::debug!("text = {:?}", text);
+        // The API answers `null` for items which do not exist (yet)
+        if text.trim() == "null" {
+            return Err(Box::new(HnError::NotFoundError(format!("item {}", id))));
+        }
         let item: Item = serde_json::from_str(&text)?;
         log::debug!("item = {:?}", item);
 
@@ -216,111 +643,18 @@ impl Client {
 
     #[tracing::instrument(skip(self))]
     pub async fn thread(&self, id: Id) -> Result<Thread, Box<dyn Error>> {
-        let item = self.item(id).await?;
-        let top = match item {
-            Item::Story(story) => story,
-            _ => unimplemented!("currently only support loading a thread from a Story"),
-        };
-        let thread = self.load_thread(top).await;
-
-        Ok(thread)
+        Thread::load(Arc::new(self.clone()), id).await
     }
 
     #[tracing::instrument(skip(self))]
     pub async fn lazy_thread(&self, id: Id) -> Result<LazyThread, Box<dyn Error>> {
-        let item = self.item(id).await?;
-        let top = match item {
-            Item::Story(story) => story,
-            _ => unimplemented!("currently only support loading a thread from a Story"),
-        };
-        let lazy_thread = LazyThread::new(top, Arc::new(self.clone()));
-
-        Ok(lazy_thread)
-    }
-
-    fn build_thread(mut root: CommentNode, comment_map: &mut CommentMap) -> CommentNode {
-        if let Some(ref kids) = root.comment.kids {
-            for kid in kids.iter() {
-                let comment = comment_map.remove(kid).expect("comment not loaded");
-                let child = CommentNode::new(root.depth + 1, comment, vec![]);
-                let child = Self::build_thread(child, comment_map);
-                root.children.push(child);
-            }
-        }
-
-        root
-    }
-
-    async fn load_thread(&self, top: Story) -> Thread {
-        let comments = Arc::new(Mutex::new(CommentMap::new()));
-        let mut queue = VecDeque::new();
-        if let Some(ref kids) = top.kids {
-            queue.extend(kids.iter());
-        }
-        let mut in_flight = FuturesUnordered::new();
-
-        loop {
-            while let Some(id) = queue.pop_front() {
-                debug!(id=?id, "initiating request");
-                let client = self.clone();
-                in_flight.push(async move { (id, client.item(id).await) });
-            }
-
-            match in_flight.next().await {
-                Some((_id, Ok(item))) => {
-                    let id = item.id();
-                    debug!(item_id=?item.id(), "fetched item");
-                    let comment = match item {
-                        Item::Comment(comment) => comment,
-                        _ => {
-                            warn!(item=?item, "while loading comment thread, got non-comment item. discarding.");
-                            continue;
-                        }
-                    };
-                    if let Some(ref kids) = comment.kids {
-                        for kid in kids {
-                            debug!(kid=?kid, "queueing new id");
-                            queue.push_back(*kid);
-                        }
-                    }
-                    comments.lock().await.insert(id, comment);
-                }
-                Some((id, Err(err))) => {
-                    tracing::warn!(err=?err, id=?id, "fetch comment failed, requeue");
-                    queue.push_back(id);
-                }
-                None => {
-                    debug!("exhausted in_flight, breaking");
-                    break;
-                }
-            }
-        }
-
-        let mut comment_map = Arc::try_unwrap(comments).unwrap().into_inner();
-        let mut thread = Thread {
-            top,
-            comments: vec![],
-        };
-        if let Some(ref kids) = thread.top.kids {
-            for kid in kids {
-                let comment = comment_map.remove(kid).expect("comment not loaded");
-                // todo: kind of weird mechanics around create and build
-                let child = CommentNode::new(0, comment, vec![]);
-                let child = Self::build_thread(child, &mut comment_map);
-                thread.comments.push(child);
-            }
-        }
-
-        thread
+        LazyThread::load(Arc::new(self.clone()), id).await
     }
 
-    pub async fn items(
-        &self,
-        ids: &[Id],
-        // max_concurrent: Option<usize>
-    ) -> Result<Vec<Item>, Box<dyn Error>> {
+    /// Retrieve several [Item]s, at most [Client::with_concurrency] at a time.
+    pub async fn items(&self, ids: &[Id]) -> Result<Vec<Item>, Box<dyn Error>> {
         let client = Arc::new(self);
-        let limit = 10;
+        let limit = self.concurrency;
 
         // Convert the vector of IDs into a stream of futures
         let stream = stream::iter(ids)
@@ -364,6 +698,13 @@ impl Client {
         let resp = self.get(&url).await?;
         let text = resp.text().await?;
         log::debug!("text = {:?}", text);
+        // The API answers `null` for users which do not exist
+        if text.trim() == "null" {
+            return Err(Box::new(HnError::NotFoundError(format!(
+                "user {}",
+                username
+            ))));
+        }
         let user: User = serde_json::from_str(&text)?;
         log::debug!("user = {:?}", user);
 
@@ -377,6 +718,23 @@ impl Client {
     //
     // Or alteratively, a proc macro which will generate these.
 
+    /// Retrieve the ids of one of the [StoryList]s.
+    pub async fn stories(&self, list: StoryList) -> Result<Vec<Id>, Box<dyn Error>> {
+        let url = format!(
+            "{base_url}/{endpoint}.json",
+            base_url = BASE_URL,
+            endpoint = list.endpoint()
+        );
+
+        let resp = self.get(&url).await?;
+        let text = resp.text().await?;
+        log::debug!("text = {:?}", text);
+        let ids: Vec<Id> = serde_json::from_str(&text)?;
+        log::debug!("ids = {:?}", ids);
+
+        Ok(ids)
+    }
+
     pub async fn new_stories(&self) -> Result<Vec<Id>, Box<dyn Error>> {
         let url = format!("{base_url}/newstories.json", base_url = BASE_URL,);
 
@@ -401,6 +759,10 @@ impl Client {
         Ok(ids)
     }
 
+    pub async fn best_stories(&self) -> Result<Vec<Id>, Box<dyn Error>> {
+        self.stories(StoryList::Best).await
+    }
+
     pub async fn updates(&self) -> Result<(Vec<Id>, Vec<String>), Box<dyn Error>> {
         let url = format!("{base_url}/updates.json", base_url = BASE_URL,);
 
@@ -455,9 +817,153 @@ impl Client {
 #[cfg(test)]
 mod tests {
 
-    use super::Client;
+    use super::{Client, CommentFilter, LazyThread, LoadOptions, Thread, MAX_RETRIES};
+    use crate::api::{Id, Item, Story, StoryList, User};
+    use crate::cli::ids::parse_id;
+    use crate::error::HnError;
+    use crate::source::ItemSource;
+    use crate::testing::{comment, store_source, story};
     use crate::util::setup;
+    use async_trait::async_trait;
+    use futures::stream::StreamExt;
+    use regex::Regex;
+    use std::collections::HashMap;
     use std::error::Error;
+    use std::sync::{Arc, Mutex};
+
+    /// A story 1 with comments 2 and 3, replies 4, 5 and 6 to 2, and a
+    /// reply 7 to 4.
+    async fn thread_source() -> Result<Arc<dyn ItemSource>, Box<dyn Error>> {
+        let source = store_source([
+            Item::Story(Story {
+                title: "Threads".to_string(),
+                descendants: Some(6),
+                ..story(1, vec![2, 3])
+            }),
+            comment(2, 1, vec![4, 5, 6], "first"),
+            comment(3, 1, vec![], "second"),
+            comment(4, 2, vec![7], "a reply"),
+            comment(5, 2, vec![], "another reply"),
+            comment(6, 2, vec![], "last reply"),
+            comment(7, 4, vec![], "a <i>needle</i>"),
+        ])
+        .await?;
+
+        Ok(source)
+    }
+
+    /// Fails to get comment 2 with a corrupt cache file, and comment 3 with
+    /// a network error, counting the attempts.
+    #[derive(Debug)]
+    struct Failing {
+        source: Arc<dyn ItemSource>,
+        attempts: Mutex<HashMap<Id, usize>>,
+    }
+
+    #[async_trait(?Send)]
+    impl ItemSource for Failing {
+        async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+            *self.attempts.lock().unwrap().entry(id).or_default() += 1;
+            match id {
+                2 => Err(Box::new(HnError::SerializationError(Some("corrupt")))),
+                3 => Err(Box::new(HnError::NetworkError(None))),
+                _ => self.source.get_item(id).await,
+            }
+        }
+
+        async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+            self.source.get_user(username).await
+        }
+
+        async fn list_ids(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+            self.source.list_ids(list).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_gives_up_on_failing_comments() -> Result<(), Box<dyn Error>> {
+        let failing = Arc::new(Failing {
+            source: thread_source().await?,
+            attempts: Mutex::new(HashMap::new()),
+        });
+        let thread = Thread::load(failing.clone(), 1).await?;
+        assert!(thread.comments().is_empty());
+        assert_eq!(failing.attempts.lock().unwrap()[&2], 1);
+        assert_eq!(failing.attempts.lock().unwrap()[&3], MAX_RETRIES + 1);
+
+        failing.attempts.lock().unwrap().clear();
+        let results: Vec<_> = LazyThread::load(failing.clone(), 1)
+            .await?
+            .walk()
+            .collect()
+            .await;
+        assert!(results.iter().all(|result| result.is_err()));
+        assert_eq!(failing.attempts.lock().unwrap()[&2], 1);
+        assert_eq!(failing.attempts.lock().unwrap()[&3], MAX_RETRIES + 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_from_comment() -> Result<(), Box<dyn Error>> {
+        let source = thread_source().await?;
+        // as the thread commands get a comment anchor of an item URL
+        let id = parse_id("https://news.ycombinator.com/item?id=1#7").map_err(|err| *err)?;
+        let thread = Thread::load(source.clone(), id).await?;
+        assert_eq!(thread.top().id, 1);
+        assert_eq!(thread.walk().count(), 6);
+        assert_eq!(LazyThread::load(source.clone(), 4).await?.top.id, 1);
+        assert!(Thread::load(source, 8).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_options_and_filter() -> Result<(), Box<dyn Error>> {
+        let options = LoadOptions {
+            collapse_after: Some(1),
+            ..LoadOptions::default()
+        };
+        let thread = Thread::load_with(thread_source().await?, 1, options).await?;
+        let ids: Vec<(u64, usize)> = thread
+            .walk()
+            .map(|node| (node.comment.id, node.collapsed))
+            .collect();
+        assert_eq!(ids, vec![(2, 2), (4, 0), (7, 0), (3, 0)]);
+
+        let options = LoadOptions {
+            max_depth: Some(2),
+            ..LoadOptions::default()
+        };
+        let stream = LazyThread::load(thread_source().await?, 1)
+            .await?
+            .walk_with(options);
+        let ids: Vec<u64> = stream
+            .map(|node| node.map(|node| node.comment.id).unwrap_or(0))
+            .collect()
+            .await;
+        assert_eq!(ids, vec![2, 3, 4, 5, 6]);
+
+        // grep keeps the comments a match replies to, once
+        let thread = Thread::load(thread_source().await?, 1).await?;
+        let mut filter = CommentFilter::new().with_grep(Regex::new("needle|last")?);
+        let ids: Vec<u64> = thread
+            .walk()
+            .flat_map(|node| filter.select(node))
+            .map(|node| node.comment.id)
+            .collect();
+        assert_eq!(ids, vec![2, 4, 7, 6]);
+
+        let mut filter = CommentFilter::new().with_min_replies(1);
+        let ids: Vec<u64> = thread
+            .walk()
+            .flat_map(|node| filter.select(node))
+            .map(|node| node.comment.id)
+            .collect();
+        assert_eq!(ids, vec![2, 4]);
+
+        Ok(())
+    }
 
     #[tokio::test]
     async fn test_item() -> Result<(), Box<dyn Error>> {
@@ -522,6 +1028,17 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_best_stories() -> Result<(), Box<dyn Error>> {
+        setup();
+
+        let client = Client::new();
+        let ids = client.best_stories().await?;
+        log::debug!("ids = {:?}", ids);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_updates() -> Result<(), Box<dyn Error>> {
         setup();
@@ -0,0 +1,1337 @@
+//! Client for the HTML pages of news.ycombinator.com.
+//!
+//! Several listings, such as historical front pages, the active stories or the
+//! best comments, are not exposed by the Firebase API. This [Client] fetches
+//! those pages and scrapes them into the same [api](crate::api) types the API
+//! client returns. Fields a page does not show, i.e. the `kids` of an item,
+//! are left empty.
+
+use crate::api::{Comment, Id, Item, Job, Story};
+use crate::auth::AuthSession;
+use crate::error::{HnError, HttpError};
+use reqwest::header::{COOKIE, LOCATION, SET_COOKIE};
+use reqwest::redirect::Policy;
+use reqwest::Client as ReqwestClient;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::time::UNIX_EPOCH;
+use tracing::debug;
+
+pub const BASE_URL: &str = "https://news.ycombinator.com";
+
+/// An [Item] listed on a page, along with its rank on that page.
+#[derive(Debug)]
+pub struct RankedItem {
+    /// One based position of the item. Pages which are not ranked, i.e. the
+    /// story pool, have no rank.
+    pub rank: Option<u32>,
+    pub item: Item,
+}
+
+/// An action on an item, performed as the logged in user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Upvote,
+    Downvote,
+    Unvote,
+    Favorite,
+    Unfavorite,
+    Hide,
+    Unhide,
+}
+
+impl Action {
+    /// Path of the link performing the action.
+    fn path(&self) -> &'static str {
+        match self {
+            Action::Upvote | Action::Downvote | Action::Unvote => "vote",
+            Action::Favorite | Action::Unfavorite => "fave",
+            Action::Hide | Action::Unhide => "hide",
+        }
+    }
+
+    /// Query parameter telling the action's link apart from the other links
+    /// with the same path.
+    fn marker(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Action::Upvote => Some(("how", "up")),
+            Action::Downvote => Some(("how", "down")),
+            Action::Unvote => Some(("how", "un")),
+            Action::Unfavorite | Action::Unhide => Some(("un", "t")),
+            Action::Favorite | Action::Hide => None,
+        }
+    }
+
+    fn not_allowed(&self, id: Id) -> HnError {
+        let reason = match self {
+            Action::Downvote => ", downvoting needs more karma and is only possible on comments",
+            Action::Upvote => ", it may already be voted on or be your own",
+            Action::Unvote => ", it is not voted on",
+            _ => "",
+        };
+        HnError::NotAllowedError(format!("Cannot {} item {}{}", self, id, reason))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self {
+            Action::Upvote => "upvote",
+            Action::Downvote => "downvote",
+            Action::Unvote => "unvote",
+            Action::Favorite => "favorite",
+            Action::Unfavorite => "unfavorite",
+            Action::Hide => "hide",
+            Action::Unhide => "unhide",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+/// Which items of a user's page to list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listed {
+    Stories,
+    Comments,
+}
+
+/// A form scraped from a page, with the values of its hidden inputs such as
+/// the `hmac` or `fnid` tokens HN checks when the form is posted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Form {
+    /// Path the form is posted to, i.e. `comment`.
+    pub action: String,
+    pub fields: Vec<(String, String)>,
+    /// Content of the form's textarea, i.e. the current text of a comment
+    /// being edited.
+    pub text: Option<String>,
+}
+
+/// Response to a posted form. HN redirects after accepting a form, and renders
+/// a page explaining the problem otherwise.
+#[derive(Debug)]
+struct Posted {
+    location: Option<String>,
+    body: String,
+}
+
+impl Posted {
+    /// Fail with [HnError::NotAllowedError] unless HN accepted the form by
+    /// redirecting.
+    fn accepted(&self, rejected: impl FnOnce() -> String) -> Result<(), Box<dyn Error>> {
+        if self.location.is_none() {
+            debug!(body = self.body, "form rejected");
+            return Err(Box::new(HnError::NotAllowedError(rejected())));
+        }
+
+        Ok(())
+    }
+}
+
+/// A client scraping the HTML pages of Hacker News.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http_client: ReqwestClient,
+    base_url: String,
+    session: Option<AuthSession>,
+}
+
+#[allow(clippy::new_without_default)]
+impl Client {
+    pub fn new() -> Self {
+        Self::with_base_url(BASE_URL)
+    }
+
+    /// Scrape a different host, i.e. a mock server in tests.
+    pub fn with_base_url(base_url: impl Into<String>) -> Self {
+        Self {
+            http_client: ReqwestClient::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            session: None,
+        }
+    }
+
+    /// Authenticate requests as the user of `session`.
+    pub fn with_session(mut self, session: AuthSession) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    pub fn session(&self) -> Option<&AuthSession> {
+        self.session.as_ref()
+    }
+
+    /// The session of the client, for actions which require being logged in.
+    pub fn require_session(&self) -> Result<&AuthSession, HnError> {
+        self.session.as_ref().ok_or(HnError::UnauthenticatedError)
+    }
+
+    /// Log in with the HN login form. On success the returned session is also
+    /// used by this client.
+    ///
+    /// HN answers a successful login with a redirect setting the `user`
+    /// cookie, and a failed one with the login page again, explaining why.
+    #[tracing::instrument(skip(self, password))]
+    pub async fn login(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<AuthSession, Box<dyn Error>> {
+        // Redirects are not followed, so the cookie of the login response is seen.
+        let http_client = ReqwestClient::builder().redirect(Policy::none()).build()?;
+        let resp = http_client
+            .post(format!("{}/login", self.base_url))
+            .form(&[("acct", username), ("pw", password), ("goto", "news")])
+            .send()
+            .await?;
+        let status = resp.status();
+        let cookie = resp
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| value.split(';').next())
+            .find_map(|pair| pair.trim().strip_prefix("user="))
+            .filter(|value| !value.is_empty())
+            .map(str::to_string);
+        if let Some(cookie) = cookie {
+            let session = AuthSession::new(username, cookie);
+            self.session = Some(session.clone());
+            return Ok(session);
+        }
+
+        if !status.is_success() && !status.is_redirection() {
+            let err = HttpError::new(status.as_u16(), resp.url().to_string());
+            return Err(Box::new(HnError::HttpError(err)));
+        }
+        let body = resp.text().await.unwrap_or_default();
+        let reason = if body.contains("Bad login") {
+            Some("Bad username or password")
+        } else if body.contains("recaptcha") || body.contains("Validation required") {
+            Some("HN requires solving a captcha, log in once with a browser and retry")
+        } else {
+            None
+        };
+
+        Err(Box::new(HnError::AuthenticationError(reason)))
+    }
+
+    /// Fetch the HTML of a page, with `page` as the one based page number.
+    async fn get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        page: u32,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut url = Url::parse(&format!("{}/{}", self.base_url, path))?;
+        url.query_pairs_mut().extend_pairs(query);
+        if page > 1 {
+            url.query_pairs_mut().append_pair("p", &page.to_string());
+        }
+        if url.query() == Some("") {
+            url.set_query(None);
+        }
+        self.fetch(url).await
+    }
+
+    /// Fetch the HTML of the page at `url`.
+    #[tracing::instrument(skip(self))]
+    async fn fetch(&self, url: Url) -> Result<String, Box<dyn Error>> {
+        let mut req = self.http_client.get(url);
+        if let Some(ref session) = self.session {
+            req = req.header(COOKIE, session.cookie_header());
+        }
+        let resp = req.send().await?;
+        let status = resp.status().as_u16();
+        if status != 200 {
+            let err = HttpError::new(status, resp.url().to_string());
+            log::error!("Recieved non 200 status: {:?}", err);
+            return Err(Box::new(HnError::HttpError(err)));
+        }
+        let html = resp.text().await?;
+        debug!(bytes = html.len(), "fetched page");
+
+        Ok(html)
+    }
+
+    /// Fetch the page at `path`, and follow its "More" links for up to
+    /// `max_pages` pages, collecting the listed items.
+    async fn collect_pages(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        listed: Listed,
+        max_pages: u32,
+    ) -> Result<Vec<Item>, Box<dyn Error>> {
+        let mut url = Url::parse(&format!("{}/{}", self.base_url, path))?;
+        url.query_pairs_mut().extend_pairs(query);
+        let mut items = vec![];
+        for page in 1..=max_pages {
+            let html = self.fetch(url.clone()).await?;
+            match listed {
+                Listed::Stories => {
+                    items.extend(parse_stories(&html)?.into_iter().map(|ranked| ranked.item))
+                }
+                Listed::Comments => {
+                    items.extend(parse_comments(&html)?.into_iter().map(Item::Comment))
+                }
+            }
+            match more_link(&html) {
+                Some(href) if page < max_pages => url = url.join(&href)?,
+                _ => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn stories(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        page: u32,
+    ) -> Result<Vec<RankedItem>, Box<dyn Error>> {
+        let html = self.get(path, query, page).await?;
+        Ok(parse_stories(&html)?)
+    }
+
+    /// The front page, as currently ranked.
+    pub async fn news(&self, page: u32) -> Result<Vec<RankedItem>, Box<dyn Error>> {
+        self.stories("news", &[], page).await
+    }
+
+    /// The front page as it was at the end of `day`, formatted as YYYY-MM-DD.
+    pub async fn front(&self, day: &str, page: u32) -> Result<Vec<RankedItem>, Box<dyn Error>> {
+        humantime::parse_rfc3339_weak(&format!("{}T00:00:00", day))
+            .map_err(|_| HnError::ArgumentError(Some("day must be formatted as YYYY-MM-DD")))?;
+        self.stories("front", &[("day", day)], page).await
+    }
+
+    /// Stories with the most active discussions.
+    pub async fn active(&self, page: u32) -> Result<Vec<RankedItem>, Box<dyn Error>> {
+        self.stories("active", &[], page).await
+    }
+
+    /// Stories submitted by new accounts.
+    pub async fn noob_stories(&self, page: u32) -> Result<Vec<RankedItem>, Box<dyn Error>> {
+        self.stories("noobstories", &[], page).await
+    }
+
+    /// The second chance pool of stories picked for another shot at the
+    /// front page.
+    pub async fn pool(&self, page: u32) -> Result<Vec<RankedItem>, Box<dyn Error>> {
+        self.stories("pool", &[], page).await
+    }
+
+    /// Perform `action` on the item `id` as the logged in user.
+    ///
+    /// The links performing actions carry a per item `auth` token, which is
+    /// scraped from the item page first. HN only shows the links of actions
+    /// the user may take, so a missing link means the action is not allowed.
+    #[tracing::instrument(skip(self))]
+    pub async fn act(&self, id: Id, action: Action) -> Result<(), Box<dyn Error>> {
+        self.require_session()?;
+        let item_id = id.to_string();
+        let html = self.get("item", &[("id", &item_id)], 1).await?;
+        if html.contains("No such item.") {
+            return Err(Box::new(HnError::NotFoundError(format!("item {}", id))));
+        }
+        if !is_logged_in(&html) {
+            return Err(Box::new(HnError::AuthenticationError(Some(
+                "The saved session has expired, log in again",
+            ))));
+        }
+        let auth = parse_auth_token(&html, id, action).ok_or_else(|| action.not_allowed(id))?;
+
+        let goto = format!("item?id={}", id);
+        let mut query = vec![("id", item_id.as_str()), ("auth", &auth), ("goto", &goto)];
+        query.extend(action.marker());
+        let body = self.get(action.path(), &query, 1).await?;
+        if body.contains("Can't make that vote") {
+            return Err(Box::new(action.not_allowed(id)));
+        }
+        debug!(id = id, action = %action, "performed action");
+
+        Ok(())
+    }
+
+    pub async fn upvote(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Upvote).await
+    }
+
+    pub async fn downvote(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Downvote).await
+    }
+
+    pub async fn unvote(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Unvote).await
+    }
+
+    pub async fn favorite(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Favorite).await
+    }
+
+    pub async fn unfavorite(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Unfavorite).await
+    }
+
+    pub async fn hide(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Hide).await
+    }
+
+    pub async fn unhide(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        self.act(id, Action::Unhide).await
+    }
+
+    /// Fetch the page at `path` as the logged in user, and scrape the form
+    /// posted to `action` from it.
+    async fn form(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        action: &str,
+        not_allowed: impl FnOnce() -> String,
+    ) -> Result<Form, Box<dyn Error>> {
+        self.require_session()?;
+        let html = self.get(path, query, 1).await?;
+        if html.contains("No such item.") {
+            return Err(Box::new(HnError::NotFoundError(format!(
+                "item {}",
+                query.first().map(|(_, id)| *id).unwrap_or_default()
+            ))));
+        }
+        if !is_logged_in(&html) {
+            return Err(Box::new(HnError::AuthenticationError(Some(
+                "The saved session has expired, log in again",
+            ))));
+        }
+
+        parse_form(&html, action).ok_or_else(|| HnError::NotAllowedError(not_allowed()).into())
+    }
+
+    /// Post a scraped form along with the values of its visible inputs.
+    /// Redirects are not followed, so where HN redirects to can be inspected.
+    async fn post(&self, form: &Form, inputs: &[(&str, &str)]) -> Result<Posted, Box<dyn Error>> {
+        let session = self.require_session()?;
+        let mut fields: Vec<(&str, &str)> = form
+            .fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        fields.extend_from_slice(inputs);
+
+        let http_client = ReqwestClient::builder().redirect(Policy::none()).build()?;
+        let resp = http_client
+            .post(format!("{}/{}", self.base_url, form.action))
+            .header(COOKIE, session.cookie_header())
+            .form(&fields)
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() && !status.is_redirection() {
+            let err = HttpError::new(status.as_u16(), resp.url().to_string());
+            log::error!("Recieved non 200 status: {:?}", err);
+            return Err(Box::new(HnError::HttpError(err)));
+        }
+        let location = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim_start_matches('/').to_string());
+        let body = resp.text().await?;
+        if body.contains("too fast") {
+            return Err(Box::new(HnError::RateLimitError(
+                "You're posting too fast, wait a while before retrying".to_string(),
+            )));
+        }
+
+        Ok(Posted { location, body })
+    }
+
+    /// Reply to the story or comment `parent` as the logged in user. Returns
+    /// the id of the new comment.
+    #[tracing::instrument(skip(self, text))]
+    pub async fn reply(&self, parent: Id, text: &str) -> Result<Id, Box<dyn Error>> {
+        let parent_id = parent.to_string();
+        let goto = format!("item?id={}", parent);
+        let form = self
+            .form(
+                "reply",
+                &[("id", &parent_id), ("goto", &goto)],
+                "comment",
+                || format!("Cannot reply to item {}, it may be dead or too old", parent),
+            )
+            .await?;
+        let posted = self.post(&form, &[("text", text)]).await?;
+        posted.accepted(|| format!("HN rejected the reply to item {}", parent))?;
+
+        // HN may redirect to the new comment's anchor, otherwise look it up
+        // among the user's latest comments.
+        let anchored = posted
+            .location
+            .as_deref()
+            .and_then(|location| location.split_once('#'))
+            .and_then(|(_, anchor)| anchor.parse().ok());
+        if let Some(id) = anchored {
+            return Ok(id);
+        }
+        let username = &self.require_session()?.username;
+        let threads = self.get("threads", &[("id", username)], 1).await?;
+        parse_comments(&threads)?
+            .into_iter()
+            .find(|c| c.parent == Some(parent) && c.by.as_deref() == Some(username))
+            .map(|c| c.id)
+            .ok_or_else(|| HnError::NotFoundError(format!("the new reply to {}", parent)).into())
+    }
+
+    /// The current text of the comment `id`, as shown when editing it.
+    pub async fn edit_text(&self, id: Id) -> Result<String, Box<dyn Error>> {
+        let form = self.edit_form(id).await?;
+        Ok(form.text.unwrap_or_default())
+    }
+
+    async fn edit_form(&self, id: Id) -> Result<Form, Box<dyn Error>> {
+        self.form("edit", &[("id", &id.to_string())], "xedit", || {
+            format!(
+                "Cannot edit item {}, only your own recent comments can be edited",
+                id
+            )
+        })
+        .await
+    }
+
+    /// Replace the text of the comment `id`. Returns the id of the comment.
+    #[tracing::instrument(skip(self, text))]
+    pub async fn edit(&self, id: Id, text: &str) -> Result<Id, Box<dyn Error>> {
+        let form = self.edit_form(id).await?;
+        self.post(&form, &[("text", text)])
+            .await?
+            .accepted(|| format!("HN rejected the edit of item {}", id))?;
+
+        Ok(id)
+    }
+
+    /// Delete the comment `id`.
+    #[tracing::instrument(skip(self))]
+    pub async fn delete(&self, id: Id) -> Result<(), Box<dyn Error>> {
+        let form = self
+            .form(
+                "delete-confirm",
+                &[("id", &id.to_string()), ("goto", "news")],
+                "xdelete",
+                || {
+                    format!(
+                        "Cannot delete item {}, only your own recent comments can be deleted",
+                        id
+                    )
+                },
+            )
+            .await?;
+        self.post(&form, &[("d", "Yes")])
+            .await?
+            .accepted(|| format!("HN rejected deleting item {}", id))?;
+
+        Ok(())
+    }
+
+    /// Submit a story. `url_or_text` is submitted as the story's url when it
+    /// is an http(s) URL, and as its text otherwise. Returns the id of the new
+    /// story.
+    #[tracing::instrument(skip(self, url_or_text))]
+    pub async fn submit(&self, title: &str, url_or_text: &str) -> Result<Id, Box<dyn Error>> {
+        let is_url = Url::parse(url_or_text.trim())
+            .map(|url| url.scheme() == "http" || url.scheme() == "https")
+            .unwrap_or(false);
+        let (url, text) = if is_url {
+            (url_or_text.trim(), "")
+        } else {
+            ("", url_or_text)
+        };
+        let form = self
+            .form("submit", &[], "r", || "Cannot submit stories".to_string())
+            .await?;
+        let posted = self
+            .post(&form, &[("title", title), ("url", url), ("text", text)])
+            .await?;
+
+        match posted.location.as_deref() {
+            // Submitting a recently posted url redirects to its discussion.
+            Some(location) if location.starts_with("item?id=") => match id_from_href(location) {
+                Some(id) => Err(Box::new(HnError::DuplicateError(id))),
+                None => Err(Box::new(HnError::ScrapeError(format!(
+                    "unexpected redirect to {}",
+                    location
+                )))),
+            },
+            Some(_) => {
+                let username = &self.require_session()?.username;
+                let submitted = self.get("submitted", &[("id", username)], 1).await?;
+                parse_stories(&submitted)?
+                    .into_iter()
+                    .find(|ranked| ranked.item.title() == Some(title))
+                    .map(|ranked| ranked.item.id())
+                    .ok_or_else(|| {
+                        HnError::NotFoundError(format!("the new story {}", title)).into()
+                    })
+            }
+            None => {
+                debug!(body = posted.body, "submission rejected");
+                Err(Box::new(HnError::NotAllowedError(format!(
+                    "HN rejected the submission {}",
+                    title
+                ))))
+            }
+        }
+    }
+
+    /// The stories, or comments, `username` marked as favorite. Favorites are
+    /// public, so no session is needed.
+    pub async fn favorites(
+        &self,
+        username: &str,
+        listed: Listed,
+        max_pages: u32,
+    ) -> Result<Vec<Item>, Box<dyn Error>> {
+        let mut query = vec![("id", username)];
+        if listed == Listed::Comments {
+            query.push(("comments", "t"));
+        }
+        self.collect_pages("favorites", &query, listed, max_pages)
+            .await
+    }
+
+    /// The stories, or comments, the logged in user upvoted.
+    pub async fn upvoted(
+        &self,
+        listed: Listed,
+        max_pages: u32,
+    ) -> Result<Vec<Item>, Box<dyn Error>> {
+        let username = &self.require_session()?.username;
+        let mut query = vec![("id", username.as_str())];
+        if listed == Listed::Comments {
+            query.push(("comments", "t"));
+        }
+        self.collect_pages("upvoted", &query, listed, max_pages)
+            .await
+    }
+
+    /// The stories the logged in user hid.
+    pub async fn hidden(&self, max_pages: u32) -> Result<Vec<Item>, Box<dyn Error>> {
+        let username = &self.require_session()?.username;
+        self.collect_pages("hidden", &[("id", username)], Listed::Stories, max_pages)
+            .await
+    }
+
+    /// The comments of `username`, along with the replies to them.
+    pub async fn threads(
+        &self,
+        username: &str,
+        max_pages: u32,
+    ) -> Result<Vec<Item>, Box<dyn Error>> {
+        self.collect_pages("threads", &[("id", username)], Listed::Comments, max_pages)
+            .await
+    }
+
+    /// The replies other users made to the comments of the logged in user.
+    /// The `threads` pages also show replies to those replies, and other
+    /// comments of the threads, so only the direct replies to the comments of
+    /// the user listed on them are kept.
+    pub async fn replies(&self, max_pages: u32) -> Result<Vec<Item>, Box<dyn Error>> {
+        let username = &self.require_session()?.username;
+        let items = self.threads(username, max_pages).await?;
+        let own: HashSet<Id> = items
+            .iter()
+            .filter(|item| item.by() == Some(username.as_str()))
+            .map(Item::id)
+            .collect();
+
+        Ok(items
+            .into_iter()
+            .filter(|item| match item {
+                Item::Comment(comment) => {
+                    item.by() != Some(username.as_str())
+                        && comment.parent.is_some_and(|parent| own.contains(&parent))
+                }
+                _ => false,
+            })
+            .collect())
+    }
+
+    /// The highest voted recent comments.
+    pub async fn best_comments(&self, page: u32) -> Result<Vec<Comment>, Box<dyn Error>> {
+        let html = self.get("bestcomments", &[], page).await?;
+        Ok(parse_comments(&html)?)
+    }
+}
+
+fn selector(selectors: &str) -> Selector {
+    // SAFE: only called with the constant selectors of this module
+    Selector::parse(selectors).expect("valid CSS selector")
+}
+
+fn text_of(element: ElementRef<'_>, selectors: &Selector) -> Option<String> {
+    element
+        .select(selectors)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+}
+
+/// Parse the item id out of a link such as `item?id=123#456`.
+fn id_from_href(href: &str) -> Option<Id> {
+    let (_, rest) = href.split_once("id=")?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Parse the `title` of an age span, either `2024-04-14T12:00:00 1713096000`
+/// or just the timestamp in UTC.
+fn parse_age(title: &str) -> Option<u64> {
+    let mut parts = title.split_whitespace();
+    let timestamp = parts.next()?;
+    if let Some(time) = parts.next().and_then(|unix| unix.parse().ok()) {
+        return Some(time);
+    }
+    humantime::parse_rfc3339_weak(timestamp)
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+fn row_id(row: ElementRef<'_>) -> Result<Id, HnError> {
+    row.value()
+        .attr("id")
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| HnError::ScrapeError("item row without a numeric id".to_string()))
+}
+
+fn row_time(row: ElementRef<'_>, age: &Selector, id: Id) -> Result<u64, HnError> {
+    row.select(age)
+        .next()
+        .and_then(|e| e.value().attr("title"))
+        .and_then(parse_age)
+        .ok_or_else(|| HnError::ScrapeError(format!("no creation time for item {}", id)))
+}
+
+/// The link to the next page of a listing.
+fn more_link(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let more = selector("a.morelink[href]");
+    let href = document
+        .select(&more)
+        .next()
+        .and_then(|a| a.value().attr("href"))
+        .map(str::to_string);
+    href
+}
+
+/// Whether a page was rendered for a logged in user.
+fn is_logged_in(html: &str) -> bool {
+    let document = Html::parse_document(html);
+    let logout = selector("a#logout");
+    let is_logged_in = document.select(&logout).next().is_some();
+    is_logged_in
+}
+
+/// Find the form posted to `action`, i.e. `comment` or `xedit`.
+pub fn parse_form(html: &str, action: &str) -> Option<Form> {
+    let document = Html::parse_document(html);
+    let forms = selector("form[action]");
+    let hidden = selector("input[type=hidden][name]");
+    let textarea = selector("textarea");
+
+    let form = document.select(&forms).find(|form| {
+        form.value()
+            .attr("action")
+            .map(|a| a.trim_start_matches('/') == action)
+            .unwrap_or(false)
+    })?;
+    let fields = form
+        .select(&hidden)
+        .filter_map(|input| {
+            let name = input.value().attr("name")?;
+            let value = input.value().attr("value").unwrap_or_default();
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    let text = form
+        .select(&textarea)
+        .next()
+        .map(|e| e.text().collect::<String>());
+
+    Some(Form {
+        action: action.to_string(),
+        fields,
+        text,
+    })
+}
+
+/// Find the `auth` token of the link performing `action` on the item `id`.
+pub fn parse_auth_token(html: &str, id: Id, action: Action) -> Option<String> {
+    let document = Html::parse_document(html);
+    let links = selector("a[href]");
+    let item_id = id.to_string();
+    // SAFE: BASE_URL is a valid url
+    let base = Url::parse(BASE_URL).unwrap();
+
+    let token = document
+        .select(&links)
+        .filter_map(|a| a.value().attr("href"))
+        .filter_map(|href| base.join(href).ok())
+        .filter(|url| url.path().trim_start_matches('/') == action.path())
+        .find_map(|url| {
+            let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+            let has = |key: &str, value: &str| params.iter().any(|(k, v)| k == key && v == value);
+            let marked = match action.marker() {
+                Some((key, value)) => has(key, value),
+                None => !params.iter().any(|(k, _)| k == "un"),
+            };
+            if !has("id", &item_id) || !marked {
+                return None;
+            }
+            params
+                .iter()
+                .find(|(key, _)| key == "auth")
+                .map(|(_, auth)| auth.clone())
+        });
+    token
+}
+
+/// Parse a page listing stories, i.e. `news`, `front` or `active`.
+///
+/// Rows without a score or an author are job postings, and are returned as
+/// [Item::Job].
+pub fn parse_stories(html: &str) -> Result<Vec<RankedItem>, HnError> {
+    let document = Html::parse_document(html);
+    let rows = selector("tr.athing");
+    let rank = selector(".rank");
+    let titleline = selector(".titleline > a");
+    let score = selector(".score");
+    let user = selector(".hnuser");
+    let age = selector(".age");
+    let links = selector("a");
+
+    let mut items = vec![];
+    for row in document.select(&rows) {
+        let id = row_id(row)?;
+        let rank = text_of(row, &rank).and_then(|r| r.trim_end_matches('.').parse().ok());
+        let link = row
+            .select(&titleline)
+            .next()
+            .ok_or_else(|| HnError::ScrapeError(format!("no title for item {}", id)))?;
+        let title = link.text().collect::<String>();
+        let url = link.value().attr("href").and_then(|href| {
+            if href.starts_with("item?id=") {
+                None
+            } else if href.contains("://") {
+                Some(href.to_string())
+            } else {
+                Some(format!("{}/{}", BASE_URL, href))
+            }
+        });
+
+        // The score, author and comment count are in the row following the title.
+        let subtext = row
+            .next_siblings()
+            .filter_map(ElementRef::wrap)
+            .next()
+            .ok_or_else(|| HnError::ScrapeError(format!("no subtext for item {}", id)))?;
+        let time = row_time(subtext, &age, id)?;
+        let by = text_of(subtext, &user);
+        let score = text_of(subtext, &score)
+            .and_then(|s| s.split_whitespace().next().and_then(|n| n.parse().ok()));
+        let descendants = subtext.select(&links).find_map(|a| {
+            let text = a.text().collect::<String>().replace('\u{a0}', " ");
+            match text.trim() {
+                "discuss" => Some(0),
+                text if text.contains("comment") => {
+                    text.split_whitespace().next().and_then(|n| n.parse().ok())
+                }
+                _ => None,
+            }
+        });
+
+        let item = if score.is_none() && by.is_none() {
+            Item::Job(Job {
+                id,
+                deleted: false,
+                by,
+                time,
+                dead: false,
+                kids: None,
+                text: None,
+                url,
+                title,
+            })
+        } else {
+            Item::Story(Story {
+                id,
+                deleted: false,
+                by,
+                time,
+                dead: false,
+                kids: None,
+                descendants,
+                score,
+                title,
+                url,
+                text: None,
+            })
+        };
+        items.push(RankedItem { rank, item });
+    }
+
+    Ok(items)
+}
+
+/// Parse a page listing comments out of their thread, i.e. `bestcomments`.
+pub fn parse_comments(html: &str) -> Result<Vec<Comment>, HnError> {
+    let document = Html::parse_document(html);
+    let rows = selector("tr.athing");
+    let comhead = selector(".comhead");
+    let user = selector(".hnuser");
+    let age = selector(".age");
+    let navs = selector(".navs a");
+    let commtext = selector(".commtext");
+
+    let mut comments = vec![];
+    for row in document.select(&rows) {
+        let id = row_id(row)?;
+        let time = row_time(row, &age, id)?;
+        let dead = text_of(row, &comhead)
+            .map(|head| head.contains("[dead]") || head.contains("[flagged]"))
+            .unwrap_or(false);
+        let parent = row
+            .select(&navs)
+            .find(|a| a.text().collect::<String>().trim() == "parent")
+            .and_then(|a| a.value().attr("href"))
+            .and_then(id_from_href);
+        let text = row
+            .select(&commtext)
+            .next()
+            .map(|e| e.inner_html().trim().to_string());
+
+        comments.push(Comment {
+            id,
+            deleted: false,
+            by: text_of(row, &user),
+            time,
+            dead,
+            kids: None,
+            parent,
+            text,
+        });
+    }
+
+    Ok(comments)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{
+        parse_auth_token, parse_comments, parse_form, parse_stories, Action, Client, Listed,
+    };
+    use crate::api::Item;
+    use crate::auth::AuthSession;
+    use crate::error::HnError;
+    use std::error::Error;
+    use wiremock::matchers::{body_string_contains, header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const NEWS: &str = include_str!("../../fixtures/html/news.html");
+    const BEST_COMMENTS: &str = include_str!("../../fixtures/html/bestcomments.html");
+    const ITEM: &str = include_str!("../../fixtures/html/item.html");
+    const REPLY: &str = include_str!("../../fixtures/html/reply.html");
+    const EDIT: &str = include_str!("../../fixtures/html/edit.html");
+    const SUBMIT: &str = include_str!("../../fixtures/html/submit.html");
+    const THREADS: &str = include_str!("../../fixtures/html/threads.html");
+
+    #[test]
+    fn test_parse_stories() -> Result<(), Box<dyn Error>> {
+        let items = parse_stories(NEWS)?;
+        let ranks: Vec<Option<u32>> = items.iter().map(|ranked| ranked.rank).collect();
+        assert_eq!(ranks, vec![Some(1), Some(2), Some(3)]);
+
+        match &items[0].item {
+            Item::Story(story) => {
+                assert_eq!(story.id, 40012345);
+                assert_eq!(story.title, "Rust 2024 & beyond");
+                assert_eq!(story.url.as_deref(), Some("https://example.com/rust-2024"));
+                assert_eq!(story.by.as_deref(), Some("steveklabnik"));
+                assert_eq!(story.time, 1713096000);
+                assert_eq!(story.score, Some(512));
+                assert_eq!(story.descendants, Some(231));
+            }
+            item => panic!("expected a story, got {:?}", item),
+        }
+        match &items[1].item {
+            Item::Story(story) => {
+                assert_eq!(story.url, None);
+                assert_eq!(story.score, Some(1));
+                assert_eq!(story.descendants, Some(0));
+                // only the ISO timestamp is present
+                assert_eq!(story.time, 1713105000);
+            }
+            item => panic!("expected a story, got {:?}", item),
+        }
+        match &items[2].item {
+            Item::Job(job) => {
+                assert_eq!(job.id, 40012500);
+                assert_eq!(job.title, "Example (YC S21) is hiring engineers");
+                assert_eq!(job.by, None);
+            }
+            item => panic!("expected a job, got {:?}", item),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_comments() -> Result<(), Box<dyn Error>> {
+        let comments = parse_comments(BEST_COMMENTS)?;
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].id, 40013001);
+        assert_eq!(comments[0].by.as_deref(), Some("tptacek"));
+        assert_eq!(comments[0].parent, Some(40012999));
+        assert_eq!(comments[0].time, 1713088800);
+        assert_eq!(
+            comments[0].text.as_deref(),
+            Some("This is <i>exactly</i> right.<p>Memory safety is table stakes.</p>")
+        );
+        assert_eq!(comments[1].text.as_deref(), Some("Charge more."));
+
+        // Pages without items, i.e. an empty pool, are not an error
+        assert!(parse_stories("<html><body></body></html>")?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_front() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/front"))
+            .and(query_param("day", "2024-04-14"))
+            .and(query_param("p", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(NEWS))
+            .mount(&server)
+            .await;
+
+        let client = Client::with_base_url(server.uri());
+        let items = client.front("2024-04-14", 2).await?;
+        assert_eq!(items.len(), 3);
+        assert!(client.front("14/04/2024", 1).await.is_err());
+        // unmatched paths respond 404
+        assert!(client.pool(1).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_login() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .and(body_string_contains("acct=pg"))
+            .and(body_string_contains("pw=hunter2"))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("Location", "news")
+                    .insert_header("Set-Cookie", "user=pg&abc123; Secure; HttpOnly"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .and(body_string_contains("acct=captcha"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("Validation required. <div class=\"g-recaptcha\"></div>"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Bad login.<br><br>"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/news"))
+            .and(header("Cookie", "user=pg&abc123"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(NEWS))
+            .mount(&server)
+            .await;
+
+        let mut client = Client::with_base_url(server.uri());
+        assert!(matches!(
+            client.require_session(),
+            Err(HnError::UnauthenticatedError)
+        ));
+
+        let err = client.login("pg", "wrong").await.unwrap_err();
+        match err.downcast_ref::<HnError>() {
+            Some(HnError::AuthenticationError(Some(msg))) => assert!(msg.contains("password")),
+            err => panic!("expected an authentication error, got {:?}", err),
+        }
+        let err = client.login("captcha", "hunter2").await.unwrap_err();
+        match err.downcast_ref::<HnError>() {
+            Some(HnError::AuthenticationError(Some(msg))) => assert!(msg.contains("captcha")),
+            err => panic!("expected an authentication error, got {:?}", err),
+        }
+        assert!(client.session().is_none());
+
+        let session = client.login("pg", "hunter2").await?;
+        assert_eq!(session, AuthSession::new("pg", "pg&abc123"));
+        assert_eq!(client.require_session()?.username, "pg");
+        // the cookie is sent with later requests
+        assert_eq!(client.news(1).await?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_auth_token() {
+        let token = |id, action| parse_auth_token(ITEM, id, action);
+        assert_eq!(
+            token(40012345, Action::Upvote).as_deref(),
+            Some("a1b2c3d4e5f6")
+        );
+        assert_eq!(
+            token(40012345, Action::Favorite).as_deref(),
+            Some("a1b2c3d4e5f6")
+        );
+        assert_eq!(
+            token(40012345, Action::Hide).as_deref(),
+            Some("a1b2c3d4e5f6")
+        );
+        assert_eq!(token(40012345, Action::Unfavorite), None);
+        assert_eq!(token(40012345, Action::Downvote), None);
+        assert_eq!(
+            token(40013001, Action::Unvote).as_deref(),
+            Some("0a1b2c3d4e5f")
+        );
+        assert_eq!(token(40013001, Action::Hide), None);
+    }
+
+    #[tokio::test]
+    async fn test_act() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/item"))
+            .and(query_param("id", "40012345"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ITEM))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/vote"))
+            .and(query_param("id", "40012345"))
+            .and(query_param("how", "up"))
+            .and(query_param("auth", "a1b2c3d4e5f6"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ITEM))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::with_base_url(server.uri());
+        let err = client.upvote(40012345).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HnError>(),
+            Some(HnError::UnauthenticatedError)
+        ));
+
+        let client = client.with_session(AuthSession::new("pg", "pg&abc123"));
+        client.upvote(40012345).await?;
+        let err = client.downvote(40012345).await.unwrap_err();
+        match err.downcast_ref::<HnError>() {
+            Some(HnError::NotAllowedError(msg)) => assert!(msg.contains("karma")),
+            err => panic!("expected a not allowed error, got {:?}", err),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_form() {
+        let form = parse_form(REPLY, "comment").unwrap();
+        let names: Vec<&str> = form.fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["parent", "goto", "hmac"]);
+        assert_eq!(form.fields[2].1, "7d3f9a2b1c");
+
+        let form = parse_form(SUBMIT, "r").unwrap();
+        assert_eq!(
+            form.fields[0],
+            ("fnid".to_string(), "Xq7bT2kLm9".to_string())
+        );
+
+        let form = parse_form(EDIT, "xedit").unwrap();
+        assert_eq!(
+            form.text.as_deref(),
+            Some("Original text with a <tag>.\n\nSecond paragraph.")
+        );
+        assert_eq!(parse_form(ITEM, "xedit"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reply_and_submit() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/reply"))
+            .and(query_param("id", "40013001"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(REPLY))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/comment"))
+            .and(body_string_contains("hmac=7d3f9a2b1c"))
+            .and(body_string_contains("text=Agreed"))
+            .respond_with(
+                ResponseTemplate::new(302).insert_header("Location", "item?id=40012345#40014000"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/comment"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("You're posting too fast. Please slow down. Thanks."),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/submit"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(SUBMIT))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/r"))
+            .and(body_string_contains("fnid=Xq7bT2kLm9"))
+            .and(body_string_contains(
+                "url=https%3A%2F%2Fexample.com%2Frust-2024",
+            ))
+            .respond_with(ResponseTemplate::new(302).insert_header("Location", "newest"))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/r"))
+            .respond_with(ResponseTemplate::new(302).insert_header("Location", "item?id=39000000"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/submitted"))
+            .and(query_param("id", "pg"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(NEWS))
+            .mount(&server)
+            .await;
+
+        let client = Client::with_base_url(server.uri());
+        assert!(client.reply(40013001, "Agreed").await.is_err());
+        let client = client.with_session(AuthSession::new("pg", "pg&abc123"));
+
+        assert_eq!(client.reply(40013001, "Agreed").await?, 40014000);
+        let err = client.reply(40013001, "Again").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HnError>(),
+            Some(HnError::RateLimitError(_))
+        ));
+
+        let id = client
+            .submit("Rust 2024 & beyond", "https://example.com/rust-2024")
+            .await?;
+        assert_eq!(id, 40012345);
+        let err = client
+            .submit("Rust 2024", "https://example.com/other")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HnError>(),
+            Some(HnError::DuplicateError(39000000))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_edit() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/edit"))
+            .and(query_param("id", "40014000"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(EDIT))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/xedit"))
+            .and(body_string_contains("hmac=c4d5e6f7a8"))
+            .and(body_string_contains("text=Updated"))
+            .respond_with(
+                ResponseTemplate::new(302).insert_header("Location", "item?id=40012345#40014000"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/xedit"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Please try again."))
+            .mount(&server)
+            .await;
+
+        let client =
+            Client::with_base_url(server.uri()).with_session(AuthSession::new("pg", "pg&abc123"));
+        assert_eq!(client.edit(40014000, "Updated").await?, 40014000);
+        let err = client.edit(40014000, "Rejected").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HnError>(),
+            Some(HnError::NotAllowedError(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_personal_pages() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/favorites"))
+            .and(query_param("id", "pg"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(NEWS))
+            .mount(&server)
+            .await;
+        // the More link of the fixture is relative, `?p=2`
+        Mock::given(method("GET"))
+            .and(path("/favorites"))
+            .and(query_param("p", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(""))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/threads"))
+            .and(query_param("id", "pg"))
+            .and(query_param("next", "40014000"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(BEST_COMMENTS))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/threads"))
+            .and(query_param("id", "pg"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(THREADS))
+            .mount(&server)
+            .await;
+
+        let client = Client::with_base_url(server.uri());
+        let favorites = client.favorites("pg", Listed::Stories, 5).await?;
+        assert_eq!(favorites.len(), 3);
+        assert!(client.upvoted(Listed::Stories, 1).await.is_err());
+
+        let client = client.with_session(AuthSession::new("pg", "pg&abc123"));
+        assert_eq!(client.threads("pg", 1).await?.len(), 2);
+        let replies = client.replies(2).await?;
+        let ids: Vec<u64> = replies.iter().map(|item| item.id()).collect();
+        assert_eq!(ids, vec![40014100]);
+
+        Ok(())
+    }
+}
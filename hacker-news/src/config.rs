@@ -1,49 +1,560 @@
-use serde::Deserialize;
+//! The configuration file, and how it combines with the environment and the
+//! command line.
+//!
+//! The config file is TOML, at `$XDG_CONFIG_HOME/hacker-news/config.toml` on
+//! Linux. Every [Settings] key can be set at the top level of the file, in a
+//! named `[profiles.<name>]` table, by an `HN_*` environment variable or by a
+//! command line flag. When a key is set in several places the first of these
+//! wins:
+//!
+//! 1. a command line flag, i.e. `--source`
+//! 2. an environment variable, i.e. `HN_SOURCE`
+//! 3. the selected profile, from `--profile`, `HN_PROFILE` or `profile = ".."`
+//! 4. the top level of the config file
+//! 5. the built in default
+//!
+//! ```toml
+//! list = "best"
+//! count = 50
+//! profile = "work"
+//!
+//! [profiles.work]
+//! username = "pg"
+//! source = "cache,network"
+//! credentials = "secret-service"
+//! ```
+//!
+//! Each profile keeps its own login session, see [Settings::credential_store].
+
+use crate::alerts::AlertsConfig;
+use crate::api::StoryList;
+#[cfg(feature = "secret-service")]
+use crate::credentials::SecretService;
+use crate::credentials::{CredentialStore, EncryptedFile};
+use crate::error::HnError;
+use crate::source::{SourceKind, SourceSpec};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
+use std::fmt;
 use std::path::Path;
+use std::path::PathBuf;
 
-#[derive(Debug, Deserialize)]
-pub struct HNConfig {
-    _user: Option<HNConfigUser>,
+/// Output formats understood by the CLI.
+pub const FORMATS: [&str; 5] = ["table", "json", "ndjson", "csv", "tsv"];
+
+/// Color modes understood by the CLI.
+pub const COLORS: [&str; 3] = ["auto", "always", "never"];
+
+/// Credential stores, see [crate::credentials]. `file` is an encrypted file
+/// in the data directory, `secret-service` the desktop keyring.
+pub const CREDENTIAL_STORES: [&str; 2] = ["file", "secret-service"];
+
+/// Settings as written in one layer of configuration, where any key may be
+/// missing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PartialSettings {
+    /// Story list shown by `hn news` and the TUI, i.e. "top" or "best".
+    pub list: Option<String>,
+    /// Number of stories shown by `hn news` and the TUI.
+    pub count: Option<usize>,
+    /// Output format, one of [FORMATS].
+    pub format: Option<String>,
+    /// When to color output, one of [COLORS].
+    pub color: Option<String>,
+    /// Maximum number of concurrent API requests.
+    pub concurrency: Option<usize>,
+    /// Maximum number of API requests per second. Zero is unlimited.
+    pub rate_limit: Option<u32>,
+    /// Where to read data from, a [SourceSpec].
+    pub source: Option<String>,
+    /// Directory of the SQLite store, search index and saved session.
+    pub data_dir: Option<PathBuf>,
+    /// Directory of the `cache` source.
+    pub cache_dir: Option<PathBuf>,
+    /// Hacker News account the profile belongs to.
+    pub username: Option<String>,
+    /// Where the session cookie is kept, one of [CREDENTIAL_STORES].
+    pub credentials: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct HNConfigUser {
-    _username: Option<String>,
+/// The contents of the config file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HNConfig {
+    #[serde(flatten)]
+    pub settings: PartialSettings,
+    /// Profile used when none is given by `--profile` or `HN_PROFILE`.
+    pub profile: Option<String>,
+    /// Named sets of settings, i.e. one per account.
+    pub profiles: BTreeMap<String, PartialSettings>,
+    /// Alert rules and where to send alerts, for `hn alerts`.
+    pub alerts: AlertsConfig,
 }
 
 impl HNConfig {
+    /// Read a config file. Files with a `.json` extension are read as JSON,
+    /// anything else as TOML.
     pub fn from_file(path: &Path) -> Result<HNConfig, Box<dyn Error>> {
-        let f = File::open(path)?;
-        let rd = BufReader::new(f);
-        let config = serde_json::from_reader(rd)?;
+        let text = std::fs::read_to_string(path)?;
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&text)?,
+            _ => toml::from_str(&text)?,
+        };
 
         Ok(config)
     }
 }
 
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File(PathBuf),
+    Profile(String),
+    /// An environment variable, by name.
+    Env(&'static str),
+    /// A command line flag, by the name of the setting.
+    Flag(&'static str),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "file {}", path.display()),
+            Origin::Profile(name) => write!(f, "profile {}", name),
+            Origin::Env(var) => write!(f, "env {}", var),
+            Origin::Flag(key) => write!(f, "flag --{}", key.replace('_', "-")),
+        }
+    }
+}
+
+/// Fully resolved settings, remembering where each value came from.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub list: StoryList,
+    pub count: usize,
+    pub format: String,
+    pub color: String,
+    pub concurrency: usize,
+    pub rate_limit: u32,
+    pub source: SourceSpec,
+    pub data_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub username: Option<String>,
+    pub credentials: String,
+    /// The selected profile, if any.
+    pub profile: Option<String>,
+    /// Every profile of the config file.
+    pub profiles: BTreeMap<String, PartialSettings>,
+    pub alerts: AlertsConfig,
+    /// The config file read, whether or not it exists.
+    pub config_path: PathBuf,
+    origins: BTreeMap<&'static str, Origin>,
+}
+
+/// Settings given on the command line, which take precedence over everything
+/// else.
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    pub config: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub settings: PartialSettings,
+}
+
+/// Apply every key set in `layer` to `target`, recording `origin(key)` for
+/// it.
+macro_rules! apply_layer {
+    ($target:expr, $origins:expr, $layer:expr, $origin:expr) => {
+        apply_layer!(
+            $target, $origins, $layer, $origin,
+            [list, count, format, color, concurrency, rate_limit, source, data_dir, cache_dir, username, credentials]
+        )
+    };
+    ($target:expr, $origins:expr, $layer:expr, $origin:expr, [$($key:ident),*]) => {
+        $(
+            if let Some(ref value) = $layer.$key {
+                $target.$key = Some(value.clone());
+                $origins.insert(stringify!($key), $origin(stringify!($key)));
+            }
+        )*
+    };
+}
+
+/// Every key of [PartialSettings], with the environment variable setting it.
+const ENV_VARS: [(&str, &str); 11] = [
+    ("list", "HN_LIST"),
+    ("count", "HN_COUNT"),
+    ("format", "HN_FORMAT"),
+    ("color", "HN_COLOR"),
+    ("concurrency", "HN_CONCURRENCY"),
+    ("rate_limit", "HN_RATE_LIMIT"),
+    ("source", "HN_SOURCE"),
+    ("data_dir", "HN_DATA_DIR"),
+    ("cache_dir", "HN_CACHE_DIR"),
+    ("username", "HN_USERNAME"),
+    ("credentials", "HN_CREDENTIALS"),
+];
+
+fn env_var(key: &str) -> &'static str {
+    ENV_VARS
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, var)| *var)
+        .unwrap_or("HN_")
+}
+
+fn parse_env<T: std::str::FromStr>(value: &str, err: &'static str) -> Result<T, HnError> {
+    value.parse().map_err(|_| HnError::ArgumentError(Some(err)))
+}
+
+/// The settings given by `HN_*` environment variables.
+fn from_env(env: &dyn Fn(&str) -> Option<String>) -> Result<PartialSettings, HnError> {
+    let var = |key| env(env_var(key)).filter(|value| !value.is_empty());
+
+    Ok(PartialSettings {
+        list: var("list"),
+        count: var("count")
+            .map(|v| parse_env(&v, "HN_COUNT must be a number"))
+            .transpose()?,
+        format: var("format"),
+        color: var("color"),
+        concurrency: var("concurrency")
+            .map(|v| parse_env(&v, "HN_CONCURRENCY must be a number"))
+            .transpose()?,
+        rate_limit: var("rate_limit")
+            .map(|v| parse_env(&v, "HN_RATE_LIMIT must be a number"))
+            .transpose()?,
+        source: var("source"),
+        data_dir: var("data_dir").map(PathBuf::from),
+        cache_dir: var("cache_dir").map(PathBuf::from),
+        username: var("username"),
+        credentials: var("credentials"),
+    })
+}
+
+impl Settings {
+    /// Read the config file and resolve it against the process environment
+    /// and `flags`. A missing config file is not an error.
+    pub fn load(flags: &Flags) -> Result<Self, Box<dyn Error>> {
+        let env = |var: &str| std::env::var(var).ok();
+        let path = flags
+            .config
+            .clone()
+            .or_else(|| {
+                env("HN_CONFIG")
+                    .filter(|path| !path.is_empty())
+                    .map(PathBuf::from)
+            })
+            .unwrap_or_else(default_config_path);
+        let file = if path.exists() {
+            HNConfig::from_file(&path)?
+        } else {
+            HNConfig::default()
+        };
+
+        Ok(Self::resolve(file, path, &env, flags)?)
+    }
+
+    /// Combine the config `file`, read from `path`, with the environment and
+    /// `flags`, in order of precedence.
+    pub fn resolve(
+        file: HNConfig,
+        path: PathBuf,
+        env: &dyn Fn(&str) -> Option<String>,
+        flags: &Flags,
+    ) -> Result<Self, HnError> {
+        let mut merged = PartialSettings::default();
+        let mut origins = BTreeMap::new();
+
+        apply_layer!(merged, origins, file.settings, |_| Origin::File(
+            path.clone()
+        ));
+        let profile = flags
+            .profile
+            .clone()
+            .or_else(|| env("HN_PROFILE").filter(|name| !name.is_empty()))
+            .or_else(|| file.profile.clone());
+        if let Some(ref name) = profile {
+            let layer = file
+                .profiles
+                .get(name)
+                .ok_or_else(|| HnError::NotFoundError(format!("profile {}", name)))?;
+            apply_layer!(merged, origins, layer, |_| Origin::Profile(name.clone()));
+        }
+        apply_layer!(merged, origins, from_env(env)?, |key| Origin::Env(env_var(
+            key
+        )));
+        apply_layer!(merged, origins, flags.settings, Origin::Flag);
+
+        let check = |value: Option<String>, allowed: &[&str], err: &'static str| match value {
+            Some(value) if !allowed.contains(&value.as_str()) => {
+                Err(HnError::ArgumentError(Some(err)))
+            }
+            value => Ok(value.unwrap_or_else(|| allowed[0].to_string())),
+        };
+
+        Ok(Self {
+            list: match merged.list {
+                Some(ref list) => list.parse()?,
+                None => StoryList::Top,
+            },
+            count: merged.count.unwrap_or(30),
+            format: check(
+                merged.format,
+                &FORMATS,
+                "format must be one of table, json, ndjson, csv or tsv",
+            )?,
+            color: check(
+                merged.color,
+                &COLORS,
+                "color must be one of auto, always or never",
+            )?,
+            concurrency: merged.concurrency.unwrap_or(10).max(1),
+            rate_limit: merged.rate_limit.unwrap_or(0),
+            source: match merged.source {
+                Some(ref source) => source.parse()?,
+                None => SourceSpec::default(),
+            },
+            data_dir: merged.data_dir.unwrap_or_else(default_data_dir),
+            cache_dir: merged.cache_dir.unwrap_or_else(default_cache_dir),
+            username: merged.username,
+            credentials: check(
+                merged.credentials,
+                &CREDENTIAL_STORES,
+                "credentials must be one of file or secret-service",
+            )?,
+            profile,
+            profiles: file.profiles,
+            alerts: file.alerts,
+            config_path: path,
+            origins,
+        })
+    }
+
+    /// Where the value of `key` came from.
+    pub fn origin(&self, key: &str) -> Origin {
+        self.origins.get(key).cloned().unwrap_or(Origin::Default)
+    }
+
+    /// Every setting as a `(key, value, origin)` triple, in the order of the
+    /// config file documentation.
+    pub fn explain(&self) -> Vec<(&'static str, String, Origin)> {
+        let path = |path: &Path| path.display().to_string();
+        vec![
+            ("list", self.list.as_str().to_string()),
+            ("count", self.count.to_string()),
+            ("format", self.format.clone()),
+            ("color", self.color.clone()),
+            ("concurrency", self.concurrency.to_string()),
+            ("rate_limit", self.rate_limit.to_string()),
+            ("source", self.source.to_string()),
+            ("data_dir", path(&self.data_dir)),
+            ("cache_dir", path(&self.cache_dir)),
+            ("username", self.username.clone().unwrap_or_default()),
+            ("credentials", self.credentials.clone()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key, value, self.origin(key)))
+        .collect()
+    }
+
+    /// Location of the SQLite store.
+    pub fn db_path(&self) -> PathBuf {
+        self.data_dir.join("hn.sqlite3")
+    }
+
+    /// Location of the full-text search index.
+    pub fn index_dir(&self) -> PathBuf {
+        self.data_dir.join("index")
+    }
+
+    /// Name of the account credentials are stored under: the selected
+    /// profile, or `default`.
+    pub fn account(&self) -> &str {
+        self.profile.as_deref().unwrap_or("default")
+    }
+
+    /// Location of the encrypted session of the selected profile, when
+    /// credentials are kept in a file.
+    pub fn session_path(&self) -> PathBuf {
+        self.data_dir
+            .join("sessions")
+            .join(format!("{}.json", self.account()))
+    }
+
+    /// The store the session cookie of the selected profile is kept in.
+    pub fn credential_store(&self) -> Result<Box<dyn CredentialStore>, HnError> {
+        match self.credentials.as_str() {
+            #[cfg(feature = "secret-service")]
+            "secret-service" => Ok(Box::new(
+                SecretService::new("hacker-news")
+                    .with_fallback(EncryptedFile::open(self.session_path())),
+            )),
+            #[cfg(not(feature = "secret-service"))]
+            "secret-service" => Err(HnError::ArgumentError(Some(
+                "secret-service credentials need hn built with the secret-service feature",
+            ))),
+            _ => Ok(Box::new(EncryptedFile::open(self.session_path()))),
+        }
+    }
+
+    /// The API client, limited to the configured concurrency and rate.
+    pub fn client(&self) -> crate::client::Client {
+        crate::client::Client::new()
+            .with_concurrency(self.concurrency)
+            .with_rate_limit(self.rate_limit)
+    }
+
+    /// The configured source, with the cache and store of layers which do not
+    /// name one placed in the configured directories.
+    pub fn source_spec(&self) -> SourceSpec {
+        let layers = self
+            .source
+            .layers
+            .iter()
+            .map(|layer| match layer {
+                SourceKind::Cache(None) => SourceKind::Cache(Some(self.cache_dir.clone())),
+                SourceKind::Db(None) => SourceKind::Db(Some(self.db_path())),
+                layer => layer.clone(),
+            })
+            .collect();
+
+        SourceSpec { layers }
+    }
+}
+
+/// Default location of the config file, `$XDG_CONFIG_HOME/hacker-news/config.toml`
+/// on Linux.
+pub fn default_config_path() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join("hacker-news"))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("config.toml")
+}
+
+/// Directory where local data, such as the SQLite store, is kept by default.
+/// This is `$XDG_DATA_HOME/hacker-news` on Linux, falling back to the current
+/// directory when no data directory can be determined.
+pub fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("hacker-news"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Directory where cached API responses are kept by default. This is
+/// `$XDG_CACHE_HOME/hacker-news` on Linux.
+pub fn default_cache_dir() -> PathBuf {
+    dirs::cache_dir()
+        .map(|dir| dir.join("hacker-news"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Default location of the SQLite store.
+pub fn default_db_path() -> PathBuf {
+    default_data_dir().join("hn.sqlite3")
+}
+
+/// Default location of the full-text search index.
+pub fn default_index_dir() -> PathBuf {
+    default_data_dir().join("index")
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::HNConfig;
+    use super::{Flags, HNConfig, Origin, PartialSettings, Settings};
+    use crate::api::StoryList;
+    use std::collections::HashMap;
     use std::error::Error;
     use std::path::PathBuf;
 
-    // This test is marked as ignored, as the feature it supports is not
-    // really implemented
-    #[ignore]
+    const CONFIG: &str = r#"
+list = "best"
+count = 50
+source = "cache,network"
+profile = "work"
+
+[profiles.work]
+username = "pg"
+count = 10
+data_dir = "/work"
+
+[profiles.home]
+username = "dang"
+
+[[alerts.rules]]
+name = "rust"
+title = "(?i)rust"
+
+[[alerts.sinks]]
+type = "stdout"
+"#;
+
     #[test]
     fn test_read_config_file() -> Result<(), Box<dyn Error>> {
-        let home = std::env::var("HOME").expect("Failed to read `$HOME` environment variable");
-        println!("$HOME = {:?}", home);
-        let mut path = PathBuf::from(home);
-        path.push(".hn.json");
-        println!("path = {:?}", path);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, CONFIG)?;
 
         let config = HNConfig::from_file(&path)?;
-        println!("config = {:#?}", config);
+        assert_eq!(config.settings.list.as_deref(), Some("best"));
+        assert_eq!(config.profile.as_deref(), Some("work"));
+        assert_eq!(config.profiles["home"].username.as_deref(), Some("dang"));
+        assert_eq!(config.alerts.rules.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_precedence() -> Result<(), Box<dyn Error>> {
+        let path = PathBuf::from("config.toml");
+        let vars: HashMap<&str, &str> = [("HN_COUNT", "5"), ("HN_SOURCE", "db")].into();
+        let env = |var: &str| vars.get(var).map(|value| value.to_string());
+        let flags = Flags {
+            settings: PartialSettings {
+                source: Some("network".to_string()),
+                ..PartialSettings::default()
+            },
+            ..Flags::default()
+        };
+
+        let settings = Settings::resolve(toml::from_str(CONFIG)?, path.clone(), &env, &flags)?;
+        assert_eq!(settings.list, StoryList::Best);
+        assert_eq!(settings.origin("list"), Origin::File(path.clone()));
+        assert_eq!(settings.username.as_deref(), Some("pg"));
+        assert_eq!(
+            settings.origin("username"),
+            Origin::Profile("work".to_string())
+        );
+        assert_eq!(settings.db_path(), PathBuf::from("/work/hn.sqlite3"));
+        assert_eq!(settings.count, 5);
+        assert_eq!(settings.origin("count"), Origin::Env("HN_COUNT"));
+        assert_eq!(settings.source.to_string(), "network");
+        assert_eq!(settings.origin("source"), Origin::Flag("source"));
+        assert_eq!(settings.format, "table");
+        assert_eq!(settings.origin("format"), Origin::Default);
+
+        // HN_PROFILE selects another profile, and unknown profiles are errors
+        let env = |var: &str| (var == "HN_PROFILE").then(|| "home".to_string());
+        let settings = Settings::resolve(
+            toml::from_str(CONFIG)?,
+            path.clone(),
+            &env,
+            &Flags::default(),
+        )?;
+        assert_eq!(settings.username.as_deref(), Some("dang"));
+        assert_eq!(settings.count, 50);
+        let flags = Flags {
+            profile: Some("nope".to_string()),
+            ..Flags::default()
+        };
+        assert!(Settings::resolve(toml::from_str(CONFIG)?, path.clone(), &env, &flags).is_err());
+
+        let env = |var: &str| (var == "HN_COLOR").then(|| "purple".to_string());
+        assert!(Settings::resolve(HNConfig::default(), path, &env, &Flags::default()).is_err());
 
         Ok(())
     }
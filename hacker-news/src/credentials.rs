@@ -0,0 +1,423 @@
+//! Storage for secrets, such as the session cookie of a logged in account.
+//!
+//! Secrets are kept in a [CredentialStore] under an account name, which is
+//! the name of the config profile the secret belongs to. Two stores exist:
+//!
+//! * [EncryptedFile], the default, which keeps every secret in a single file
+//!   encrypted with XChaCha20-Poly1305. The key is derived with Argon2 from
+//!   the `HN_PASSPHRASE` environment variable when it is set, and otherwise
+//!   is a random key kept beside the file, readable only by the current user.
+//! * `SecretService`, which uses the Secret Service D-Bus API of the desktop
+//!   keyring. It is only available when built with the `secret-service`
+//!   feature, and falls back to an [EncryptedFile] when there is no D-Bus
+//!   session to connect to, as on a headless machine.
+
+use crate::error::HnError;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Debug;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the environment variable holding the passphrase of an
+/// [EncryptedFile].
+pub const PASSPHRASE_VAR: &str = "HN_PASSPHRASE";
+
+/// Somewhere secrets can be kept.
+pub trait CredentialStore: Debug {
+    /// Retrieve the secret of `account`. Returns `None` when there is none.
+    fn get(&self, account: &str) -> Result<Option<String>, Box<dyn Error>>;
+
+    /// Store the secret of `account`, replacing any previous secret.
+    fn set(&self, account: &str, secret: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Remove the secret of `account`. Returns false if there was none.
+    fn delete(&self, account: &str) -> Result<bool, Box<dyn Error>>;
+}
+
+/// Write `contents` to `path`, creating the file with 0600 permissions.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files, tighten an existing one too.
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(contents)?;
+
+    Ok(())
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// How the key of an [EncryptedFile] is obtained.
+#[derive(Debug, Clone)]
+enum KeySource {
+    /// Derived from a passphrase with the salt stored in the file.
+    Passphrase(String),
+    /// A random key kept in a separate file.
+    KeyFile(PathBuf),
+}
+
+impl KeySource {
+    fn kind(&self) -> KeyKind {
+        match self {
+            KeySource::Passphrase(_) => KeyKind::Passphrase,
+            KeySource::KeyFile(_) => KeyKind::KeyFile,
+        }
+    }
+}
+
+/// Which kind of key the secrets of an [EncryptedFile] are encrypted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum KeyKind {
+    Passphrase,
+    KeyFile,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Sealed {
+    /// Kind of key the entries are encrypted with, which is missing from
+    /// files written before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<KeyKind>,
+    /// Base64 salt of the passphrase, when the key is derived from one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    /// Base64 nonce and ciphertext of each secret, by account.
+    entries: BTreeMap<String, String>,
+}
+
+impl Sealed {
+    /// Kind of key the entries are encrypted with, if there are any.
+    fn key_kind(&self) -> Option<KeyKind> {
+        self.key.or(match (&self.salt, self.entries.is_empty()) {
+            (Some(_), _) => Some(KeyKind::Passphrase),
+            (None, false) => Some(KeyKind::KeyFile),
+            (None, true) => None,
+        })
+    }
+}
+
+/// Secrets kept in an encrypted file.
+#[derive(Debug, Clone)]
+pub struct EncryptedFile {
+    path: PathBuf,
+    key: KeySource,
+}
+
+const NONCE_LEN: usize = 24;
+
+impl EncryptedFile {
+    /// Secrets in the file at `path`, with the key derived from `HN_PASSPHRASE`
+    /// when it is set, or else kept in `path` with a `.key` extension.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        match std::env::var(PASSPHRASE_VAR) {
+            Ok(passphrase) if !passphrase.is_empty() => Self::with_passphrase(path, passphrase),
+            _ => {
+                let key_file = path.with_extension("key");
+                Self {
+                    path,
+                    key: KeySource::KeyFile(key_file),
+                }
+            }
+        }
+    }
+
+    /// Secrets in the file at `path`, encrypted with a key derived from
+    /// `passphrase`.
+    pub fn with_passphrase(path: impl Into<PathBuf>, passphrase: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            key: KeySource::Passphrase(passphrase.into()),
+        }
+    }
+
+    fn load(&self) -> Result<Sealed, Box<dyn Error>> {
+        match read_optional(&self.path)? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(Sealed::default()),
+        }
+    }
+
+    fn save(&self, sealed: &Sealed) -> Result<(), Box<dyn Error>> {
+        write_private(&self.path, serde_json::to_string_pretty(sealed)?.as_bytes())
+    }
+
+    /// The cipher for the secrets of `sealed`, filling in a salt or creating
+    /// the key file when `create` is set.
+    fn cipher(
+        &self,
+        sealed: &mut Sealed,
+        create: bool,
+    ) -> Result<Option<XChaCha20Poly1305>, Box<dyn Error>> {
+        match sealed.key_kind() {
+            Some(kind) if kind != self.key.kind() => {
+                return Err(Box::new(HnError::AuthenticationError(Some(match kind {
+                    KeyKind::Passphrase => {
+                        "the stored credentials are encrypted with a passphrase, set HN_PASSPHRASE"
+                    }
+                    KeyKind::KeyFile => {
+                        "the stored credentials are encrypted with a key file, unset HN_PASSPHRASE"
+                    }
+                }))));
+            }
+            _ => sealed.key = Some(self.key.kind()),
+        }
+
+        let mut key = [0u8; 32];
+        match self.key {
+            KeySource::Passphrase(ref passphrase) => {
+                let salt = match sealed.salt {
+                    Some(ref salt) => BASE64.decode(salt)?,
+                    None if create => {
+                        let mut salt = vec![0u8; 16];
+                        rand::thread_rng().fill_bytes(&mut salt);
+                        sealed.salt = Some(BASE64.encode(&salt));
+                        salt
+                    }
+                    None => return Ok(None),
+                };
+                argon2::Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+                    .map_err(|err| HnError::StorageError(Some(err.to_string().into())))?;
+            }
+            KeySource::KeyFile(ref path) => match read_optional(path)? {
+                Some(bytes) => {
+                    let bytes = BASE64.decode(bytes.trim_ascii())?;
+                    if bytes.len() != key.len() {
+                        return Err(Box::new(HnError::StorageError(Some(
+                            format!("{} is not a credentials key", path.display()).into(),
+                        ))));
+                    }
+                    key.copy_from_slice(&bytes);
+                }
+                None if create => {
+                    rand::thread_rng().fill_bytes(&mut key);
+                    write_private(path, BASE64.encode(key).as_bytes())?;
+                }
+                None => return Ok(None),
+            },
+        }
+
+        Ok(Some(XChaCha20Poly1305::new(&key.into())))
+    }
+}
+
+impl CredentialStore for EncryptedFile {
+    fn get(&self, account: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let mut sealed = self.load()?;
+        let Some(entry) = sealed.entries.get(account).cloned() else {
+            return Ok(None);
+        };
+        let Some(cipher) = self.cipher(&mut sealed, false)? else {
+            return Ok(None);
+        };
+        let bytes = BASE64.decode(entry)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(Box::new(HnError::SerializationError(Some(
+                "stored credentials are truncated",
+            ))));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                HnError::AuthenticationError(Some(
+                    "could not decrypt the stored credentials, check HN_PASSPHRASE",
+                ))
+            })?;
+
+        Ok(Some(String::from_utf8(plaintext)?))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), Box<dyn Error>> {
+        let mut sealed = self.load()?;
+        // SAFE: a cipher is always returned when creating one is allowed
+        let cipher = self.cipher(&mut sealed, true)?.unwrap();
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), secret.as_bytes())
+            .map_err(|_| HnError::SerializationError(Some("failed to encrypt credentials")))?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend(ciphertext);
+        sealed
+            .entries
+            .insert(account.to_string(), BASE64.encode(bytes));
+
+        self.save(&sealed)
+    }
+
+    fn delete(&self, account: &str) -> Result<bool, Box<dyn Error>> {
+        let mut sealed = self.load()?;
+        if sealed.entries.remove(account).is_none() {
+            return Ok(false);
+        }
+        self.save(&sealed)?;
+
+        Ok(true)
+    }
+}
+
+/// Secrets kept by the Secret Service D-Bus API, i.e. GNOME Keyring or
+/// KWallet.
+#[cfg(feature = "secret-service")]
+#[derive(Debug, Clone)]
+pub struct SecretService {
+    service: String,
+    fallback: Option<EncryptedFile>,
+}
+
+#[cfg(feature = "secret-service")]
+impl SecretService {
+    pub fn new(service: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            fallback: None,
+        }
+    }
+
+    /// Keep secrets in `file` instead when the keyring can not be reached.
+    pub fn with_fallback(mut self, file: EncryptedFile) -> Self {
+        self.fallback = Some(file);
+        self
+    }
+
+    /// Run a blocking keyring call without stalling the async runtime.
+    fn blocking<T>(f: impl FnOnce() -> T) -> T {
+        use tokio::runtime::{Handle, RuntimeFlavor};
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(f)
+            }
+            _ => f(),
+        }
+    }
+
+    /// Run `op` on the keyring entry of `account`, or `fallback` on the
+    /// fallback file when the keyring can not be connected to.
+    fn with_entry<T>(
+        &self,
+        account: &str,
+        op: impl FnOnce(&keyring::Entry) -> keyring::Result<T>,
+        fallback: impl FnOnce(&EncryptedFile) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        let result = keyring::Entry::new(&self.service, account)
+            .and_then(|entry| Self::blocking(|| op(&entry)));
+        match (result, &self.fallback) {
+            (Err(keyring::Error::PlatformFailure(err)), Some(file)) => {
+                tracing::warn!(
+                    "secret service unavailable, using {}: {}",
+                    file.path.display(),
+                    err
+                );
+                fallback(file)
+            }
+            (result, _) => Ok(result?),
+        }
+    }
+}
+
+#[cfg(feature = "secret-service")]
+impl CredentialStore for SecretService {
+    fn get(&self, account: &str) -> Result<Option<String>, Box<dyn Error>> {
+        self.with_entry(
+            account,
+            |entry| match entry.get_password() {
+                Ok(secret) => Ok(Some(secret)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(err) => Err(err),
+            },
+            |file| file.get(account),
+        )
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), Box<dyn Error>> {
+        self.with_entry(
+            account,
+            |entry| entry.set_password(secret),
+            |file| file.set(account, secret),
+        )
+    }
+
+    fn delete(&self, account: &str) -> Result<bool, Box<dyn Error>> {
+        self.with_entry(
+            account,
+            |entry| match entry.delete_credential() {
+                Ok(()) => Ok(true),
+                Err(keyring::Error::NoEntry) => Ok(false),
+                Err(err) => Err(err),
+            },
+            |file| file.delete(account),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{CredentialStore, EncryptedFile};
+    use std::error::Error;
+
+    #[test]
+    fn test_encrypted_file() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("credentials.json");
+
+        let store = EncryptedFile::with_passphrase(&path, "hunter2");
+        assert_eq!(store.get("work")?, None);
+        store.set("work", "pg&abc123")?;
+        store.set("home", "dang&def456")?;
+        assert_eq!(store.get("work")?.as_deref(), Some("pg&abc123"));
+        assert!(!std::fs::read_to_string(&path)?.contains("abc123"));
+        assert!(EncryptedFile::with_passphrase(&path, "wrong")
+            .get("work")
+            .is_err());
+        assert!(store.delete("work")?);
+        assert!(!store.delete("work")?);
+        assert_eq!(store.get("home")?.as_deref(), Some("dang&def456"));
+
+        // Without a passphrase a random key file is created beside the secrets
+        let path = dir.path().join("keyed.json");
+        let store = EncryptedFile {
+            path: path.clone(),
+            key: super::KeySource::KeyFile(path.with_extension("key")),
+        };
+        store.set("default", "pg&abc123")?;
+        assert!(path.with_extension("key").exists());
+        assert_eq!(store.get("default")?.as_deref(), Some("pg&abc123"));
+
+        // Entries under one key are not mixed with entries under another
+        let passphrase = EncryptedFile::with_passphrase(&path, "hunter2");
+        assert!(passphrase.set("work", "dang&def456").is_err());
+        assert!(passphrase.get("default").is_err());
+        assert_eq!(store.get("default")?.as_deref(), Some("pg&abc123"));
+        assert_eq!(store.get("work")?, None);
+
+        Ok(())
+    }
+}
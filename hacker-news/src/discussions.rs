@@ -0,0 +1,271 @@
+//! Find the Hacker News discussions of a given URL.
+//!
+//! The same article is often submitted under slightly different URLs, so both
+//! the requested URL and the URL of every candidate story are normalised with
+//! [normalize_url] before they are compared. Candidates come either from an
+//! Algolia compatible [SearchClient], or from the local [Store].
+
+use crate::api::{Id, Item, Score};
+use crate::error::HnError;
+use crate::search::{SearchClient, SearchQuery, Tag};
+use crate::store::Store;
+use futures::stream::StreamExt;
+use reqwest::Url;
+use std::cmp::Reverse;
+use std::error::Error;
+
+/// Query parameters of known ad and analytics trackers, which only track
+/// where a visitor came from and are dropped by [normalize_url] along with
+/// `utm_*`. Generic names such as `ref` or `source` select content on some
+/// sites, so they are kept.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "twclid", "ttclid",
+    "igshid", "mc_cid", "mc_eid", "ref_src", "smid", "_hsenc", "_hsmi", "mkt_tok",
+];
+
+/// A story discussing a URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discussion {
+    pub id: Id,
+    pub title: String,
+    pub url: String,
+    pub by: Option<String>,
+    /// Creation date of the story, in Unix Time.
+    pub time: u64,
+    pub points: Option<Score>,
+    pub num_comments: Option<u32>,
+}
+
+/// Normalise a URL so different spellings of the same address compare equal.
+///
+/// The scheme, `www.` prefix, fragment, trailing slashes and tracking query
+/// parameters such as `utm_source` are removed, the host is lower cased and
+/// the remaining query parameters are sorted. The result is not a valid URL,
+/// i.e. `https://www.example.com/a/?utm_source=hn&b=1#top` becomes
+/// `example.com/a?b=1`.
+pub fn normalize_url(url: &str) -> Result<String, HnError> {
+    let url = url.trim();
+    let with_scheme = if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("http://{}", url)
+    };
+    let parsed =
+        Url::parse(&with_scheme).map_err(|_| HnError::ArgumentError(Some("not a valid URL")))?;
+    let host = parsed
+        .host_str()
+        .ok_or(HnError::ArgumentError(Some("URL has no host")))?
+        .to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+
+    let mut normalized = host.to_string();
+    if let Some(port) = parsed.port() {
+        normalized.push_str(&format!(":{}", port));
+    }
+    normalized.push_str(parsed.path().trim_end_matches('/'));
+
+    let mut params: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| {
+            let key = key.to_lowercase();
+            !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if !params.is_empty() {
+        params.sort();
+        let query = params
+            .iter()
+            .map(|(key, value)| match value.as_str() {
+                "" => key.clone(),
+                value => format!("{}={}", key, value),
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        normalized.push('?');
+        normalized.push_str(&query);
+    }
+
+    Ok(normalized)
+}
+
+fn matches(candidate: Option<&str>, target: &str) -> bool {
+    candidate
+        .and_then(|url| normalize_url(url).ok())
+        .map(|url| url == target)
+        .unwrap_or(false)
+}
+
+fn sort(mut discussions: Vec<Discussion>) -> Vec<Discussion> {
+    discussions.sort_by_key(|d| Reverse(d.time));
+    discussions.dedup_by_key(|d| d.id);
+    discussions
+}
+
+/// Find the stories submitting `url` through an Algolia compatible search API,
+/// newest first.
+pub async fn find_discussions(
+    client: &SearchClient,
+    url: &str,
+) -> Result<Vec<Discussion>, Box<dyn Error>> {
+    let target = normalize_url(url)?;
+    let query = SearchQuery::new(target.clone())
+        .tag(Tag::Story)
+        .restrict_to_url()
+        .hits_per_page(100);
+
+    let mut discussions = vec![];
+    // Matching on the url attribute is fuzzy, so only look at the first few
+    // pages of candidates and keep the exact matches.
+    let mut stream = Box::pin(client.search_stream(query).take(500));
+    while let Some(hit) = stream.next().await {
+        let hit = hit?;
+        if !matches(hit.url.as_deref(), &target) {
+            continue;
+        }
+        if let Some(id) = hit.id() {
+            discussions.push(Discussion {
+                id,
+                title: hit.title.unwrap_or_default(),
+                url: hit.url.unwrap_or_default(),
+                by: hit.author,
+                time: hit.created_at_i,
+                points: hit.points,
+                num_comments: hit.num_comments,
+            });
+        }
+    }
+
+    Ok(sort(discussions))
+}
+
+/// Find the stories submitting `url` in the local [Store], newest first.
+pub fn find_discussions_local(store: &Store, url: &str) -> Result<Vec<Discussion>, Box<dyn Error>> {
+    let target = normalize_url(url)?;
+    // SAFE: normalize_url always starts with the host
+    let host = target.split(['/', '?', ':']).next().unwrap();
+
+    let discussions = store
+        .stories_with_url_like(&format!("%{}%", host))?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::Story(story) if matches(story.url.as_deref(), &target) => Some(Discussion {
+                id: story.id,
+                title: story.title,
+                url: story.url.unwrap_or_default(),
+                by: story.by,
+                time: story.time,
+                points: story.score,
+                num_comments: story.descendants,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    Ok(sort(discussions))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{find_discussions, find_discussions_local, normalize_url};
+    use crate::api::{Item, Story};
+    use crate::search::SearchClient;
+    use crate::store::Store;
+    use crate::testing;
+    use serde_json::json;
+    use std::error::Error;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_normalize_url() {
+        let expected = "example.com/post?id=1&page=2";
+        for url in [
+            "https://www.example.com/post/?page=2&id=1",
+            "http://example.com/post?id=1&page=2&utm_source=hn&utm_medium=rss",
+            "HTTPS://WWW.EXAMPLE.COM/post?fbclid=abc&id=1&page=2#comments",
+            "example.com/post?page=2&id=1",
+        ] {
+            assert_eq!(normalize_url(url).unwrap(), expected, "{}", url);
+        }
+        assert_eq!(
+            normalize_url("https://example.com/").unwrap(),
+            "example.com"
+        );
+        assert_ne!(
+            normalize_url("https://example.com/Post").unwrap(),
+            normalize_url("https://example.com/post").unwrap()
+        );
+        assert_eq!(
+            normalize_url("https://example.com/post?ref=v2&source=feed&gclid=1&ttclid=2").unwrap(),
+            "example.com/post?ref=v2&source=feed"
+        );
+        assert!(normalize_url("http://").is_err());
+    }
+
+    fn story(id: u64, time: u64, url: &str) -> Item {
+        Item::Story(Story {
+            time,
+            descendants: Some(3),
+            score: Some(10),
+            url: Some(url.to_string()),
+            ..testing::story(id, vec![])
+        })
+    }
+
+    #[test]
+    fn test_find_discussions_local() -> Result<(), Box<dyn Error>> {
+        let mut store = Store::open_in_memory()?;
+        store.upsert_item(&story(1, 100, "https://example.com/post"))?;
+        store.upsert_item(&story(2, 200, "http://www.example.com/post/?utm_source=x"))?;
+        store.upsert_item(&story(3, 300, "https://example.com/post/other"))?;
+
+        let found = find_discussions_local(&store, "example.com/post")?;
+        let ids: Vec<u64> = found.iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_discussions_remote() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        let hit = |id: u64, time: u64, url: &str| {
+            json!({
+                "objectID": id.to_string(),
+                "author": "pg",
+                "created_at_i": time,
+                "title": "A post",
+                "url": url,
+                "points": 5,
+                "num_comments": 2,
+                "_tags": ["story"],
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/search"))
+            .and(query_param("restrictSearchableAttributes", "url"))
+            .and(query_param("query", "example.com/post"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "hits": [
+                    hit(1, 100, "https://example.com/post"),
+                    hit(2, 200, "https://example.com/post/other"),
+                    hit(3, 300, "https://www.example.com/post#top"),
+                ],
+                "nbHits": 3,
+                "page": 0,
+                "nbPages": 1,
+                "hitsPerPage": 100,
+            })))
+            .mount(&server)
+            .await;
+
+        let client = SearchClient::with_base_url(server.uri());
+        let found = find_discussions(&client, "https://example.com/post?utm_campaign=a").await?;
+        let ids: Vec<u64> = found.iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+
+        Ok(())
+    }
+}
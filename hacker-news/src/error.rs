@@ -1,6 +1,7 @@
 use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
+use std::io;
 use std::io::Write;
 use termcolor::BufferWriter;
 use termcolor::Color;
@@ -25,8 +26,8 @@ pub enum HnError {
     // Error used when program attempts to invoke an action requiring authentication,
     // but is not authenticated
     UnauthenticatedError,
-    // Error used when a client fails to authenticate
-    AuthenticationError,
+    // Error used when a client fails to authenticate, i.e. a bad password or a captcha challenge
+    AuthenticationError(Option<&'static str>),
     // Error raised from a failure during an HTTP request/response
     HttpError(HttpError),
     // Error raised from Network connectivity problems
@@ -37,6 +38,21 @@ pub enum HnError {
     SerializationError(Option<&'static str>),
     // Error converting a non top level item into a listing, i.e. a Comment or PollOption.
     ListingError(Option<&'static str>),
+    // Error reading from or writing to local storage, i.e. the SQLite store.
+    StorageError(Option<Box<dyn Error>>),
+    // Error when requested data does not exist in a source, i.e. an offline cache.
+    NotFoundError(String),
+    // Error when HN refuses an action of the logged in user, i.e. downvoting without enough karma.
+    NotAllowedError(String),
+    // Error when HN refuses a post because the user is posting too fast.
+    RateLimitError(String),
+    // Error when a submitted URL was already posted, with the id of the existing story.
+    DuplicateError(u64),
+    // Error extracting data from an HTML page, i.e. after the markup of news.ycombinator.com changed.
+    ScrapeError(String),
+    // Error with what the program was doing when the wrapped error happened, i.e. the item it
+    // was loading.
+    Context(String, Box<HnError>),
     // HACK: catch all variant
     Unknown,
 }
@@ -50,9 +66,10 @@ impl Display for HnError {
                     "An unauthenticated client attempted an action requiring authentication."
                 )
             }
-            HnError::AuthenticationError => {
-                write!(f, "A Hacker News client failed to authenticate.")
-            }
+            HnError::AuthenticationError(msg) => match msg {
+                Some(msg) => write!(f, "A Hacker News client failed to authenticate. {}.", msg),
+                None => write!(f, "A Hacker News client failed to authenticate."),
+            },
             HnError::HttpError(http_err) => {
                 write!(
                     f,
@@ -60,10 +77,7 @@ impl Display for HnError {
                     http_err.url, http_err.code,
                 )
             }
-            HnError::NetworkError(src) => match src {
-                Some(src) => write!(f, "Failed to make network request. {}", src.to_string()),
-                None => write!(f, "Failed to make network request."),
-            },
+            HnError::NetworkError(_src) => write!(f, "Failed to make network request."),
             HnError::ArgumentError(msg) => match msg {
                 None => write!(f, "Incorrect argument configuration."),
                 Some(msg) => write!(f, "Incorrect argument configuration. {}.", msg),
@@ -76,6 +90,15 @@ impl Display for HnError {
                 Some(msg) => write!(f, "Failed to convert item to top level listing. {}.", msg),
                 None => write!(f, "Failed to convert item to top level listing."),
             },
+            HnError::StorageError(_src) => write!(f, "Failed to access local storage."),
+            HnError::NotFoundError(what) => write!(f, "Could not find {}.", what),
+            HnError::NotAllowedError(msg) => write!(f, "Action not allowed. {}.", msg),
+            HnError::RateLimitError(msg) => write!(f, "Rate limited by Hacker News. {}.", msg),
+            HnError::DuplicateError(id) => {
+                write!(f, "The URL was already submitted, see item {}.", id)
+            }
+            HnError::ScrapeError(msg) => write!(f, "Failed to parse HTML page. {}.", msg),
+            HnError::Context(context, _err) => write!(f, "{}.", context),
             HnError::Unknown => {
                 write!(f, "uknown error.")
             }
@@ -87,15 +110,106 @@ impl HnError {
     pub fn variant_str(&self) -> &'static str {
         match self {
             HnError::UnauthenticatedError => "UnauthenticatedError",
-            HnError::AuthenticationError => "AuthenticationError",
+            HnError::AuthenticationError(_msg) => "AuthenticationError",
             HnError::HttpError(_http_err) => "HttpError",
             HnError::NetworkError(_source) => "NetworkErr",
             HnError::ArgumentError(_msg) => "ArgumentError",
             HnError::SerializationError(_msg) => "SerializationError",
             HnError::ListingError(_msg) => "ListingError",
+            HnError::StorageError(_source) => "StorageError",
+            HnError::NotFoundError(_what) => "NotFoundError",
+            HnError::NotAllowedError(_msg) => "NotAllowedError",
+            HnError::RateLimitError(_msg) => "RateLimitError",
+            HnError::DuplicateError(_id) => "DuplicateError",
+            HnError::ScrapeError(_msg) => "ScrapeError",
+            HnError::Context(_context, err) => err.variant_str(),
             HnError::Unknown => "Unknown",
         }
     }
+
+    /// Wrap this error with what the program was doing when it happened, i.e.
+    /// `could not load the thread of item 1`.
+    pub fn context(self: Box<Self>, context: impl Into<String>) -> Box<HnError> {
+        Box::new(HnError::Context(context.into(), self))
+    }
+
+    /// The process exit code of this class of error, so scripts can tell
+    /// failures apart:
+    ///
+    /// * 1: an unexpected error
+    /// * 2: incorrect arguments or configuration
+    /// * 3: the requested data does not exist
+    /// * 4: the user is not, or failed to get, authenticated
+    /// * 5: Hacker News refused the action, i.e. rate limited or a duplicate
+    /// * 6: a network failure or unsuccessful HTTP response
+    /// * 7: a failure reading or writing local storage
+    /// * 8: data that could not be parsed, i.e. a changed HTML page
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HnError::ArgumentError(_msg) => 2,
+            HnError::NotFoundError(_what) => 3,
+            HnError::UnauthenticatedError | HnError::AuthenticationError(_) => 4,
+            HnError::NotAllowedError(_)
+            | HnError::RateLimitError(_)
+            | HnError::DuplicateError(_) => 5,
+            HnError::HttpError(_) | HnError::NetworkError(_) => 6,
+            HnError::StorageError(_) => 7,
+            HnError::SerializationError(_) | HnError::ListingError(_) | HnError::ScrapeError(_) => {
+                8
+            }
+            HnError::Context(_context, err) => err.exit_code(),
+            HnError::Unknown => 1,
+        }
+    }
+
+    /// Whether the error, or one of its causes, is the reader of the output
+    /// going away, i.e. `hn news | head -1`, which ends the output early
+    /// rather than failing the command.
+    pub fn is_broken_pipe(&self) -> bool {
+        let mut source = self.source();
+        while let Some(err) = source {
+            match err.downcast_ref::<io::Error>() {
+                Some(err) if err.kind() == io::ErrorKind::BrokenPipe => return true,
+                _ => source = err.source(),
+            }
+        }
+
+        false
+    }
+
+    /// A suggestion of how the user could resolve this error, if there is one.
+    pub fn hint(&self) -> Option<String> {
+        let hint = match self {
+            HnError::UnauthenticatedError => "log in with `hn login` first",
+            HnError::AuthenticationError(_msg) => {
+                "check the username and password, HN may also ask for a captcha after repeated attempts"
+            }
+            HnError::HttpError(http_err) if http_err.code == 429 || http_err.code >= 500 => {
+                "Hacker News may be overloaded, try again later or lower the rate_limit setting"
+            }
+            HnError::NetworkError(_src) => {
+                "check the network connection, or read a local copy with --source db or --source cache=<dir>"
+            }
+            HnError::ArgumentError(_msg) => "see `hn help` for the usage of each subcommand",
+            HnError::StorageError(_src) => {
+                "check the permissions of the data and cache directories, see `hn config show`"
+            }
+            HnError::RateLimitError(_msg) => "wait a few minutes before posting again",
+            HnError::DuplicateError(id) => {
+                return Some(format!(
+                    "see https://news.ycombinator.com/item?id={}",
+                    id
+                ))
+            }
+            HnError::ScrapeError(_msg) => {
+                "the layout of news.ycombinator.com may have changed, please report a bug"
+            }
+            HnError::Context(_context, err) => return err.hint(),
+            _ => return None,
+        };
+
+        Some(hint.to_string())
+    }
 }
 
 struct Colorizer {
@@ -105,46 +219,54 @@ struct Colorizer {
     pieces: Vec<(String, Style)>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum Style {
     Good,
     Warning,
     Error,
     Hint,
+    // Secondary information, i.e. the age and id of a comment.
+    Meta,
+    #[default]
     Default,
 }
 
-impl Default for Style {
-    fn default() -> Self {
-        Self::Default
+impl Style {
+    /// The terminal color of the style.
+    pub fn spec(&self) -> ColorSpec {
+        let mut color = ColorSpec::new();
+        match self {
+            Style::Good => {
+                color.set_fg(Some(Color::Green));
+            }
+            Style::Warning => {
+                color.set_fg(Some(Color::Yellow));
+            }
+            Style::Error => {
+                color.set_fg(Some(Color::Red));
+                color.set_bold(true);
+            }
+            Style::Hint => {
+                color.set_dimmed(true);
+                color.set_italic(true);
+            }
+            Style::Meta => {
+                color.set_dimmed(true);
+            }
+            Style::Default => {}
+        }
+
+        color
     }
 }
 
 impl Colorizer {
-    pub fn print(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let writer = BufferWriter::stdout(ColorChoice::Always);
+    pub fn print(&self, color: ColorChoice) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufferWriter::stderr(color);
         let mut buffer = writer.buffer();
 
         for piece in self.pieces.iter() {
-            let mut color = ColorSpec::new();
-            match piece.1 {
-                Style::Good => {
-                    color.set_fg(Some(Color::Green));
-                }
-                Style::Warning => {
-                    color.set_fg(Some(Color::Yellow));
-                }
-                Style::Error => {
-                    color.set_fg(Some(Color::Red));
-                    color.set_bold(true);
-                }
-                Style::Hint => {
-                    color.set_dimmed(true);
-                    color.set_italic(true);
-                }
-                Style::Default => {}
-            }
-
+            let color = piece.1.spec();
             buffer.set_color(&color)?;
             buffer.write_all(piece.0.as_bytes())?;
             buffer.reset()?;
@@ -157,22 +279,118 @@ impl Colorizer {
 }
 
 impl HnError {
-    pub fn formatted_print(&self) {
+    /// Print the error, its chain of causes and a hint to stderr, colored
+    /// according to `color`.
+    pub fn formatted_print(&self, color: ColorChoice) {
         let mut colorizer = Colorizer { pieces: vec![] };
 
         colorizer.pieces.push(("error: ".to_string(), Style::Error));
         colorizer
             .pieces
             .push((format!("{}\n", self), Style::Default));
-        colorizer
-            .pieces
-            .push((format!("{}\n", self.variant_str()), Style::Hint));
+        let mut shown = self.to_string();
+        let mut source = self.source();
+        while let Some(err) = source {
+            // Some errors, i.e. of reqwest, already include their cause in
+            // their message.
+            let message = err.to_string();
+            if !shown.contains(&message) {
+                colorizer
+                    .pieces
+                    .push(("  caused by: ".to_string(), Style::Warning));
+                colorizer
+                    .pieces
+                    .push((format!("{}\n", message), Style::Default));
+                shown = message;
+            }
+            source = err.source();
+        }
+        if let Some(hint) = self.hint() {
+            colorizer
+                .pieces
+                .push((format!("  hint: {}\n", hint), Style::Hint));
+        }
 
-        if let Err(_err) = colorizer.print() {
+        if let Err(_err) = colorizer.print(color) {
             log::error!("Failed formatted color print of {}", self);
             eprintln!("error: {}", self);
         }
     }
 }
 
-impl Error for HnError {}
+impl Error for HnError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HnError::NetworkError(Some(src)) | HnError::StorageError(Some(src)) => {
+                Some(src.as_ref())
+            }
+            HnError::Context(_context, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{HnError, HttpError};
+    use crate::cli::lib_err;
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn test_broken_pipe() {
+        let err = lib_err(Box::new(io::Error::from(io::ErrorKind::BrokenPipe)))
+            .context("failed to write output");
+        assert!(err.is_broken_pipe());
+
+        let err = lib_err(Box::new(io::Error::from(io::ErrorKind::PermissionDenied)))
+            .context("failed to write output");
+        assert!(!err.is_broken_pipe());
+        assert_eq!(err.exit_code(), 7);
+    }
+
+    #[test]
+    fn test_exit_code_and_hint() {
+        let err = Box::new(HnError::NotFoundError("item 1".to_string()))
+            .context("could not load the thread of item 1");
+        assert_eq!(err.exit_code(), 3);
+        assert_eq!(err.hint(), None);
+        assert_eq!(err.to_string(), "could not load the thread of item 1.");
+
+        let err = Box::new(HnError::HttpError(HttpError::new(503, "url".to_string())))
+            .context("could not load the top stories")
+            .context("could not list stories");
+        assert_eq!(err.exit_code(), 6);
+        assert!(err.hint().unwrap().contains("overloaded"));
+        assert_eq!(
+            HnError::HttpError(HttpError::new(404, "url".to_string())).hint(),
+            None
+        );
+
+        assert_eq!(HnError::DuplicateError(8863).exit_code(), 5);
+        assert_eq!(
+            HnError::DuplicateError(8863).hint().as_deref(),
+            Some("see https://news.ycombinator.com/item?id=8863")
+        );
+        assert_eq!(HnError::UnauthenticatedError.exit_code(), 4);
+        assert_eq!(HnError::Unknown.exit_code(), 1);
+    }
+
+    #[test]
+    fn test_lib_err_classes() {
+        let json = serde_json::from_str::<u64>("null").unwrap_err();
+        let err = lib_err(Box::new(json));
+        assert_eq!(err.exit_code(), 8);
+        assert!(err.hint().is_none());
+
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let err = lib_err(Box::new(io));
+        assert_eq!(err.exit_code(), 7);
+        assert!(err.source().is_some());
+
+        let err = lib_err(Box::new(HnError::RateLimitError("slow down".to_string())));
+        assert_eq!(err.exit_code(), 5);
+        assert_eq!(lib_err("unexpected".into()).exit_code(), 1);
+    }
+}
@@ -0,0 +1,391 @@
+//! Full-text search over locally stored [Item]s.
+//!
+//! The [SearchIndex] is an embedded [tantivy] index of the title and text of
+//! items, with the HTML of the text stripped before it is tokenised. Queries
+//! can be filtered by author, item type, time and score, and ranked either by
+//! relevance or by date.
+
+use crate::api::{Id, Item, Score};
+use crate::error::HnError;
+use crate::render::to_plain;
+use std::error::Error;
+use std::ops::Bound;
+use std::path::Path;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, RangeQuery, TermQuery};
+use tantivy::schema::{
+    Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, STRING, TEXT,
+};
+use tantivy::{doc, DocAddress, Index, IndexReader, Order, ReloadPolicy, TantivyDocument, Term};
+
+const WRITER_MEMORY_BUDGET: usize = 50_000_000;
+
+/// How the results of a [LocalQuery] are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ranking {
+    /// Best matching items first.
+    #[default]
+    Relevance,
+    /// Newest items first.
+    Date,
+}
+
+/// A query against a [SearchIndex].
+#[derive(Debug, Clone)]
+pub struct LocalQuery {
+    /// Query text, in the tantivy query syntax. Matches everything when empty.
+    pub text: String,
+    /// Only match items by this author.
+    pub author: Option<String>,
+    /// Only match items of this type, i.e. "story" or "comment".
+    pub kind: Option<String>,
+    /// Only match items created at or after this Unix Time.
+    pub since: Option<u64>,
+    /// Only match items created before this Unix Time.
+    pub until: Option<u64>,
+    /// Only match items with at least this score.
+    pub min_score: Option<Score>,
+    pub ranking: Ranking,
+    /// Maximum number of results.
+    pub limit: usize,
+}
+
+impl LocalQuery {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            author: None,
+            kind: None,
+            since: None,
+            until: None,
+            min_score: None,
+            ranking: Ranking::default(),
+            limit: 20,
+        }
+    }
+}
+
+/// A single result of a [LocalQuery].
+#[derive(Debug, Clone)]
+pub struct LocalHit {
+    pub id: Id,
+    pub kind: String,
+    pub by: Option<String>,
+    pub time: u64,
+    pub score: Option<Score>,
+    pub title: Option<String>,
+    /// The item's text, with HTML stripped.
+    pub text: Option<String>,
+    /// Relevance of the hit. Zero when ranking by date.
+    pub relevance: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fields {
+    id: Field,
+    kind: Field,
+    by: Field,
+    time: Field,
+    score: Field,
+    title: Field,
+    text: Field,
+}
+
+/// An embedded full-text index of [Item]s.
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    fields: Fields,
+}
+
+fn index_err(err: tantivy::TantivyError) -> Box<dyn Error> {
+    Box::new(HnError::StorageError(Some(Box::new(err))))
+}
+
+impl SearchIndex {
+    fn schema() -> (Schema, Fields) {
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            id: builder.add_u64_field("id", INDEXED | STORED | FAST),
+            kind: builder.add_text_field("type", STRING | STORED),
+            by: builder.add_text_field("by", STRING | STORED),
+            time: builder.add_u64_field("time", INDEXED | STORED | FAST),
+            score: builder.add_u64_field("score", INDEXED | STORED | FAST),
+            title: builder.add_text_field("title", TEXT | STORED),
+            text: builder.add_text_field("text", TEXT | STORED),
+        };
+
+        (builder.build(), fields)
+    }
+
+    /// Open the index in `dir`, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        let (schema, fields) = Self::schema();
+        let directory = MmapDirectory::open(dir.as_ref())
+            .map_err(|err| HnError::StorageError(Some(Box::new(err))))?;
+        let index = Index::open_or_create(directory, schema).map_err(index_err)?;
+        Self::with_index(index, fields)
+    }
+
+    /// Create an index which only lives in memory.
+    pub fn open_in_ram() -> Result<Self, Box<dyn Error>> {
+        let (schema, fields) = Self::schema();
+        Self::with_index(Index::create_in_ram(schema), fields)
+    }
+
+    fn with_index(index: Index, fields: Fields) -> Result<Self, Box<dyn Error>> {
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .map_err(index_err)?;
+
+        Ok(Self {
+            index,
+            reader,
+            fields,
+        })
+    }
+
+    /// Add, or replace, items in the index. Deleted items and items without a
+    /// title or text are skipped. Returns the number of items indexed.
+    pub fn add_items<'a>(
+        &self,
+        items: impl IntoIterator<Item = &'a Item>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut writer = self
+            .index
+            .writer::<TantivyDocument>(WRITER_MEMORY_BUDGET)
+            .map_err(index_err)?;
+        let f = self.fields;
+        let mut count = 0;
+        for item in items {
+            writer.delete_term(Term::from_field_u64(f.id, item.id()));
+            if item.deleted() || (item.title().is_none() && item.text().is_none()) {
+                continue;
+            }
+            let mut doc = doc!(
+                f.id => item.id(),
+                f.kind => item.kind(),
+                f.time => item.time(),
+            );
+            if let Some(by) = item.by() {
+                doc.add_text(f.by, by);
+            }
+            if let Some(score) = item.score() {
+                doc.add_u64(f.score, score);
+            }
+            if let Some(title) = item.title() {
+                doc.add_text(f.title, title);
+            }
+            if let Some(text) = item.text() {
+                doc.add_text(f.text, to_plain(text));
+            }
+            writer.add_document(doc).map_err(index_err)?;
+            count += 1;
+        }
+        writer.commit().map_err(index_err)?;
+        self.reader.reload().map_err(index_err)?;
+
+        Ok(count)
+    }
+
+    /// Number of items in the index.
+    pub fn len(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run a query against the index.
+    pub fn search(&self, query: &LocalQuery) -> Result<Vec<LocalHit>, Box<dyn Error>> {
+        let f = self.fields;
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![];
+        if query.text.trim().is_empty() {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        } else {
+            let parser = QueryParser::for_index(&self.index, vec![f.title, f.text]);
+            let parsed = parser.parse_query(&query.text).map_err(|err| {
+                Box::new(HnError::ArgumentError(Some(
+                    "a query is words, \"exact phrases\", title:word or text:word, combined with AND, OR, + and -",
+                )))
+                .context(err.to_string()) as Box<dyn Error>
+            })?;
+            clauses.push((Occur::Must, parsed));
+        }
+        if let Some(ref author) = query.author {
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(f.by, author),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        if let Some(ref kind) = query.kind {
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(f.kind, kind),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        if query.since.is_some() || query.until.is_some() {
+            let lower = query.since.map(Bound::Included).unwrap_or(Bound::Unbounded);
+            let upper = query.until.map(Bound::Excluded).unwrap_or(Bound::Unbounded);
+            clauses.push((
+                Occur::Must,
+                Box::new(RangeQuery::new_u64_bounds("time".to_string(), lower, upper)),
+            ));
+        }
+        if let Some(min_score) = query.min_score {
+            clauses.push((
+                Occur::Must,
+                Box::new(RangeQuery::new_u64_bounds(
+                    "score".to_string(),
+                    Bound::Included(min_score),
+                    Bound::Unbounded,
+                )),
+            ));
+        }
+        let boolean = BooleanQuery::new(clauses);
+
+        let searcher = self.reader.searcher();
+        let limit = query.limit.max(1);
+        let addresses: Vec<(f32, DocAddress)> = match query.ranking {
+            Ranking::Relevance => searcher
+                .search(&boolean, &TopDocs::with_limit(limit))
+                .map_err(index_err)?,
+            Ranking::Date => searcher
+                .search(
+                    &boolean,
+                    &TopDocs::with_limit(limit).order_by_fast_field::<u64>("time", Order::Desc),
+                )
+                .map_err(index_err)?
+                .into_iter()
+                .map(|(_time, address)| (0.0, address))
+                .collect(),
+        };
+
+        let mut hits = Vec::with_capacity(addresses.len());
+        for (relevance, address) in addresses {
+            let doc: TantivyDocument = searcher.doc(address).map_err(index_err)?;
+            let u64_of = |field| doc.get_first(field).and_then(|v| v.as_u64());
+            let str_of = |field| {
+                doc.get_first(field)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+            };
+            hits.push(LocalHit {
+                id: u64_of(f.id).unwrap_or_default(),
+                kind: str_of(f.kind).unwrap_or_default(),
+                by: str_of(f.by),
+                time: u64_of(f.time).unwrap_or_default(),
+                score: u64_of(f.score),
+                title: str_of(f.title),
+                text: str_of(f.text),
+                relevance,
+            });
+        }
+
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{LocalQuery, Ranking, SearchIndex};
+    use crate::api::{Comment, Item, Story};
+    use crate::error::HnError;
+    use std::error::Error;
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item::Story(Story {
+                id: 1,
+                deleted: false,
+                by: Some("dang".to_string()),
+                time: 1000,
+                dead: false,
+                kids: Some(vec![2, 3]),
+                descendants: Some(2),
+                score: Some(150),
+                title: "Tokio 2.0 released".to_string(),
+                url: None,
+                text: None,
+            }),
+            Item::Comment(Comment {
+                id: 2,
+                deleted: false,
+                by: Some("pg".to_string()),
+                time: 2000,
+                dead: false,
+                kids: None,
+                parent: Some(1),
+                text: Some("I&#x27;ve used <i>tokio</i> for years.<p>Great runtime".to_string()),
+            }),
+            Item::Comment(Comment {
+                id: 3,
+                deleted: false,
+                by: Some("tptacek".to_string()),
+                time: 3000,
+                dead: false,
+                kids: None,
+                parent: Some(1),
+                text: Some("Nothing to do with <a href=\"x\">async</a>".to_string()),
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_search_filters_and_ranking() -> Result<(), Box<dyn Error>> {
+        let index = SearchIndex::open_in_ram()?;
+        assert_eq!(index.add_items(items().iter())?, 3);
+        // re-adding replaces rather than duplicates
+        index.add_items(items().iter())?;
+        assert_eq!(index.len(), 3);
+
+        let hits = index.search(&LocalQuery::new("tokio"))?;
+        let mut ids: Vec<u64> = hits.iter().map(|hit| hit.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+
+        // html stripped before tokenising: "i" and "p" tags are not words
+        assert!(index.search(&LocalQuery::new("href"))?.is_empty());
+        let hit = &index.search(&LocalQuery::new("runtime"))?[0];
+        assert_eq!(
+            hit.text.as_deref(),
+            Some("I've used tokio for years.\n\nGreat runtime")
+        );
+
+        let mut query = LocalQuery::new("tokio");
+        query.author = Some("pg".to_string());
+        assert_eq!(index.search(&query)?.len(), 1);
+
+        let mut query = LocalQuery::new("");
+        query.kind = Some("comment".to_string());
+        query.ranking = Ranking::Date;
+        let ids: Vec<u64> = index.search(&query)?.iter().map(|hit| hit.id).collect();
+        assert_eq!(ids, vec![3, 2]);
+
+        let mut query = LocalQuery::new("");
+        query.since = Some(1500);
+        query.until = Some(3000);
+        assert_eq!(index.search(&query)?.len(), 1);
+
+        let mut query = LocalQuery::new("");
+        query.min_score = Some(100);
+        assert_eq!(index.search(&query)?[0].id, 1);
+
+        let err = index.search(&LocalQuery::new("title:(tokio")).unwrap_err();
+        assert_eq!(err.downcast::<HnError>().unwrap().exit_code(), 2);
+
+        Ok(())
+    }
+}
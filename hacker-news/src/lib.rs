@@ -1,9 +1,23 @@
 //! A library for interacting with Hacker News.
 
+pub mod alerts;
+pub mod archive;
+pub mod auth;
 pub mod client;
 pub mod config;
+pub mod credentials;
+pub mod discussions;
 pub mod error;
+pub mod index;
+pub mod notify;
+pub mod render;
 // pub mod parser;
 pub mod api;
 pub mod cli;
+pub mod search;
+pub mod source;
+pub mod store;
 pub mod util;
+
+#[cfg(test)]
+mod testing;
@@ -1,5 +1,7 @@
+use hacker_news::cli::color_choice;
 use hacker_news::cli::hacker_news::HackerNews;
 use hacker_news::cli::HnCommand;
+use hacker_news::error::HnError;
 use std::env;
 use std::error::Error;
 use tracing_subscriber::filter::EnvFilter;
@@ -11,12 +13,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .init();
 
     let app = HackerNews::parser();
-    let matches = app.get_matches_from(env::args_os());
+    let matches = match app.get_matches_from_safe(env::args_os()) {
+        Ok(matches) => matches,
+        // Usage errors exit like any other argument error, help and version
+        // are printed to stdout.
+        Err(err) if err.use_stderr() => {
+            eprintln!("{}", err.message);
+            std::process::exit(HnError::ArgumentError(None).exit_code());
+        }
+        Err(err) => err.exit(),
+    };
 
-    // TODO: Implement nice error printing on HnError
     if let Err(err) = HackerNews::cmd(&matches).await {
-        err.formatted_print();
-        std::process::exit(1);
+        if err.is_broken_pipe() {
+            std::process::exit(0);
+        }
+        err.formatted_print(color_choice(&matches));
+        std::process::exit(err.exit_code());
     }
 
     std::process::exit(0);
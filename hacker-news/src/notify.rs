@@ -0,0 +1,318 @@
+//! Reply notifications, which Hacker News itself does not have.
+//!
+//! A [Notifier] tracks the most recent items a user submitted, and the ids of
+//! their direct replies (`kids`). Each check refreshes the tracked items and
+//! reports the replies which were not seen before. The first time an item is
+//! seen its existing replies are only recorded, so starting to watch a user
+//! does not report their whole history.
+//!
+//! The seen replies are kept in a [NotifyState] file, so a reply is reported
+//! once across invocations. Checks can refresh every tracked item
+//! ([Notifier::poll]), or only the items and profiles listed as changed by the
+//! API's `updates.json` ([Notifier::poll_updates]).
+
+use crate::api::{Comment, Id, Item};
+use crate::error::HnError;
+use crate::source::ItemSource;
+use crate::util::check_username;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Number of a user's most recent submissions tracked by default.
+pub const DEFAULT_TRACKED: usize = 50;
+
+/// What a [Notifier] saw during previous checks.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotifyState {
+    /// The tracked submissions of the user, most recent first.
+    pub tracked: Vec<Id>,
+    /// The replies already seen, per tracked submission.
+    pub seen: BTreeMap<Id, Vec<Id>>,
+    /// Time of the last check, in Unix Time.
+    pub last_checked: u64,
+}
+
+impl NotifyState {
+    /// Load the state saved at `path`, or an empty state if there is none.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path.as_ref()) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+}
+
+/// Location of the notification state of `username`, in the data directory
+/// `data_dir`. Fails for usernames HN would not allow, which could name a
+/// file outside of it.
+pub fn state_path(data_dir: &Path, username: &str) -> Result<PathBuf, HnError> {
+    check_username(username)?;
+
+    Ok(data_dir.join("notify").join(format!("{}.json", username)))
+}
+
+/// A new direct reply to one of the user's submissions.
+#[derive(Debug)]
+pub struct Reply {
+    /// The user's story or comment which was replied to.
+    pub parent: Id,
+    pub comment: Comment,
+}
+
+/// Reports new replies to a user's submissions.
+#[derive(Debug)]
+pub struct Notifier {
+    source: Arc<dyn ItemSource>,
+    username: String,
+    state: NotifyState,
+    path: PathBuf,
+    tracked: usize,
+}
+
+impl Notifier {
+    /// Notify about replies to `username`, with the state kept at `path`.
+    ///
+    /// Fresh data is needed to see new replies, so `source` should not be a
+    /// cache.
+    pub fn open(
+        source: Arc<dyn ItemSource>,
+        username: impl Into<String>,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let state = NotifyState::load(&path)?;
+
+        Ok(Self {
+            source,
+            username: username.into(),
+            state,
+            path,
+            tracked: DEFAULT_TRACKED,
+        })
+    }
+
+    /// Track the `tracked` most recent submissions instead of [DEFAULT_TRACKED].
+    pub fn with_tracked(mut self, tracked: usize) -> Self {
+        self.tracked = tracked;
+        self
+    }
+
+    pub fn state(&self) -> &NotifyState {
+        &self.state
+    }
+
+    /// Refresh the user's submissions and all tracked items, returning the
+    /// replies not seen before.
+    pub async fn poll(&mut self) -> Result<Vec<Reply>, Box<dyn Error>> {
+        self.refresh(true, None).await
+    }
+
+    /// Like [Notifier::poll], but only refresh what `updates.json` reported
+    /// as changed: the user's submissions if their profile changed, and the
+    /// tracked items among `items`.
+    pub async fn poll_updates(
+        &mut self,
+        items: &[Id],
+        profiles: &[String],
+    ) -> Result<Vec<Reply>, Box<dyn Error>> {
+        let refresh_user = self.state.tracked.is_empty() || profiles.contains(&self.username);
+        let changed: HashSet<Id> = items.iter().copied().collect();
+        self.refresh(refresh_user, Some(&changed)).await
+    }
+
+    async fn refresh(
+        &mut self,
+        refresh_user: bool,
+        changed: Option<&HashSet<Id>>,
+    ) -> Result<Vec<Reply>, Box<dyn Error>> {
+        // Only kept once every reply was fetched, so a failed check reports
+        // the same replies when it is retried.
+        let mut state = self.state.clone();
+        let mut newly_tracked = HashSet::new();
+        if refresh_user {
+            let user = self
+                .source
+                .get_user(&self.username)
+                .await?
+                .ok_or_else(|| HnError::NotFoundError(format!("user {}", self.username)))?;
+            let tracked: Vec<Id> = user
+                .submitted
+                .unwrap_or_default()
+                .into_iter()
+                .take(self.tracked)
+                .collect();
+            newly_tracked = tracked
+                .iter()
+                .filter(|id| !state.seen.contains_key(id))
+                .copied()
+                .collect();
+            state.seen.retain(|id, _| tracked.contains(id));
+            state.tracked = tracked;
+        }
+
+        let mut replies = vec![];
+        for id in state.tracked.clone() {
+            let is_new = newly_tracked.contains(&id);
+            if !is_new && changed.map(|c| !c.contains(&id)).unwrap_or(false) {
+                continue;
+            }
+            let kids: Vec<Id> = match self.source.get_item(id).await? {
+                Some(item) => item.kids().unwrap_or_default().to_vec(),
+                None => continue,
+            };
+            let seen = state.seen.entry(id).or_default();
+            let unseen: Vec<Id> = kids.into_iter().filter(|kid| !seen.contains(kid)).collect();
+            seen.extend(unseen.iter().copied());
+            if is_new {
+                debug!(id = id, replies = unseen.len(), "started tracking item");
+                continue;
+            }
+            for kid in unseen {
+                if let Some(Item::Comment(comment)) = self.source.get_item(kid).await? {
+                    replies.push(Reply {
+                        parent: id,
+                        comment,
+                    });
+                }
+            }
+        }
+
+        state.last_checked = crate::util::now();
+        state.save(&self.path)?;
+        self.state = state;
+
+        Ok(replies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{state_path, Notifier, NotifyState};
+    use crate::api::{Id, Item, StoryList, User};
+    use crate::source::ItemSource;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Fake {
+        items: Mutex<HashMap<Id, serde_json::Value>>,
+        submitted: Mutex<Vec<Id>>,
+    }
+
+    #[async_trait(?Send)]
+    impl ItemSource for Fake {
+        async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+            let items = self.items.lock().unwrap();
+            Ok(items
+                .get(&id)
+                .cloned()
+                .map(serde_json::from_value)
+                .transpose()?)
+        }
+
+        async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+            let user = json!({
+                "id": username,
+                "created": 1,
+                "karma": 1,
+                "submitted": *self.submitted.lock().unwrap(),
+            });
+            Ok(Some(serde_json::from_value(user)?))
+        }
+
+        async fn list_ids(&self, _list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+            Ok(None)
+        }
+    }
+
+    impl Fake {
+        fn add(&self, id: Id, parent: Option<Id>, kids: &[Id]) {
+            let item = match parent {
+                None => {
+                    json!({"type": "story", "id": id, "by": "pg", "time": id, "title": "t", "kids": kids})
+                }
+                Some(parent) => {
+                    json!({"type": "comment", "id": id, "by": "dang", "time": id, "parent": parent, "text": "hi", "kids": kids})
+                }
+            };
+            self.items.lock().unwrap().insert(id, item);
+        }
+    }
+
+    #[test]
+    fn test_state_path() {
+        assert_eq!(
+            state_path(Path::new("/data"), "pg").unwrap(),
+            Path::new("/data/notify/pg.json")
+        );
+        assert!(state_path(Path::new("/data"), "../../.bashrc").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_poll() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pg.json");
+        let fake = Arc::new(Fake::default());
+        fake.add(1, None, &[2]);
+        fake.add(2, Some(1), &[]);
+        fake.submitted.lock().unwrap().push(1);
+
+        // existing replies are not reported
+        let mut notifier = Notifier::open(fake.clone(), "pg", &path)?;
+        assert!(notifier.poll().await?.is_empty());
+
+        fake.add(3, Some(1), &[]);
+        fake.add(1, None, &[2, 3]);
+        let replies = notifier.poll().await?;
+        assert_eq!(replies.len(), 1);
+        assert_eq!((replies[0].parent, replies[0].comment.id), (1, 3));
+        assert!(notifier.poll().await?.is_empty());
+
+        // seen replies are remembered across invocations
+        let mut notifier = Notifier::open(fake.clone(), "pg", &path)?;
+        assert!(notifier.poll().await?.is_empty());
+        assert_eq!(NotifyState::load(&path)?.seen[&1], vec![2, 3]);
+
+        // only items reported as changed are refreshed
+        fake.add(4, Some(1), &[]);
+        fake.add(1, None, &[2, 3, 4]);
+        assert!(notifier.poll_updates(&[9], &[]).await?.is_empty());
+        let replies = notifier.poll_updates(&[1], &[]).await?;
+        assert_eq!(replies[0].comment.id, 4);
+
+        // a reply failing to load is reported by the next successful poll
+        fake.items
+            .lock()
+            .unwrap()
+            .insert(5, json!({"type": "comment"}));
+        fake.add(1, None, &[2, 3, 4, 5]);
+        assert!(notifier.poll().await.is_err());
+        fake.add(5, Some(1), &[]);
+        let replies = notifier.poll().await?;
+        assert_eq!(replies[0].comment.id, 5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,544 @@
+//! Rendering of the HTML of comment and story text.
+//!
+//! The `text` of an item is HN flavoured HTML: paragraphs separated by
+//! `<p>`, `<i>` italics, `<a href>` links, `<pre><code>` code blocks and
+//! entities such as `&#x27;`. Quotes are paragraphs starting with `>`.
+//! [parse] reads it into [Block]s, which are rendered as plain text by
+//! [to_plain], as Markdown by [to_markdown], or laid out in lines of styled
+//! [Span]s by [layout] for a [Terminal] or the TUI.
+
+use ego_tree::NodeRef;
+use scraper::{Html, Node};
+use unicode_width::UnicodeWidthStr;
+
+/// How a [Span] of text is shown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub italic: bool,
+    pub code: bool,
+    pub quote: bool,
+    /// The URL the text links to.
+    pub link: Option<String>,
+}
+
+/// A run of text in a single [Style].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Span {
+    fn new(text: impl Into<String>, style: Style) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+/// A paragraph level part of a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    Paragraph(Vec<Span>),
+    /// A paragraph starting with `>`, without the marker.
+    Quote(Vec<Span>),
+    /// The verbatim lines of a `<pre>` block.
+    Code(String),
+}
+
+#[derive(Default)]
+struct Parser {
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+}
+
+impl Parser {
+    fn blocks(&mut self, node: NodeRef<Node>) {
+        for child in node.children() {
+            match child.value() {
+                Node::Element(el) if el.name() == "p" => {
+                    self.end_paragraph();
+                    self.blocks(child);
+                    self.end_paragraph();
+                }
+                Node::Element(el) if el.name() == "pre" => {
+                    self.end_paragraph();
+                    let code: String = child
+                        .descendants()
+                        .filter_map(|node| node.value().as_text().map(|text| &text[..]))
+                        .collect();
+                    self.blocks
+                        .push(Block::Code(code.trim_end_matches('\n').to_string()));
+                }
+                _ => self.inline(child, &Style::default()),
+            }
+        }
+        self.end_paragraph();
+    }
+
+    fn inline(&mut self, node: NodeRef<Node>, style: &Style) {
+        match node.value() {
+            Node::Text(text) => self.push(text, style),
+            Node::Element(el) => {
+                let mut style = style.clone();
+                match el.name() {
+                    "i" | "em" => style.italic = true,
+                    "code" => style.code = true,
+                    "a" => style.link = el.attr("href").map(str::to_string),
+                    "br" => self.push(" ", &style),
+                    _ => {}
+                }
+                for child in node.children() {
+                    self.inline(child, &style);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Add text with whitespace runs collapsed, merging it into the last
+    /// span when the style is the same.
+    fn push(&mut self, text: &str, style: &Style) {
+        let mut collapsed = String::with_capacity(text.len());
+        let mut space = false;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                space = true;
+            } else {
+                if space {
+                    collapsed.push(' ');
+                }
+                space = false;
+                collapsed.push(c);
+            }
+        }
+        if space {
+            collapsed.push(' ');
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.style == *style => last.text.push_str(&collapsed),
+            _ => self.spans.push(Span::new(collapsed, style.clone())),
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        let mut spans = std::mem::take(&mut self.spans);
+        if let Some(first) = spans.first_mut() {
+            first.text = first.text.trim_start().to_string();
+        }
+        if let Some(last) = spans.last_mut() {
+            last.text = last.text.trim_end().to_string();
+        }
+        spans.retain(|span| !span.text.is_empty());
+        if spans.is_empty() {
+            return;
+        }
+
+        match spans[0].text.strip_prefix('>') {
+            Some(rest) => {
+                spans[0].text = rest.trim_start().to_string();
+                spans.retain(|span| !span.text.is_empty());
+                for span in spans.iter_mut() {
+                    span.style.quote = true;
+                }
+                self.blocks.push(Block::Quote(spans));
+            }
+            None => self.blocks.push(Block::Paragraph(spans)),
+        }
+    }
+}
+
+/// Read the HTML of an item's text into [Block]s.
+pub fn parse(html: &str) -> Vec<Block> {
+    let fragment = Html::parse_fragment(html);
+    let mut parser = Parser::default();
+    parser.blocks(*fragment.root_element());
+
+    parser.blocks
+}
+
+/// Replace links by their text followed by the URL, or by the URL alone when
+/// the text is the URL, possibly shortened with `...` as HN does.
+fn unlink(spans: &[Span]) -> Vec<Span> {
+    let mut unlinked = Vec::with_capacity(spans.len());
+    for span in spans {
+        let mut style = span.style.clone();
+        let href = match style.link.take() {
+            Some(href) => href,
+            None => {
+                unlinked.push(span.clone());
+                continue;
+            }
+        };
+        let shortened = span
+            .text
+            .strip_suffix("...")
+            .is_some_and(|prefix| href.starts_with(prefix));
+        if span.text == href || shortened {
+            unlinked.push(Span::new(href, style));
+        } else {
+            unlinked.push(Span::new(span.text.clone(), style.clone()));
+            unlinked.push(Span::new(format!(" ({})", href), style));
+        }
+    }
+
+    unlinked
+}
+
+fn text(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+/// Convert the HTML of an item's text into plain text, with paragraphs
+/// separated by a blank line and the URLs of links spelled out.
+pub fn to_plain(html: &str) -> String {
+    let blocks: Vec<String> = parse(html)
+        .iter()
+        .map(|block| match block {
+            Block::Paragraph(spans) => text(&unlink(spans)),
+            Block::Quote(spans) => format!("> {}", text(&unlink(spans))),
+            Block::Code(code) => code
+                .lines()
+                .map(|line| format!("    {}", line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        })
+        .collect();
+
+    blocks.join("\n\n")
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '[' | ']' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn markdown_inline(spans: &[Span]) -> String {
+    let mut markdown = String::new();
+    for span in spans {
+        // Emphasis markers must hug the text, so keep surrounding spaces
+        // outside of them.
+        let trimmed = span.text.trim();
+        let leading = &span.text[..span.text.len() - span.text.trim_start().len()];
+        let trailing = &span.text[span.text.trim_end().len()..];
+        let mut inner = if span.style.code {
+            format!("`{}`", trimmed)
+        } else {
+            markdown_escape(trimmed)
+        };
+        if span.style.italic && !trimmed.is_empty() {
+            inner = format!("*{}*", inner);
+        }
+        if let Some(ref href) = span.style.link {
+            let shortened = span
+                .text
+                .strip_suffix("...")
+                .is_some_and(|prefix| href.starts_with(prefix));
+            inner = if trimmed == href || shortened {
+                format!("<{}>", href)
+            } else {
+                format!("[{}]({})", inner, href)
+            };
+        }
+        markdown.push_str(leading);
+        markdown.push_str(&inner);
+        markdown.push_str(trailing);
+    }
+
+    markdown
+}
+
+/// Convert the HTML of an item's text into Markdown.
+pub fn to_markdown(html: &str) -> String {
+    let blocks: Vec<String> = parse(html)
+        .iter()
+        .map(|block| match block {
+            Block::Paragraph(spans) => markdown_inline(spans),
+            Block::Quote(spans) => format!("> {}", markdown_inline(spans)),
+            Block::Code(code) => format!("```\n{}\n```", code),
+        })
+        .collect();
+
+    blocks.join("\n\n")
+}
+
+/// Break spans into lines no wider than `width`, at whitespace. Words wider
+/// than `width`, i.e. long URLs, get a line of their own.
+pub fn wrap(spans: &[Span], width: usize) -> Vec<Vec<Span>> {
+    // words with their style, and whether whitespace precedes them
+    let mut words: Vec<(&str, &Style, bool)> = vec![];
+    let mut space = false;
+    for span in spans {
+        let mut rest = span.text.as_str();
+        while !rest.is_empty() {
+            let trimmed = rest.trim_start();
+            space |= trimmed.len() < rest.len();
+            let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+            if end > 0 {
+                words.push((&trimmed[..end], &span.style, space));
+                space = false;
+            }
+            rest = &trimmed[end..];
+        }
+    }
+
+    let mut lines = vec![];
+    let mut line: Vec<Span> = vec![];
+    let mut line_width = 0;
+    for (word, style, space) in words {
+        let word_width = word.width();
+        if line_width > 0 && space && line_width + 1 + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        // Spaces between differently styled words are unstyled, so i.e. a
+        // link is not underlined up to the previous word.
+        if space && line_width > 0 {
+            line_width += 1;
+            match line.last_mut() {
+                Some(last) if last.style == *style => last.text.push(' '),
+                _ => line.push(Span::new(" ", Style::default())),
+            }
+        }
+        line_width += word_width;
+        match line.last_mut() {
+            Some(last) if last.style == *style => last.text.push_str(word),
+            _ => line.push(Span::new(word, style.clone())),
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Lay out the HTML of an item's text in lines of spans no wider than
+/// `width`, with a blank line between paragraphs, quotes prefixed by `> ` and
+/// code blocks indented by four spaces. Without `hyperlinks` the URLs of
+/// links are spelled out.
+pub fn layout(html: &str, width: usize, hyperlinks: bool) -> Vec<Vec<Span>> {
+    let mut lines = vec![];
+    for block in parse(html) {
+        if !lines.is_empty() {
+            lines.push(vec![]);
+        }
+        match block {
+            Block::Paragraph(spans) | Block::Quote(spans) if !hyperlinks => {
+                let quote = spans[0].style.quote;
+                lines.extend(prefixed(&unlink(&spans), width, quote));
+            }
+            Block::Paragraph(spans) | Block::Quote(spans) => {
+                let quote = spans[0].style.quote;
+                lines.extend(prefixed(&spans, width, quote));
+            }
+            Block::Code(code) => {
+                let style = Style {
+                    code: true,
+                    ..Style::default()
+                };
+                lines.extend(
+                    code.lines()
+                        .map(|line| vec![Span::new(format!("    {}", line), style.clone())]),
+                );
+            }
+        }
+    }
+
+    lines
+}
+
+fn prefixed(spans: &[Span], width: usize, quote: bool) -> Vec<Vec<Span>> {
+    if !quote {
+        return wrap(spans, width);
+    }
+    let style = Style {
+        quote: true,
+        ..Style::default()
+    };
+    wrap(spans, width.saturating_sub(2))
+        .into_iter()
+        .map(|mut line| {
+            line.insert(0, Span::new("> ", style.clone()));
+            line
+        })
+        .collect()
+}
+
+/// Renders text for a terminal, word wrapped to its width.
+#[derive(Debug, Clone, Copy)]
+pub struct Terminal {
+    /// Columns available, including indentation.
+    pub width: usize,
+    /// Show italics, code, quotes and links with ANSI escape codes.
+    pub styled: bool,
+    /// Make links clickable with OSC 8 escape codes, rather than spelling
+    /// out their URLs.
+    pub hyperlinks: bool,
+}
+
+impl Terminal {
+    /// The narrowest a text is wrapped to, however deep it is indented.
+    const MIN_WIDTH: usize = 20;
+
+    /// A terminal of `width` columns, optionally styled. Styled terminals also
+    /// get hyperlinks.
+    pub fn new(width: usize, styled: bool) -> Self {
+        Self {
+            width,
+            styled,
+            hyperlinks: styled,
+        }
+    }
+
+    /// The width of the terminal on stdout, or of `$COLUMNS`, falling back to
+    /// 80 columns.
+    pub fn stdout_width() -> usize {
+        terminal_size::terminal_size()
+            .map(|(width, _)| width.0 as usize)
+            .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+            .unwrap_or(80)
+    }
+
+    /// The text of `span` with its style and link as escape sequences. Any
+    /// control characters of the text or link, such as an ESC starting an
+    /// escape sequence of its own, are dropped.
+    fn span(&self, span: &Span) -> String {
+        let printable = |text: &str| text.chars().filter(|c| !c.is_control()).collect::<String>();
+        let mut codes = vec![];
+        if self.styled {
+            if span.style.italic {
+                codes.push("3");
+            }
+            if span.style.code {
+                codes.push("2");
+            }
+            if span.style.quote {
+                codes.push("32");
+            }
+            if span.style.link.is_some() {
+                codes.push("4");
+            }
+        }
+        let text = match codes.is_empty() {
+            true => printable(&span.text),
+            false => format!("\x1b[{}m{}\x1b[0m", codes.join(";"), printable(&span.text)),
+        };
+
+        match span.style.link {
+            Some(ref href) if self.hyperlinks => {
+                format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", printable(href), text)
+            }
+            _ => text,
+        }
+    }
+
+    /// Render the HTML of an item's text in lines starting with `indent`.
+    pub fn render(&self, html: &str, indent: &str) -> Vec<String> {
+        let width = self
+            .width
+            .saturating_sub(indent.width())
+            .max(Self::MIN_WIDTH);
+        layout(html, width, self.hyperlinks)
+            .iter()
+            .map(|line| {
+                let text: String = line.iter().map(|span| self.span(span)).collect();
+                match text.is_empty() {
+                    true => indent.trim_end().to_string(),
+                    false => format!("{}{}", indent, text),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{layout, parse, to_markdown, to_plain, wrap, Block, Span, Style, Terminal};
+
+    const HTML: &str = "It&#x27;s <i>fine</i>.<p>See <a href=\"https:&#x2F;&#x2F;example.com&#x2F;a&#x2F;long&#x2F;path\" rel=\"nofollow\">https:&#x2F;&#x2F;example.com&#x2F;a...</a> &amp; <a href=\"https:&#x2F;&#x2F;example.com\">that</a><p>&gt; quoted <i>text</i><p><pre><code>  fn main() {}\n</code></pre>";
+
+    #[test]
+    fn test_parse() {
+        let blocks = parse(HTML);
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(
+            blocks[0],
+            Block::Paragraph(vec![
+                Span::new("It's ", Style::default()),
+                Span::new(
+                    "fine",
+                    Style {
+                        italic: true,
+                        ..Style::default()
+                    }
+                ),
+                Span::new(".", Style::default()),
+            ])
+        );
+        assert!(matches!(blocks[2], Block::Quote(_)));
+        assert_eq!(blocks[3], Block::Code("  fn main() {}".to_string()));
+    }
+
+    #[test]
+    fn test_to_plain_and_markdown() {
+        assert_eq!(
+            to_plain(HTML),
+            "It's fine.\n\nSee https://example.com/a/long/path & that (https://example.com)\n\n> quoted text\n\n      fn main() {}"
+        );
+        assert_eq!(
+            to_markdown(HTML),
+            "It's *fine*.\n\nSee <https://example.com/a/long/path> & [that](https://example.com)\n\n> quoted *text*\n\n```\n  fn main() {}\n```"
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        let spans = vec![Span::new("one two three four", Style::default())];
+        let lines: Vec<String> = wrap(&spans, 9)
+            .iter()
+            .map(|line| line.iter().map(|span| span.text.as_str()).collect())
+            .collect();
+        assert_eq!(lines, vec!["one two", "three", "four"]);
+
+        let lines = layout("&gt; a b c d", 5, false);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][0].text, "> ");
+    }
+
+    #[test]
+    fn test_terminal() {
+        let terminal = Terminal::new(24, false);
+        assert_eq!(
+            terminal.render("a <i>b</i> c d e f g h i j k l m n o p", "  "),
+            vec!["  a b c d e f g h i j k", "  l m n o p"]
+        );
+        let terminal = Terminal::new(80, true);
+        assert_eq!(
+            terminal.render("<a href=\"https://example.com\">x</a>", ""),
+            vec!["\x1b]8;;https://example.com\x1b\\\x1b[4mx\x1b[0m\x1b]8;;\x1b\\"]
+        );
+
+        // Escape sequences in the text or link are not passed to the terminal
+        assert_eq!(
+            terminal.render(
+                "<a href=\"https://e.com/&#x1b;]8;;x&#x7;\">&#x1b;[2Jx&#x7;</a>",
+                ""
+            ),
+            vec!["\x1b]8;;https://e.com/]8;;x\x1b\\\x1b[4m[2Jx\x1b[0m\x1b]8;;\x1b\\"]
+        );
+        assert_eq!(
+            Terminal::new(80, false).render("a&#x1b;[31mb", ""),
+            vec!["a[31mb"]
+        );
+    }
+}
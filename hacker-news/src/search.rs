@@ -0,0 +1,585 @@
+//! Client for the [Algolia Hacker News Search API](https://hn.algolia.com/api).
+//!
+//! The official API has no search, so full-text search of stories and comments
+//! goes through Algolia. Queries are built with [SearchQuery], and hits can be
+//! converted into [Item]s with [SearchHit::into_item].
+
+use crate::api::{Comment, Id, Item, Job, Poll, PollOption, Score, Story};
+use crate::error::{HnError, HttpError};
+use futures::stream::{self, Stream};
+use reqwest::Client as ReqwestClient;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+/// Base URL of the public Algolia API.
+pub const ALGOLIA_BASE_URL: &str = "https://hn.algolia.com/api/v1";
+
+/// A tag to filter search results on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tag {
+    Story,
+    Comment,
+    Poll,
+    PollOption,
+    Job,
+    AskHn,
+    ShowHn,
+    FrontPage,
+    /// Items by the given user.
+    Author(String),
+    /// Items in the thread of the given story.
+    StoryId(Id),
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Story => write!(f, "story"),
+            Tag::Comment => write!(f, "comment"),
+            Tag::Poll => write!(f, "poll"),
+            Tag::PollOption => write!(f, "pollopt"),
+            Tag::Job => write!(f, "job"),
+            Tag::AskHn => write!(f, "ask_hn"),
+            Tag::ShowHn => write!(f, "show_hn"),
+            Tag::FrontPage => write!(f, "front_page"),
+            Tag::Author(author) => write!(f, "author_{}", author),
+            Tag::StoryId(id) => write!(f, "story_{}", id),
+        }
+    }
+}
+
+impl std::str::FromStr for Tag {
+    type Err = HnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tag = match s {
+            "story" => Tag::Story,
+            "comment" => Tag::Comment,
+            "poll" => Tag::Poll,
+            "pollopt" => Tag::PollOption,
+            "job" => Tag::Job,
+            "ask_hn" => Tag::AskHn,
+            "show_hn" => Tag::ShowHn,
+            "front_page" => Tag::FrontPage,
+            other => match (other.strip_prefix("author_"), other.strip_prefix("story_")) {
+                (Some(author), _) if !author.is_empty() => Tag::Author(author.to_string()),
+                (_, Some(id)) => Tag::StoryId(id.parse().map_err(|_| {
+                    HnError::ArgumentError(Some("story_ tag must be followed by an item id"))
+                })?),
+                _ => {
+                    return Err(HnError::ArgumentError(Some(
+                        "tag must be one of story, comment, poll, pollopt, job, ask_hn, show_hn, front_page, author_<user> or story_<id>",
+                    )))
+                }
+            },
+        };
+
+        Ok(tag)
+    }
+}
+
+/// A numeric attribute which can be filtered on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericField {
+    Points,
+    NumComments,
+    /// Creation time, in Unix Time.
+    CreatedAt,
+}
+
+impl NumericField {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NumericField::Points => "points",
+            NumericField::NumComments => "num_comments",
+            NumericField::CreatedAt => "created_at_i",
+        }
+    }
+}
+
+/// Comparison of a [NumericField] against a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Eq => "=",
+            Comparison::Ge => ">=",
+            Comparison::Gt => ">",
+        }
+    }
+}
+
+/// Order of search results, which selects the API endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchOrder {
+    /// Sorted by relevance, then points, then number of comments.
+    #[default]
+    Relevance,
+    /// Sorted by date, most recent first.
+    Date,
+}
+
+/// A typed search query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    text: String,
+    tags: Vec<Vec<Tag>>,
+    numeric_filters: Vec<(NumericField, Comparison, u64)>,
+    order: SearchOrder,
+    page: u32,
+    hits_per_page: Option<u32>,
+    restrict_to_url: bool,
+}
+
+impl SearchQuery {
+    /// A query for `text`. An empty text matches everything.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Only return results with this tag.
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.tags.push(vec![tag]);
+        self
+    }
+
+    /// Only return results with at least one of these tags.
+    pub fn any_tag(mut self, tags: Vec<Tag>) -> Self {
+        if !tags.is_empty() {
+            self.tags.push(tags);
+        }
+        self
+    }
+
+    /// Only return results where `field` compares to `value`.
+    pub fn numeric(mut self, field: NumericField, cmp: Comparison, value: u64) -> Self {
+        self.numeric_filters.push((field, cmp, value));
+        self
+    }
+
+    /// Only return results with at least this many points.
+    pub fn min_points(self, points: Score) -> Self {
+        self.numeric(NumericField::Points, Comparison::Ge, points)
+    }
+
+    /// Only return results with at least this many comments.
+    pub fn min_comments(self, comments: u64) -> Self {
+        self.numeric(NumericField::NumComments, Comparison::Ge, comments)
+    }
+
+    /// Only return results created at or after this Unix Time.
+    pub fn created_after(self, time: u64) -> Self {
+        self.numeric(NumericField::CreatedAt, Comparison::Ge, time)
+    }
+
+    /// Only return results created before this Unix Time.
+    pub fn created_before(self, time: u64) -> Self {
+        self.numeric(NumericField::CreatedAt, Comparison::Lt, time)
+    }
+
+    pub fn order(mut self, order: SearchOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Zero-based page of results to start from.
+    pub fn page(mut self, page: u32) -> Self {
+        self.page = page;
+        self
+    }
+
+    pub fn hits_per_page(mut self, hits_per_page: u32) -> Self {
+        self.hits_per_page = Some(hits_per_page);
+        self
+    }
+
+    /// Only match the query text against the `url` attribute.
+    pub fn restrict_to_url(mut self) -> Self {
+        self.restrict_to_url = true;
+        self
+    }
+
+    fn endpoint(&self) -> &'static str {
+        match self.order {
+            SearchOrder::Relevance => "search",
+            SearchOrder::Date => "search_by_date",
+        }
+    }
+
+    /// The query string parameters of this query.
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = vec![("query", self.text.clone())];
+        if !self.tags.is_empty() {
+            let tags = self
+                .tags
+                .iter()
+                .map(|group| match group.as_slice() {
+                    [tag] => tag.to_string(),
+                    group => format!(
+                        "({})",
+                        group
+                            .iter()
+                            .map(Tag::to_string)
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    ),
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            params.push(("tags", tags));
+        }
+        if !self.numeric_filters.is_empty() {
+            let filters = self
+                .numeric_filters
+                .iter()
+                .map(|(field, cmp, value)| format!("{}{}{}", field.as_str(), cmp.as_str(), value))
+                .collect::<Vec<_>>()
+                .join(",");
+            params.push(("numericFilters", filters));
+        }
+        if self.restrict_to_url {
+            params.push(("restrictSearchableAttributes", "url".to_string()));
+        }
+        params.push(("page", self.page.to_string()));
+        if let Some(hits_per_page) = self.hits_per_page {
+            params.push(("hitsPerPage", hits_per_page.to_string()));
+        }
+
+        params
+    }
+}
+
+/// A single search result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    /// The item id, as a string.
+    #[serde(rename = "objectID")]
+    pub object_id: String,
+    pub author: Option<String>,
+    /// Creation date of the item, in Unix Time.
+    pub created_at_i: u64,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub points: Option<Score>,
+    pub num_comments: Option<u32>,
+    /// The text of an Ask HN, Show HN or job. HTML.
+    pub story_text: Option<String>,
+    /// The text of a comment. HTML.
+    pub comment_text: Option<String>,
+    /// The story a comment belongs to.
+    pub story_id: Option<Id>,
+    /// The title of the story a comment belongs to.
+    pub story_title: Option<String>,
+    pub parent_id: Option<Id>,
+    #[serde(rename = "_tags", default)]
+    pub tags: Vec<String>,
+}
+
+impl SearchHit {
+    /// The item id of this hit.
+    pub fn id(&self) -> Option<Id> {
+        self.object_id.parse().ok()
+    }
+
+    /// The type of item, taken from the hit's tags.
+    pub fn kind(&self) -> &str {
+        ["story", "comment", "poll", "pollopt", "job"]
+            .into_iter()
+            .find(|kind| self.tags.iter().any(|tag| tag == kind))
+            .unwrap_or("story")
+    }
+
+    /// Convert this hit into an [Item]. Search results do not include the
+    /// ids of an item's comments, so `kids` is always `None`.
+    pub fn into_item(self) -> Option<Item> {
+        let id = self.id()?;
+        let item = match self.kind() {
+            "comment" => Item::Comment(Comment {
+                id,
+                deleted: false,
+                by: self.author,
+                time: self.created_at_i,
+                dead: false,
+                kids: None,
+                parent: self.parent_id,
+                text: self.comment_text,
+            }),
+            "poll" => Item::Poll(Poll {
+                id,
+                deleted: false,
+                by: self.author,
+                time: self.created_at_i,
+                dead: false,
+                kids: None,
+                parts: None,
+                descendants: self.num_comments,
+                score: self.points,
+                title: self.title.unwrap_or_default(),
+                text: self.story_text,
+            }),
+            "pollopt" => Item::PollOption(PollOption {
+                id,
+                deleted: false,
+                by: self.author,
+                time: self.created_at_i,
+                dead: false,
+                kids: None,
+                parent: self.parent_id,
+                score: self.points,
+            }),
+            "job" => Item::Job(Job {
+                id,
+                deleted: false,
+                by: self.author,
+                time: self.created_at_i,
+                dead: false,
+                kids: None,
+                text: self.story_text,
+                url: self.url,
+                title: self.title.unwrap_or_default(),
+            }),
+            _ => Item::Story(Story {
+                id,
+                deleted: false,
+                by: self.author,
+                time: self.created_at_i,
+                dead: false,
+                kids: None,
+                descendants: self.num_comments,
+                score: self.points,
+                title: self.title.unwrap_or_default(),
+                url: self.url,
+                text: self.story_text,
+            }),
+        };
+
+        Some(item)
+    }
+}
+
+/// A single page of search results.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    /// Total number of hits across all pages.
+    #[serde(rename = "nbHits")]
+    pub nb_hits: u64,
+    /// Zero-based index of this page.
+    pub page: u32,
+    /// Total number of pages.
+    #[serde(rename = "nbPages")]
+    pub nb_pages: u32,
+    #[serde(rename = "hitsPerPage")]
+    pub hits_per_page: u32,
+}
+
+/// Client for the Algolia Hacker News Search API.
+#[derive(Debug, Clone)]
+pub struct SearchClient {
+    http_client: ReqwestClient,
+    base_url: String,
+}
+
+#[allow(clippy::new_without_default)]
+impl SearchClient {
+    pub fn new() -> Self {
+        Self::with_base_url(ALGOLIA_BASE_URL)
+    }
+
+    /// Use a different base URL than [ALGOLIA_BASE_URL], i.e. a local stub or
+    /// a self hosted mirror of the API.
+    pub fn with_base_url(base_url: impl Into<String>) -> Self {
+        Self {
+            http_client: ReqwestClient::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Retrieve the page of results selected by the query.
+    #[tracing::instrument(skip(self))]
+    pub async fn search(&self, query: &SearchQuery) -> Result<SearchPage, Box<dyn Error>> {
+        let url = format!("{}/{}", self.base_url, query.endpoint());
+        let resp = self
+            .http_client
+            .get(&url)
+            .query(&query.params())
+            .send()
+            .await?;
+        let status = resp.status().as_u16();
+        if status != 200 {
+            return Err(Box::new(HnError::HttpError(HttpError::new(
+                status,
+                resp.url().to_string(),
+            ))));
+        }
+        let text = resp.text().await?;
+        log::debug!("text = {:?}", text);
+        let page: SearchPage = serde_json::from_str(&text)?;
+
+        Ok(page)
+    }
+
+    /// Stream every hit of a query, starting at the query's page and fetching
+    /// further pages as the stream is consumed.
+    pub fn search_stream(
+        &self,
+        query: SearchQuery,
+    ) -> impl Stream<Item = Result<SearchHit, Box<dyn Error>>> + '_ {
+        let buffer: VecDeque<SearchHit> = VecDeque::new();
+        stream::unfold(
+            (Some(query), buffer),
+            move |(mut next, mut buffer)| async move {
+                loop {
+                    if let Some(hit) = buffer.pop_front() {
+                        return Some((Ok(hit), (next, buffer)));
+                    }
+                    let query = next.take()?;
+                    match self.search(&query).await {
+                        Ok(page) => {
+                            if page.page + 1 < page.nb_pages && !page.hits.is_empty() {
+                                let page_num = page.page + 1;
+                                next = Some(query.page(page_num));
+                            }
+                            buffer.extend(page.hits);
+                        }
+                        Err(err) => return Some((Err(err), (None, buffer))),
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{SearchClient, SearchOrder, SearchQuery, Tag};
+    use crate::api::Item;
+    use futures::StreamExt;
+    use serde_json::json;
+    use std::error::Error;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn page(page: u32, nb_pages: u32, ids: &[u64]) -> serde_json::Value {
+        let hits: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "objectID": id.to_string(),
+                    "author": "pg",
+                    "created_at_i": 1_160_418_111,
+                    "title": "Y Combinator",
+                    "url": "http://ycombinator.com",
+                    "points": 57,
+                    "num_comments": 15,
+                    "_tags": ["story", "author_pg", format!("story_{}", id)],
+                })
+            })
+            .collect();
+        json!({
+            "hits": hits,
+            "nbHits": ids.len(),
+            "page": page,
+            "nbPages": nb_pages,
+            "hitsPerPage": 2,
+        })
+    }
+
+    #[test]
+    fn test_query_params() {
+        let query = SearchQuery::new("rust")
+            .tag(Tag::Story)
+            .any_tag(vec![Tag::Author("pg".to_string()), Tag::ShowHn])
+            .min_points(100)
+            .created_after(1_600_000_000)
+            .hits_per_page(50);
+        let params = query.params();
+        assert!(params.contains(&("tags", "story,(author_pg,show_hn)".to_string())));
+        assert!(params.contains(&(
+            "numericFilters",
+            "points>=100,created_at_i>=1600000000".to_string()
+        )));
+        assert!(params.contains(&("hitsPerPage", "50".to_string())));
+        assert_eq!(
+            "author_dang".parse::<Tag>().unwrap(),
+            Tag::Author("dang".to_string())
+        );
+        assert_eq!("story_42".parse::<Tag>().unwrap(), Tag::StoryId(42));
+        assert!("author_".parse::<Tag>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_stream_pages() -> Result<(), Box<dyn Error>> {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/search_by_date"))
+            .and(query_param("query", "rust"))
+            .and(query_param("tags", "story"))
+            .and(query_param("page", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(0, 2, &[1, 2])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/search_by_date"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(page(1, 2, &[3])))
+            .mount(&server)
+            .await;
+
+        let client = SearchClient::with_base_url(server.uri());
+        let query = SearchQuery::new("rust")
+            .tag(Tag::Story)
+            .order(SearchOrder::Date);
+        let hits: Vec<_> = client.search_stream(query).collect().await;
+        let ids: Vec<u64> = hits
+            .into_iter()
+            .map(|hit| hit.map(|hit| hit.id().unwrap()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let first = client
+            .search(
+                &SearchQuery::new("rust")
+                    .tag(Tag::Story)
+                    .order(SearchOrder::Date),
+            )
+            .await?;
+        match first.hits[0].clone().into_item() {
+            Some(Item::Story(story)) => {
+                assert_eq!(story.score, Some(57));
+                assert_eq!(story.descendants, Some(15));
+            }
+            other => panic!("unexpected item {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_http_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let client = SearchClient::with_base_url(server.uri());
+        assert!(client.search(&SearchQuery::new("rust")).await.is_err());
+    }
+}
@@ -0,0 +1,643 @@
+//! Abstraction over where [Item]s, [User]s and story lists come from.
+//!
+//! An [ItemSource] can be the Hacker News API ([Client]), an on-disk JSON cache
+//! ([DiskCache]), an item archive ([ArchiveReader]) or the SQLite store
+//! ([Store]). A [Layered] source tries several sources in order, and writes
+//! anything found in a later source through to the earlier ones, i.e. a
+//! `cache,network` source fills the cache as items are fetched from the API.
+//!
+//! Sources can be described by a [SourceSpec], a comma separated list of
+//! `network`, `cache[=dir]`, `archive=dir` or `db[=path]`, which is what the
+//! `--source` flag of the CLI and the `HN_SOURCE` environment variable accept.
+
+use crate::api::{Id, Item, StoryList, User};
+use crate::archive::ArchiveReader;
+use crate::client::Client;
+use crate::config;
+use crate::error::HnError;
+use crate::store::Store;
+use crate::util::check_username;
+use async_trait::async_trait;
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, StreamExt};
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// How many items [ItemSource::get_items] retrieves at a time, unless the
+/// source is configured otherwise.
+const DEFAULT_CONCURRENCY: usize = 10;
+
+/// A source of Hacker News data.
+///
+/// The `get_*` and `list_ids` methods return `Ok(None)` when the source does
+/// not have the requested data, so a [Layered] source can fall back to the
+/// next source. The `put_*` methods are used to write data through to a
+/// source, and do nothing for read-only sources.
+#[async_trait(?Send)]
+pub trait ItemSource: Debug + Send + Sync {
+    /// Retrieve an [Item] by id.
+    async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>>;
+
+    /// Retrieve a [User] by username.
+    async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>>;
+
+    /// Retrieve the ids of a [StoryList], in rank order.
+    async fn list_ids(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>>;
+
+    /// Store an [Item] in this source.
+    async fn put_item(&self, _item: &Item) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Store a [User] in this source.
+    async fn put_user(&self, _user: &User) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Store the ids of a [StoryList] in this source.
+    async fn put_list(&self, _list: StoryList, _ids: &[Id]) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Retrieve several [Item]s concurrently, in the order of `ids`. Fails if
+    /// any item can not be found.
+    async fn get_items(&self, ids: &[Id]) -> Result<Vec<Item>, Box<dyn Error>> {
+        get_buffered(self, ids, DEFAULT_CONCURRENCY).await
+    }
+}
+
+/// Retrieve the items `ids` from `source`, at most `limit` at a time.
+async fn get_buffered<S: ItemSource + ?Sized>(
+    source: &S,
+    ids: &[Id],
+    limit: usize,
+) -> Result<Vec<Item>, Box<dyn Error>> {
+    stream::iter(ids)
+        .map(|id| async move {
+            source.get_item(*id).await?.ok_or_else(|| {
+                Box::new(HnError::NotFoundError(format!("item {}", id))) as Box<dyn Error>
+            })
+        })
+        .buffered(limit)
+        .collect::<Vec<Result<Item, Box<dyn Error>>>>()
+        .await
+        .into_iter()
+        .collect()
+}
+
+/// Turn the [HnError::NotFoundError] of a [Client] call into `Ok(None)`, so a
+/// [Layered] source looks in its next source.
+fn found<T>(result: Result<T, Box<dyn Error>>) -> Result<Option<T>, Box<dyn Error>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => match err.downcast_ref::<HnError>() {
+            Some(HnError::NotFoundError(_what)) => Ok(None),
+            _ => Err(err),
+        },
+    }
+}
+
+#[async_trait(?Send)]
+impl ItemSource for Client {
+    async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        found(self.item(id).await)
+    }
+
+    async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+        found(self.user(username.to_string()).await)
+    }
+
+    async fn list_ids(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+        self.stories(list).await.map(Some)
+    }
+
+    async fn get_items(&self, ids: &[Id]) -> Result<Vec<Item>, Box<dyn Error>> {
+        self.items(ids).await
+    }
+}
+
+#[async_trait(?Send)]
+impl ItemSource for ArchiveReader {
+    async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        self.item(id).await
+    }
+
+    async fn get_user(&self, _username: &str) -> Result<Option<User>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    async fn list_ids(&self, _list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+fn lock(store: &Mutex<Store>) -> Result<std::sync::MutexGuard<'_, Store>, Box<dyn Error>> {
+    store
+        .lock()
+        .map_err(|_| Box::new(HnError::StorageError(None)) as Box<dyn Error>)
+}
+
+#[async_trait(?Send)]
+impl ItemSource for Mutex<Store> {
+    async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        lock(self)?.item(id)
+    }
+
+    async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+        lock(self)?.user(username)
+    }
+
+    async fn list_ids(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+        lock(self)?.latest_list(list)
+    }
+
+    async fn put_item(&self, item: &Item) -> Result<(), Box<dyn Error>> {
+        lock(self)?.upsert_item(item)
+    }
+
+    async fn put_user(&self, user: &User) -> Result<(), Box<dyn Error>> {
+        lock(self)?.upsert_user(user)
+    }
+
+    async fn put_list(&self, list: StoryList, ids: &[Id]) -> Result<(), Box<dyn Error>> {
+        lock(self)?.snapshot_list(list, ids).map(|_| ())
+    }
+}
+
+/// A cache of API responses stored as JSON files on disk.
+///
+/// Items and users are kept until they are removed from the directory. Story
+/// lists change constantly, so they are only returned while they are younger
+/// than `list_ttl`.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+    list_ttl: Duration,
+}
+
+impl DiskCache {
+    /// Default lifetime of a cached story list.
+    pub const DEFAULT_LIST_TTL: Duration = Duration::from_secs(5 * 60);
+
+    /// Use `dir` as a cache directory, creating it if needed.
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let dir = dir.as_ref().to_path_buf();
+        for sub in ["items", "users", "lists"] {
+            fs::create_dir_all(dir.join(sub))?;
+        }
+
+        Ok(Self {
+            dir,
+            list_ttl: Self::DEFAULT_LIST_TTL,
+        })
+    }
+
+    /// Set how long a cached story list is considered fresh.
+    pub fn with_list_ttl(mut self, list_ttl: Duration) -> Self {
+        self.list_ttl = list_ttl;
+        self
+    }
+
+    fn read<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, Box<dyn Error>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    fn write<T: serde::Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(value)?)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    fn item_path(&self, id: Id) -> PathBuf {
+        self.dir.join("items").join(format!("{}.json", id))
+    }
+
+    fn user_path(&self, username: &str) -> Result<PathBuf, Box<dyn Error>> {
+        check_username(username)?;
+
+        Ok(self.dir.join("users").join(format!("{}.json", username)))
+    }
+
+    fn list_path(&self, list: StoryList) -> PathBuf {
+        self.dir
+            .join("lists")
+            .join(format!("{}.json", list.as_str()))
+    }
+}
+
+#[async_trait(?Send)]
+impl ItemSource for DiskCache {
+    async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        Self::read(&self.item_path(id))
+    }
+
+    async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+        Self::read(&self.user_path(username)?)
+    }
+
+    async fn list_ids(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+        let path = self.list_path(list);
+        let fresh = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map(|age| age <= self.list_ttl)
+            .unwrap_or(false);
+        if !fresh {
+            return Ok(None);
+        }
+
+        Self::read(&path)
+    }
+
+    async fn put_item(&self, item: &Item) -> Result<(), Box<dyn Error>> {
+        Self::write(&self.item_path(item.id()), item)
+    }
+
+    async fn put_user(&self, user: &User) -> Result<(), Box<dyn Error>> {
+        Self::write(&self.user_path(&user.id)?, user)
+    }
+
+    async fn put_list(&self, list: StoryList, ids: &[Id]) -> Result<(), Box<dyn Error>> {
+        Self::write(&self.list_path(list), ids)
+    }
+}
+
+/// Several sources tried in order. Data found in a source is written through
+/// to every source before it.
+#[derive(Debug, Default)]
+pub struct Layered {
+    sources: Vec<Arc<dyn ItemSource>>,
+    concurrency: usize,
+}
+
+impl Layered {
+    pub fn new(sources: Vec<Arc<dyn ItemSource>>) -> Self {
+        Self {
+            sources,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// Retrieve at most `concurrency` items at a time from
+    /// [ItemSource::get_items].
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Add a source, tried after all sources added before it.
+    pub fn push(mut self, source: Arc<dyn ItemSource>) -> Self {
+        self.sources.push(source);
+        self
+    }
+}
+
+impl Layered {
+    /// Get data from the first source which has it, with the index of that
+    /// source. A source failing, i.e. the network while offline, is skipped,
+    /// and its error only returned when no later source has the data either.
+    async fn first<'a, T>(
+        &'a self,
+        get: impl Fn(&'a dyn ItemSource) -> LocalBoxFuture<'a, Result<Option<T>, Box<dyn Error>>>,
+    ) -> Result<Option<(usize, T)>, Box<dyn Error>> {
+        let mut failure = None;
+        for (idx, source) in self.sources.iter().enumerate() {
+            match get(source.as_ref()).await {
+                Ok(Some(value)) => {
+                    debug!(layer = idx, "found");
+                    return Ok(Some((idx, value)));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    warn!(err = %err, layer = idx, "source failed, trying the next one");
+                    failure.get_or_insert(err);
+                }
+            }
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Log a failure to write data through to an earlier source, which should not
+/// fail reading it.
+fn write_through(result: Result<(), Box<dyn Error>>) {
+    if let Err(err) = result {
+        warn!(err = %err, "failed to write through to an earlier source");
+    }
+}
+
+#[async_trait(?Send)]
+impl ItemSource for Layered {
+    async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        let (idx, item) = match self.first(|source| source.get_item(id)).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        for earlier in self.sources[..idx].iter() {
+            write_through(earlier.put_item(&item).await);
+        }
+
+        Ok(Some(item))
+    }
+
+    async fn get_user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+        let (idx, user) = match self.first(|source| source.get_user(username)).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        for earlier in self.sources[..idx].iter() {
+            write_through(earlier.put_user(&user).await);
+        }
+
+        Ok(Some(user))
+    }
+
+    async fn list_ids(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+        let (idx, ids) = match self.first(|source| source.list_ids(list)).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        for earlier in self.sources[..idx].iter() {
+            write_through(earlier.put_list(list, &ids).await);
+        }
+
+        Ok(Some(ids))
+    }
+
+    async fn get_items(&self, ids: &[Id]) -> Result<Vec<Item>, Box<dyn Error>> {
+        get_buffered(self, ids, self.concurrency).await
+    }
+
+    async fn put_item(&self, item: &Item) -> Result<(), Box<dyn Error>> {
+        for source in self.sources.iter() {
+            source.put_item(item).await?;
+        }
+        Ok(())
+    }
+
+    async fn put_user(&self, user: &User) -> Result<(), Box<dyn Error>> {
+        for source in self.sources.iter() {
+            source.put_user(user).await?;
+        }
+        Ok(())
+    }
+
+    async fn put_list(&self, list: StoryList, ids: &[Id]) -> Result<(), Box<dyn Error>> {
+        for source in self.sources.iter() {
+            source.put_list(list, ids).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Description of a single layer of a [SourceSpec].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceKind {
+    /// The Hacker News API.
+    Network,
+    /// A [DiskCache], in the default cache directory if no path is given.
+    Cache(Option<PathBuf>),
+    /// An item archive.
+    Archive(PathBuf),
+    /// The SQLite store, at the default location if no path is given.
+    Db(Option<PathBuf>),
+}
+
+/// A textual description of a [Layered] source, i.e. `cache,network` or
+/// `archive=/data/hn,db`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceSpec {
+    pub layers: Vec<SourceKind>,
+}
+
+impl Default for SourceSpec {
+    fn default() -> Self {
+        Self {
+            layers: vec![SourceKind::Network],
+        }
+    }
+}
+
+impl FromStr for SourceSpec {
+    type Err = HnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut layers = vec![];
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (kind, path) = match part.split_once('=') {
+                Some((kind, path)) => (kind, Some(PathBuf::from(path))),
+                None => (part, None),
+            };
+            let layer = match (kind, path) {
+                ("network", None) => SourceKind::Network,
+                ("cache", path) => SourceKind::Cache(path),
+                ("archive", Some(path)) => SourceKind::Archive(path),
+                ("db", path) => SourceKind::Db(path),
+                _ => {
+                    return Err(HnError::ArgumentError(Some(
+                        "source must be a comma separated list of network, cache[=dir], archive=dir or db[=path]",
+                    )))
+                }
+            };
+            layers.push(layer);
+        }
+        if layers.is_empty() {
+            return Err(HnError::ArgumentError(Some("source must not be empty")));
+        }
+
+        Ok(Self { layers })
+    }
+}
+
+impl fmt::Display for SourceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, layer) in self.layers.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match layer {
+                SourceKind::Network => write!(f, "network")?,
+                SourceKind::Cache(None) => write!(f, "cache")?,
+                SourceKind::Cache(Some(dir)) => write!(f, "cache={}", dir.display())?,
+                SourceKind::Archive(dir) => write!(f, "archive={}", dir.display())?,
+                SourceKind::Db(None) => write!(f, "db")?,
+                SourceKind::Db(Some(path)) => write!(f, "db={}", path.display())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SourceSpec {
+    /// Open every layer of this spec and combine them into a single source.
+    pub fn build(&self) -> Result<Arc<dyn ItemSource>, Box<dyn Error>> {
+        self.build_with_client(Client::new())
+    }
+
+    /// Like [SourceSpec::build], with `client` as the `network` layer. The
+    /// layers share the concurrency of `client`.
+    pub fn build_with_client(&self, client: Client) -> Result<Arc<dyn ItemSource>, Box<dyn Error>> {
+        let mut sources: Vec<Arc<dyn ItemSource>> = vec![];
+        for layer in self.layers.iter() {
+            let source: Arc<dyn ItemSource> = match layer {
+                SourceKind::Network => Arc::new(client.clone()),
+                SourceKind::Cache(dir) => Arc::new(DiskCache::new(
+                    dir.clone().unwrap_or_else(config::default_cache_dir),
+                )?),
+                SourceKind::Archive(dir) => Arc::new(ArchiveReader::open(dir)?),
+                SourceKind::Db(path) => Arc::new(Mutex::new(Store::open(
+                    path.clone().unwrap_or_else(config::default_db_path),
+                )?)),
+            };
+            sources.push(source);
+        }
+
+        if sources.len() == 1 {
+            // SAFE: checked the length above
+            return Ok(sources.pop().unwrap());
+        }
+        Ok(Arc::new(
+            Layered::new(sources).with_concurrency(client.concurrency()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{DiskCache, ItemSource, Layered, SourceKind, SourceSpec};
+    use crate::api::{Id, Item, StoryList, User};
+    use crate::store::Store;
+    use crate::testing::comment;
+    use async_trait::async_trait;
+    use std::error::Error;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// A source counting how many items are being retrieved at once.
+    #[derive(Debug, Default)]
+    struct Counting {
+        active: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait(?Send)]
+    impl ItemSource for Counting {
+        async fn get_item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(Some(comment(id, 1, vec![], "text")))
+        }
+
+        async fn get_user(&self, _username: &str) -> Result<Option<User>, Box<dyn Error>> {
+            Ok(None)
+        }
+
+        async fn list_ids(&self, _list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_parse_source_spec() {
+        let spec: SourceSpec = "cache, archive=/tmp/hn,db=hn.sqlite3,network"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            spec.layers,
+            vec![
+                SourceKind::Cache(None),
+                SourceKind::Archive(PathBuf::from("/tmp/hn")),
+                SourceKind::Db(Some(PathBuf::from("hn.sqlite3"))),
+                SourceKind::Network,
+            ]
+        );
+        assert!("archive".parse::<SourceSpec>().is_err());
+        assert!("ftp".parse::<SourceSpec>().is_err());
+        assert!("".parse::<SourceSpec>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_layered_write_through() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let cache = Arc::new(DiskCache::new(dir.path())?);
+        let store = Arc::new(Mutex::new(Store::open_in_memory()?));
+        store.put_item(&comment(42, 1, vec![], "text")).await?;
+        store.put_list(StoryList::Top, &[42]).await?;
+
+        let layered = Layered::new(vec![cache.clone(), store]);
+        assert!(cache.get_item(42).await?.is_none());
+        assert!(layered.get_item(42).await?.is_some());
+        assert!(cache.get_item(42).await?.is_some());
+        assert!(layered.get_item(43).await?.is_none());
+
+        assert_eq!(layered.list_ids(StoryList::Top).await?, Some(vec![42]));
+        assert_eq!(cache.list_ids(StoryList::Top).await?, Some(vec![42]));
+        assert_eq!(layered.get_items(&[42]).await?.len(), 1);
+        assert!(layered.get_items(&[42, 43]).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_rejects_unsafe_usernames() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let cache = DiskCache::new(dir.path())?;
+        assert!(cache.get_user("pg").await?.is_none());
+        assert!(cache.get_user("../../config").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_layered_concurrency() -> Result<(), Box<dyn Error>> {
+        let counting = Arc::new(Counting::default());
+        let layered = Layered::new(vec![counting.clone()]).with_concurrency(3);
+        assert_eq!(layered.get_items(&[1, 2, 3, 4, 5, 6, 7]).await?.len(), 7);
+        assert_eq!(counting.peak.load(Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_layered_skips_failing_source() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let cache = Arc::new(DiskCache::new(dir.path())?);
+        std::fs::write(dir.path().join("items").join("42.json"), "{corrupt")?;
+        std::fs::write(dir.path().join("items").join("43.json"), "{corrupt")?;
+        let store = Arc::new(Mutex::new(Store::open_in_memory()?));
+        store.put_item(&comment(42, 1, vec![], "text")).await?;
+
+        let layered = Layered::new(vec![cache.clone(), store]);
+        assert!(cache.get_item(42).await.is_err());
+        assert!(layered.get_item(42).await?.is_some());
+        // written through, replacing the corrupt file
+        assert!(cache.get_item(42).await?.is_some());
+        // no later source has it, so the failure is reported
+        assert!(layered.get_item(43).await.is_err());
+
+        Ok(())
+    }
+}
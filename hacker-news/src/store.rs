@@ -0,0 +1,680 @@
+//! A local mirror of Hacker News data, persisted in SQLite.
+//!
+//! The schema is normalised so the data can be queried directly with SQL:
+//!
+//! - `items`: one row per [Item], holding its latest known state.
+//! - `kids`: parent/child edges between items, in ranked display order. Poll
+//!   options are recorded here as well, with `kind = 'part'`.
+//! - `item_history`: every observed change to an item's `score` or
+//!   `descendants`, with the time it was observed.
+//! - `users`: one row per [User], holding its latest known state.
+//! - `list_snapshots`: the rank of every item in a [StoryList] at the time
+//!   the list was fetched.
+//! - `seen_users`: usernames shown by commands such as `hn user` and
+//!   `hn query`, with the time they were last seen, to complete usernames.
+//!
+//! All timestamps are in Unix Time.
+
+use crate::api::{Comment, Id, Item, Job, Poll, PollOption, Story, StoryList, User};
+use crate::error::HnError;
+use crate::util::now;
+use chrono::DateTime;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::error::Error;
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS items (
+    id          INTEGER PRIMARY KEY,
+    type        TEXT    NOT NULL,
+    by          TEXT,
+    time        INTEGER NOT NULL,
+    deleted     INTEGER NOT NULL DEFAULT 0,
+    dead        INTEGER NOT NULL DEFAULT 0,
+    parent      INTEGER,
+    title       TEXT,
+    url         TEXT,
+    text        TEXT,
+    score       INTEGER,
+    descendants INTEGER,
+    fetched_at  INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS items_by ON items (by);
+CREATE INDEX IF NOT EXISTS items_parent ON items (parent);
+
+CREATE TABLE IF NOT EXISTS kids (
+    parent   INTEGER NOT NULL,
+    kid      INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    kind     TEXT    NOT NULL DEFAULT 'kid',
+    PRIMARY KEY (parent, kind, kid)
+);
+
+CREATE TABLE IF NOT EXISTS item_history (
+    id          INTEGER NOT NULL,
+    observed_at INTEGER NOT NULL,
+    score       INTEGER,
+    descendants INTEGER
+);
+CREATE INDEX IF NOT EXISTS item_history_id ON item_history (id, observed_at);
+
+CREATE TABLE IF NOT EXISTS users (
+    id         TEXT    PRIMARY KEY,
+    created    INTEGER NOT NULL,
+    karma      INTEGER NOT NULL,
+    about      TEXT,
+    delay      INTEGER,
+    fetched_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS submissions (
+    user     TEXT    NOT NULL,
+    item     INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (user, item)
+);
+
+CREATE TABLE IF NOT EXISTS seen_users (
+    name    TEXT    PRIMARY KEY,
+    seen_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS list_snapshots (
+    list     TEXT    NOT NULL,
+    taken_at INTEGER NOT NULL,
+    rank     INTEGER NOT NULL,
+    item     INTEGER NOT NULL,
+    PRIMARY KEY (list, taken_at, rank)
+);
+";
+
+/// A single observed change of an item's score or comment count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// When the change was observed, in Unix Time.
+    pub observed_at: u64,
+    pub score: Option<u64>,
+    pub descendants: Option<u32>,
+}
+
+/// SQLite backed storage of items, users and story list snapshots.
+#[derive(Debug)]
+pub struct Store {
+    conn: Connection,
+}
+
+fn storage_err(err: rusqlite::Error) -> Box<dyn Error> {
+    Box::new(HnError::StorageError(Some(Box::new(err))))
+}
+
+impl Store {
+    /// Open the store at `path`, creating the database and schema if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path).map_err(storage_err)?;
+        Self::init(conn)
+    }
+
+    /// Open a store which only lives in memory.
+    pub fn open_in_memory() -> Result<Self, Box<dyn Error>> {
+        let conn = Connection::open_in_memory().map_err(storage_err)?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self, Box<dyn Error>> {
+        conn.execute_batch(SCHEMA).map_err(storage_err)?;
+        Ok(Self { conn })
+    }
+
+    /// Borrow the underlying connection, i.e. to run ad-hoc queries.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Insert or update an [Item]. A row is added to `item_history` whenever
+    /// the item is new, or its score or descendants changed.
+    pub fn upsert_item(&mut self, item: &Item) -> Result<(), Box<dyn Error>> {
+        self.upsert_item_at(item, now())
+    }
+
+    fn upsert_item_at(&mut self, item: &Item, observed_at: u64) -> Result<(), Box<dyn Error>> {
+        let (kind, parent, title, url, text, score, descendants) = match item {
+            Item::Job(j) => (
+                "job",
+                None,
+                Some(&j.title),
+                j.url.as_ref(),
+                j.text.as_ref(),
+                None,
+                None,
+            ),
+            Item::Story(s) => (
+                "story",
+                None,
+                Some(&s.title),
+                s.url.as_ref(),
+                s.text.as_ref(),
+                s.score,
+                s.descendants,
+            ),
+            Item::Comment(c) => ("comment", c.parent, None, None, c.text.as_ref(), None, None),
+            Item::Poll(p) => (
+                "poll",
+                None,
+                Some(&p.title),
+                None,
+                p.text.as_ref(),
+                p.score,
+                p.descendants,
+            ),
+            Item::PollOption(po) => ("pollopt", po.parent, None, None, None, po.score, None),
+        };
+
+        let tx = self.conn.transaction().map_err(storage_err)?;
+        let previous: Option<(Option<u64>, Option<u32>)> = tx
+            .query_row(
+                "SELECT score, descendants FROM items WHERE id = ?1",
+                params![item.id()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(storage_err)?;
+        tx.execute(
+            "INSERT INTO items
+                (id, type, by, time, deleted, dead, parent, title, url, text, score, descendants, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+             ON CONFLICT (id) DO UPDATE SET
+                type = excluded.type,
+                by = excluded.by,
+                time = excluded.time,
+                deleted = excluded.deleted,
+                dead = excluded.dead,
+                parent = excluded.parent,
+                title = excluded.title,
+                url = excluded.url,
+                text = excluded.text,
+                score = excluded.score,
+                descendants = excluded.descendants,
+                fetched_at = excluded.fetched_at",
+            params![
+                item.id(),
+                kind,
+                item.by(),
+                item.time(),
+                item.deleted(),
+                item.dead(),
+                parent,
+                title,
+                url,
+                text,
+                score,
+                descendants,
+                observed_at,
+            ],
+        )
+        .map_err(storage_err)?;
+
+        if previous != Some((score, descendants)) {
+            tx.execute(
+                "INSERT INTO item_history (id, observed_at, score, descendants) VALUES (?1, ?2, ?3, ?4)",
+                params![item.id(), observed_at, score, descendants],
+            )
+            .map_err(storage_err)?;
+        }
+
+        tx.execute("DELETE FROM kids WHERE parent = ?1", params![item.id()])
+            .map_err(storage_err)?;
+        let parts = match item {
+            Item::Poll(p) => p.parts.as_deref(),
+            _ => None,
+        };
+        for (kind, ids) in [("kid", item.kids()), ("part", parts)] {
+            for (position, kid) in ids.unwrap_or(&[]).iter().enumerate() {
+                tx.execute(
+                    "INSERT OR REPLACE INTO kids (parent, kid, position, kind) VALUES (?1, ?2, ?3, ?4)",
+                    params![item.id(), kid, position, kind],
+                )
+                .map_err(storage_err)?;
+            }
+        }
+        tx.commit().map_err(storage_err)?;
+
+        Ok(())
+    }
+
+    /// Insert or update a [User], replacing its list of submissions.
+    pub fn upsert_user(&mut self, user: &User) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.transaction().map_err(storage_err)?;
+        tx.execute(
+            "INSERT INTO users (id, created, karma, about, delay, fetched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (id) DO UPDATE SET
+                created = excluded.created,
+                karma = excluded.karma,
+                about = excluded.about,
+                delay = excluded.delay,
+                fetched_at = excluded.fetched_at",
+            params![
+                user.id,
+                user.created.timestamp(),
+                user.karma,
+                user.about,
+                user.delay,
+                now()
+            ],
+        )
+        .map_err(storage_err)?;
+        tx.execute("DELETE FROM submissions WHERE user = ?1", params![user.id])
+            .map_err(storage_err)?;
+        for (position, item) in user.submitted.as_deref().unwrap_or(&[]).iter().enumerate() {
+            tx.execute(
+                "INSERT OR REPLACE INTO submissions (user, item, position) VALUES (?1, ?2, ?3)",
+                params![user.id, item, position],
+            )
+            .map_err(storage_err)?;
+        }
+        tx.commit().map_err(storage_err)?;
+
+        Ok(())
+    }
+
+    /// Record the current ranking of a [StoryList]. Returns the timestamp of
+    /// the snapshot.
+    pub fn snapshot_list(&mut self, list: StoryList, ids: &[Id]) -> Result<u64, Box<dyn Error>> {
+        let taken_at = now();
+        let tx = self.conn.transaction().map_err(storage_err)?;
+        // Two snapshots of the same list within a second replace each other.
+        tx.execute(
+            "DELETE FROM list_snapshots WHERE list = ?1 AND taken_at = ?2",
+            params![list.as_str(), taken_at],
+        )
+        .map_err(storage_err)?;
+        for (rank, id) in ids.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO list_snapshots (list, taken_at, rank, item) VALUES (?1, ?2, ?3, ?4)",
+                params![list.as_str(), taken_at, rank + 1, id],
+            )
+            .map_err(storage_err)?;
+        }
+        tx.commit().map_err(storage_err)?;
+
+        Ok(taken_at)
+    }
+
+    /// The most recent snapshot of a [StoryList], in rank order.
+    pub fn latest_list(&self, list: StoryList) -> Result<Option<Vec<Id>>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT item FROM list_snapshots
+                 WHERE list = ?1
+                   AND taken_at = (SELECT MAX(taken_at) FROM list_snapshots WHERE list = ?1)
+                 ORDER BY rank",
+            )
+            .map_err(storage_err)?;
+        let ids = stmt
+            .query_map(params![list.as_str()], |row| row.get(0))
+            .map_err(storage_err)?
+            .collect::<Result<Vec<Id>, _>>()
+            .map_err(storage_err)?;
+
+        Ok(if ids.is_empty() { None } else { Some(ids) })
+    }
+
+    /// Load an [Item] from the store.
+    pub fn item(&self, id: Id) -> Result<Option<Item>, Box<dyn Error>> {
+        let item = self
+            .conn
+            .query_row(
+                "SELECT id, type, by, time, deleted, dead, parent, title, url, text, score, descendants
+                 FROM items WHERE id = ?1",
+                params![id],
+                |row| self.item_from_row(row),
+            )
+            .optional()
+            .map_err(storage_err)?;
+
+        Ok(item)
+    }
+
+    /// Load a [User] from the store.
+    pub fn user(&self, username: &str) -> Result<Option<User>, Box<dyn Error>> {
+        let user = self
+            .conn
+            .query_row(
+                "SELECT id, created, karma, about, delay FROM users WHERE id = ?1",
+                params![username],
+                |row| {
+                    Ok(User {
+                        id: row.get(0)?,
+                        created: DateTime::from_timestamp(row.get::<_, i64>(1)?, 0)
+                            .unwrap_or_default(),
+                        karma: row.get(2)?,
+                        about: row.get(3)?,
+                        delay: row.get(4)?,
+                        submitted: None,
+                    })
+                },
+            )
+            .optional()
+            .map_err(storage_err)?;
+        let mut user = match user {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let submitted = self.edges(
+            "SELECT item FROM submissions WHERE user = ?1 ORDER BY position",
+            &user.id,
+        )?;
+        user.submitted = if submitted.is_empty() {
+            None
+        } else {
+            Some(submitted)
+        };
+
+        Ok(Some(user))
+    }
+
+    /// Record that the users `names` were seen now, i.e. shown by a command.
+    pub fn record_seen_users<'a>(
+        &mut self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.transaction().map_err(storage_err)?;
+        let seen_at = now();
+        for name in names {
+            tx.execute(
+                "INSERT INTO seen_users (name, seen_at) VALUES (?1, ?2)
+                 ON CONFLICT (name) DO UPDATE SET seen_at = excluded.seen_at",
+                params![name, seen_at],
+            )
+            .map_err(storage_err)?;
+        }
+        tx.commit().map_err(storage_err)
+    }
+
+    /// Up to `limit` usernames starting with `prefix`, of the authors of the
+    /// most recently fetched items, the stored profiles and the users seen
+    /// by commands, newest first.
+    pub fn recent_users(&self, prefix: &str, limit: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        let pattern = format!(
+            "{}%",
+            prefix
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name FROM (
+                     SELECT by AS name, fetched_at FROM items WHERE by IS NOT NULL
+                     UNION ALL
+                     SELECT id AS name, fetched_at FROM users
+                     UNION ALL
+                     SELECT name, seen_at AS fetched_at FROM seen_users
+                 )
+                 WHERE name LIKE ?1 ESCAPE '\\'
+                 GROUP BY name ORDER BY MAX(fetched_at) DESC, name LIMIT ?2",
+            )
+            .map_err(storage_err)?;
+        let users = stmt
+            .query_map(params![pattern, limit as i64], |row| row.get(0))
+            .map_err(storage_err)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(storage_err)?;
+
+        Ok(users)
+    }
+
+    /// Every observed change of an item's score and comment count, oldest first.
+    pub fn history(&self, id: Id) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT observed_at, score, descendants FROM item_history
+                 WHERE id = ?1 ORDER BY observed_at, rowid",
+            )
+            .map_err(storage_err)?;
+        let history = stmt
+            .query_map(params![id], |row| {
+                Ok(HistoryEntry {
+                    observed_at: row.get(0)?,
+                    score: row.get(1)?,
+                    descendants: row.get(2)?,
+                })
+            })
+            .map_err(storage_err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_err)?;
+
+        Ok(history)
+    }
+
+    /// Every story whose url matches the SQL `LIKE` pattern.
+    pub fn stories_with_url_like(&self, pattern: &str) -> Result<Vec<Item>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, type, by, time, deleted, dead, parent, title, url, text, score, descendants
+                 FROM items WHERE type = 'story' AND url LIKE ?1 ORDER BY time DESC",
+            )
+            .map_err(storage_err)?;
+        let items = stmt
+            .query_map(params![pattern], |row| self.item_from_row(row))
+            .map_err(storage_err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(storage_err)?;
+
+        Ok(items)
+    }
+
+    /// Call `f` with every [Item] in the store, in id order.
+    pub fn for_each_item(
+        &self,
+        mut f: impl FnMut(Item) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, type, by, time, deleted, dead, parent, title, url, text, score, descendants
+                 FROM items ORDER BY id",
+            )
+            .map_err(storage_err)?;
+        let mut rows = stmt.query([]).map_err(storage_err)?;
+        while let Some(row) = rows.next().map_err(storage_err)? {
+            f(self.item_from_row(row).map_err(storage_err)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn edges<P: rusqlite::ToSql>(&self, sql: &str, key: P) -> Result<Vec<Id>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare_cached(sql).map_err(storage_err)?;
+        let ids = stmt
+            .query_map(params![key], |row| row.get(0))
+            .map_err(storage_err)?
+            .collect::<Result<Vec<Id>, _>>()
+            .map_err(storage_err)?;
+
+        Ok(ids)
+    }
+
+    fn kids(&self, id: Id, kind: &str) -> rusqlite::Result<Option<Vec<Id>>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT kid FROM kids WHERE parent = ?1 AND kind = ?2 ORDER BY position",
+        )?;
+        let ids = stmt
+            .query_map(params![id, kind], |row| row.get(0))?
+            .collect::<Result<Vec<Id>, _>>()?;
+
+        Ok(if ids.is_empty() { None } else { Some(ids) })
+    }
+
+    fn item_from_row(&self, row: &Row) -> rusqlite::Result<Item> {
+        let id: Id = row.get(0)?;
+        let kind: String = row.get(1)?;
+        let by: Option<String> = row.get(2)?;
+        let time: u64 = row.get(3)?;
+        let deleted: bool = row.get(4)?;
+        let dead: bool = row.get(5)?;
+        let parent: Option<Id> = row.get(6)?;
+        let title: Option<String> = row.get(7)?;
+        let url: Option<String> = row.get(8)?;
+        let text: Option<String> = row.get(9)?;
+        let score: Option<u64> = row.get(10)?;
+        let descendants: Option<u32> = row.get(11)?;
+        let kids = self.kids(id, "kid")?;
+
+        let item = match kind.as_str() {
+            "job" => Item::Job(Job {
+                id,
+                deleted,
+                by,
+                time,
+                dead,
+                kids,
+                text,
+                url,
+                title: title.unwrap_or_default(),
+            }),
+            "story" => Item::Story(Story {
+                id,
+                deleted,
+                by,
+                time,
+                dead,
+                kids,
+                descendants,
+                score,
+                title: title.unwrap_or_default(),
+                url,
+                text,
+            }),
+            "comment" => Item::Comment(Comment {
+                id,
+                deleted,
+                by,
+                time,
+                dead,
+                kids,
+                parent,
+                text,
+            }),
+            "poll" => Item::Poll(Poll {
+                id,
+                deleted,
+                by,
+                time,
+                dead,
+                kids,
+                parts: self.kids(id, "part")?,
+                descendants,
+                score,
+                title: title.unwrap_or_default(),
+                text,
+            }),
+            "pollopt" => Item::PollOption(PollOption {
+                id,
+                deleted,
+                by,
+                time,
+                dead,
+                kids,
+                parent,
+                score,
+            }),
+            other => {
+                return Err(rusqlite::Error::InvalidColumnType(
+                    1,
+                    format!("unknown item type '{}'", other),
+                    rusqlite::types::Type::Text,
+                ))
+            }
+        };
+
+        Ok(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Store;
+    use crate::api::{Item, Story, StoryList, User};
+    use crate::testing;
+    use chrono::DateTime;
+    use std::error::Error;
+
+    fn story(score: u64, descendants: u32) -> Item {
+        Item::Story(Story {
+            by: Some("what_ever".to_string()),
+            time: 1623432780,
+            descendants: Some(descendants),
+            score: Some(score),
+            title: "Apple admits it ranked its Files app ahead of competitor Dropbox".to_string(),
+            url: Some("https://www.theverge.com/".to_string()),
+            ..testing::story(27476206, vec![27488169, 27478163])
+        })
+    }
+
+    #[test]
+    fn test_upsert_item_keeps_history() -> Result<(), Box<dyn Error>> {
+        let mut store = Store::open_in_memory()?;
+        store.upsert_item_at(&story(10, 1), 100)?;
+        // unchanged, no new history entry
+        store.upsert_item_at(&story(10, 1), 200)?;
+        store.upsert_item_at(&story(25, 4), 300)?;
+
+        let history = store.history(27476206)?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].observed_at, 100);
+        assert_eq!(history[1].score, Some(25));
+
+        match store.item(27476206)? {
+            Some(Item::Story(s)) => {
+                assert_eq!(s.score, Some(25));
+                assert_eq!(s.kids, Some(vec![27488169, 27478163]));
+            }
+            other => panic!("unexpected item {:?}", other),
+        }
+        assert!(store.item(1)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_and_list_snapshots() -> Result<(), Box<dyn Error>> {
+        let mut store = Store::open_in_memory()?;
+        store.upsert_user(&User {
+            id: "pg".to_string(),
+            delay: None,
+            created: DateTime::from_timestamp(1160418092, 0).unwrap(),
+            karma: 155111,
+            about: Some("Bug fixer.".to_string()),
+            submitted: Some(vec![3, 2, 1]),
+        })?;
+        let user = store.user("pg")?.expect("user stored");
+        assert_eq!(user.karma, 155111);
+        assert_eq!(user.created.timestamp(), 1160418092);
+        assert_eq!(user.submitted, Some(vec![3, 2, 1]));
+
+        store.snapshot_list(StoryList::Top, &[5, 4, 6])?;
+        assert_eq!(store.latest_list(StoryList::Top)?, Some(vec![5, 4, 6]));
+        assert_eq!(store.latest_list(StoryList::New)?, None);
+
+        store.upsert_item_at(&story(10, 1), 100)?;
+        assert_eq!(store.recent_users("", 10)?, vec!["pg", "what_ever"]);
+        assert_eq!(store.recent_users("wh", 10)?, vec!["what_ever"]);
+        assert_eq!(store.recent_users("what%", 10)?, Vec::<String>::new());
+        store.record_seen_users(["dang", "patio11"])?;
+        assert_eq!(
+            store.recent_users("", 10)?,
+            vec!["dang", "patio11", "pg", "what_ever"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+//! Items and sources shared by the tests of several modules.
+
+use crate::api::{Comment, Id, Item, Story};
+use crate::source::ItemSource;
+use crate::store::Store;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+/// A story `id` by pg with the top level comments `kids`. Tests needing other
+/// fields override them, i.e. `Story { score: Some(10), ..story(1, vec![]) }`.
+pub(crate) fn story(id: Id, kids: Vec<Id>) -> Story {
+    Story {
+        id,
+        deleted: false,
+        by: Some("pg".to_string()),
+        time: 0,
+        dead: false,
+        kids: Some(kids),
+        descendants: Some(0),
+        score: Some(1),
+        title: format!("story {}", id),
+        url: None,
+        text: None,
+    }
+}
+
+/// A comment `id` by `user<id>` on `parent`, with the replies `kids`.
+pub(crate) fn comment(id: Id, parent: Id, kids: Vec<Id>, text: &str) -> Item {
+    Item::Comment(Comment {
+        id,
+        deleted: false,
+        by: Some(format!("user{}", id)),
+        time: 0,
+        dead: false,
+        kids: Some(kids),
+        parent: Some(parent),
+        text: Some(text.to_string()),
+    })
+}
+
+/// An in memory [Store] holding `items`, to load threads from.
+pub(crate) async fn store_source(
+    items: impl IntoIterator<Item = Item>,
+) -> Result<Arc<Mutex<Store>>, Box<dyn Error>> {
+    let source = Arc::new(Mutex::new(Store::open_in_memory()?));
+    for item in items {
+        source.put_item(&item).await?;
+    }
+
+    Ok(source)
+}
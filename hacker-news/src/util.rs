@@ -1,3 +1,6 @@
+use crate::error::HnError;
+use std::io::Write;
+
 /// Try initializing tracing subscriber. Useful in context of unit
 /// tests where depending on the exact `cargo test` invocation
 /// tracing may or may not already be initialized. I.e. when running
@@ -5,3 +8,155 @@
 pub fn setup() {
     let _ = tracing_subscriber::fmt::try_init();
 }
+
+/// Parse a point in time given either as Unix Time, or as a duration before
+/// now such as `90m`, `12h` or `7days`. Returns Unix Time.
+pub fn parse_since(s: &str) -> Result<u64, HnError> {
+    if let Ok(time) = s.parse::<u64>() {
+        return Ok(time);
+    }
+    let ago = humantime::parse_duration(s).map_err(|_| {
+        HnError::ArgumentError(Some(
+            "time must be Unix Time or a duration such as 90m, 12h or 7days",
+        ))
+    })?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Ok(now.saturating_sub(ago).as_secs())
+}
+
+/// Check that `username` only has the characters HN allows in usernames,
+/// letters, digits, `_` and `-`, so it can safely name a file.
+pub fn check_username(username: &str) -> Result<(), HnError> {
+    let valid = !username.is_empty()
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(HnError::ArgumentError(Some(
+            "usernames may only contain letters, digits, _ and -",
+        )));
+    }
+
+    Ok(())
+}
+
+/// The current Unix Time.
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// How long before `now` the Unix Time `time` was, in the largest whole unit,
+/// i.e. `45s`, `12m`, `3h`, `5d` or `2y`.
+pub fn format_age(time: u64, now: u64) -> String {
+    let secs = now.saturating_sub(time);
+    let (value, unit) = match secs {
+        0..=59 => (secs, "s"),
+        60..=3_599 => (secs / 60, "m"),
+        3_600..=86_399 => (secs / 3_600, "h"),
+        86_400..=31_535_999 => (secs / 86_400, "d"),
+        _ => (secs / 31_536_000, "y"),
+    };
+
+    format!("{}{}", value, unit)
+}
+
+/// Let the user compose text in their editor, `$VISUAL` or `$EDITOR` falling
+/// back to vi, starting from `initial`. Returns the trimmed text, or an error
+/// when it is left empty.
+pub fn compose(initial: &str) -> Result<String, HnError> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    compose_with(&editor, initial)
+}
+
+pub(crate) fn compose_with(editor: &str, initial: &str) -> Result<String, HnError> {
+    // Created with a random name, only readable by the user, and removed when
+    // dropped.
+    let mut file = tempfile::Builder::new()
+        .prefix("hn-")
+        .suffix(".txt")
+        .tempfile()
+        .map_err(|_| HnError::ArgumentError(Some("failed to create a file to edit")))?;
+    file.write_all(initial.as_bytes())
+        .and_then(|()| file.flush())
+        .map_err(|_| HnError::ArgumentError(Some("failed to create a file to edit")))?;
+    let path = file.path();
+
+    // Run through the shell, like git does, so the editor may carry arguments.
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg(editor)
+        .arg(path)
+        .status();
+    let text = std::fs::read_to_string(path);
+    match status {
+        Ok(status) if status.success() => {}
+        _ => {
+            return Err(HnError::ArgumentError(Some(
+                "the editor exited with an error",
+            )))
+        }
+    }
+    let text = text
+        .map_err(|_| HnError::ArgumentError(Some("failed to read the edited file")))?
+        .trim()
+        .to_string();
+    if text.is_empty() {
+        return Err(HnError::ArgumentError(Some("text is empty, aborting")));
+    }
+
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{check_username, compose_with, format_age, parse_since};
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("1623432780").unwrap(), 1623432780);
+        let day_ago = parse_since("1day").unwrap();
+        let hour_ago = parse_since("1h").unwrap();
+        // allow for the clock ticking between the two calls
+        assert!((hour_ago - day_ago).abs_diff(23 * 60 * 60) <= 1);
+        assert!(parse_since("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_check_username() {
+        assert!(check_username("pg").is_ok());
+        assert!(check_username("dang_-2").is_ok());
+        assert!(check_username("").is_err());
+        assert!(check_username("../config").is_err());
+        assert!(check_username("a/b").is_err());
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(1000, 1000), "0s");
+        assert_eq!(format_age(1000, 1059), "59s");
+        assert_eq!(format_age(0, 2 * 3600 + 59), "2h");
+        assert_eq!(format_age(0, 3 * 86400), "3d");
+        assert_eq!(format_age(0, 800 * 86400), "2y");
+        // clock skew does not underflow
+        assert_eq!(format_age(2000, 1000), "0s");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compose_with() {
+        let text = compose_with("sed -i s/draft/final/", "  a draft reply\n").unwrap();
+        assert_eq!(text, "a final reply");
+        assert!(compose_with("true", "").is_err());
+        assert!(compose_with("false", "text").is_err());
+    }
+}